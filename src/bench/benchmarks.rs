@@ -38,11 +38,10 @@ pub fn run_ten_million_test(count: usize) {
     println!("⏱️ 检索耗时: {:?}", start_retrieve_h.elapsed());
     println!("📊 召回结果数量: {}", results_h.len());
 
-    if let Some((id, score)) = results_h.first() {
-        if let Some(node) = engine.tdb.get_payload(*id as u64) {
-            println!("🔝 最高分结果: ID={}, Score={:.4}", id, score);
-            println!("📝 内容摘要: {}", node.get("content").unwrap().as_str().unwrap());
-        }
+    if let Some((id, score)) = results_h.first()
+        && let Some(node) = engine.tdb.get_payload(*id as u64) {
+        println!("🔝 最高分结果: ID={}, Score={:.4}", id, score);
+        println!("📝 内容摘要: {}", node.get("content").unwrap().as_str().unwrap());
     }
     println!("\n✅ 千万级压力测试完成。");
 }
@@ -63,3 +62,37 @@ pub fn run_v2_benchmark(args: &[String]) {
     // 复用千万级压力测试方法来跑压测
     engine.load_million_test_data(node_count);
 }
+
+/// 批量检索吞吐对比: 查询间并行 vs 逐个顺序执行 (标准数据集 + 5 条评测查询)
+pub fn run_batch_benchmark() {
+    use crate::core::config::BatchParallelism;
+
+    let test_queries = [
+        "PeroCore 是如何处理长时记忆的？",
+        "2024年发生了哪些重要的事情？",
+        "如何通过代码表达对生活的热爱？",
+        "PEDSA 相比传统的向量数据库有什么优势？",
+        "在上海徐家汇的那次咖啡馆偶遇，对项目有什么影响？",
+    ];
+    // 有模型时查询向量化占大头，最能体现两种模式的差异
+    let mut builder = AdvancedEngine::builder().path(".trivium_pedsa_batch_bench");
+    if let Ok(model) = embedding::CandleModel::new() {
+        builder = builder.with_embedding_model(model);
+    }
+    let mut engine = builder.build().expect("无法打开压测存储");
+    if engine.tdb.node_count() == 0 {
+        engine.load_standard_data();
+    }
+    engine.compile();
+    // 扩成 200 次查询，放大调度差异
+    let queries: Vec<&str> = test_queries.iter().copied().cycle().take(200).collect();
+
+    for mode in [BatchParallelism::Sequential, BatchParallelism::QueryLevel] {
+        engine.config.batch_parallelism = mode;
+        let _ = engine.retrieve_batch(&queries[..test_queries.len()], 0, 0.0, 10);
+        let start = Instant::now();
+        let _ = engine.retrieve_batch(&queries, 0, 0.0, 10);
+        let elapsed = start.elapsed();
+        println!("⏱️ {:?}: {} 次查询耗时 {:?} ({:.1} QPS)", mode, queries.len(), elapsed, queries.len() as f64 / elapsed.as_secs_f64());
+    }
+}
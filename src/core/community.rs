@@ -0,0 +1,142 @@
+use ahash::{AHashMap, AHashSet};
+use crate::core::engine::AdvancedEngine;
+
+/// 标签传播的最大轮数 (通常数轮内收敛)
+const MAX_PROPAGATION_ROUNDS: usize = 20;
+
+/// 无向加权邻接表 (忽略自环与抑制边，抑制表示互斥而非关联)
+fn undirected_adjacency(engine: &AdvancedEngine, ids: &[u64]) -> AHashMap<u64, Vec<(u64, f32)>> {
+    let mut adjacency: AHashMap<u64, Vec<(u64, f32)>> = AHashMap::new();
+    for &id in ids {
+        for edge in engine.tdb.get_edges(id) {
+            if edge.target_id == id || edge.label == "inhibition" { continue; }
+            adjacency.entry(id).or_default().push((edge.target_id, edge.weight));
+            adjacency.entry(edge.target_id).or_default().push((id, edge.weight));
+        }
+    }
+    adjacency
+}
+
+impl AdvancedEngine {
+    /// 弱连通分量: 记忆边与本体边一并视为无向边 (抑制边不连通)
+    /// 每个分量内 id 升序，分量按最小 id 排序；孤立节点自成一个分量。
+    pub fn find_components(&self) -> Vec<Vec<i64>> {
+        let mut ids = self.tdb.all_node_ids();
+        ids.sort_unstable();
+        let adjacency = undirected_adjacency(self, &ids);
+        let mut visited: AHashSet<u64> = AHashSet::with_capacity(ids.len());
+        let mut components = Vec::new();
+        for &start in &ids {
+            if !visited.insert(start) { continue; }
+            let mut component = vec![start as i64];
+            let mut stack = vec![start];
+            while let Some(node) = stack.pop() {
+                for &(neighbor, _) in adjacency.get(&node).map_or(&[][..], Vec::as_slice) {
+                    if visited.insert(neighbor) {
+                        component.push(neighbor as i64);
+                        stack.push(neighbor);
+                    }
+                }
+            }
+            component.sort_unstable();
+            components.push(component);
+        }
+        components
+    }
+
+    /// 社群发现: 在无向加权图上做确定性的标签传播
+    /// 每轮按节点 id 顺序，把节点的社群改为邻居中边权总和最大的社群 (并列取编号较小者)，
+    /// 直到没有变化。返回 节点 id -> 社群编号 (按社群内最小节点 id 从 0 连续编号)。
+    pub fn detect_communities(&self) -> AHashMap<i64, usize> {
+        let mut ids = self.tdb.all_node_ids();
+        ids.sort_unstable();
+        let adjacency = undirected_adjacency(self, &ids);
+
+        let mut label: AHashMap<u64, u64> = ids.iter().map(|&id| (id, id)).collect();
+        for _ in 0..MAX_PROPAGATION_ROUNDS {
+            let mut changed = false;
+            for &id in &ids {
+                let Some(neighbors) = adjacency.get(&id) else { continue; };
+                let mut votes: AHashMap<u64, f32> = AHashMap::new();
+                for &(neighbor, weight) in neighbors {
+                    if let Some(&l) = label.get(&neighbor) {
+                        *votes.entry(l).or_insert(0.0) += weight;
+                    }
+                }
+                let Some(max) = votes.values().copied().reduce(f32::max) else { continue; };
+                if votes.get(&label[&id]) == Some(&max) { continue; }
+                if let Some(best) = votes.iter().filter(|&(_, &w)| w == max).map(|(&l, _)| l).min() {
+                    label.insert(id, best);
+                    changed = true;
+                }
+            }
+            if !changed { break; }
+        }
+
+        let mut numbering: AHashMap<u64, usize> = AHashMap::new();
+        ids.iter().map(|id| {
+            let next = numbering.len();
+            let community = *numbering.entry(label[id]).or_insert(next);
+            (*id as i64, community)
+        }).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_communities_splits_clusters() {
+        let mut engine = AdvancedEngine::open_temp("communities");
+        let rust = engine.get_or_create_feature("Rust");
+        let cooking = engine.get_or_create_feature("烹饪");
+        for id in 1..=3 {
+            engine.add_event(id, &format!("Rust 学习笔记 {}", id), 0, 0, 0);
+            engine.add_edge(rust, id, 1.0);
+        }
+        for id in 11..=13 {
+            engine.add_event(id, &format!("烹饪笔记 {}", id), 0, 0, 0);
+            engine.add_edge(cooking, id, 1.0);
+        }
+        engine.add_event(99, "孤立的事件", 0, 0, 0);
+
+        let communities = engine.detect_communities();
+        assert!((1..=3).all(|id| communities[&id] == communities[&rust]));
+        assert!((11..=13).all(|id| communities[&id] == communities[&cooking]));
+        assert_ne!(communities[&rust], communities[&cooking]);
+        assert_ne!(communities[&99], communities[&rust]);
+        assert_ne!(communities[&99], communities[&cooking]);
+    }
+
+    #[test]
+    fn test_find_components() {
+        let mut engine = AdvancedEngine::open_temp("components");
+        let rust = engine.get_or_create_feature("Rust");
+        engine.add_event(1, "Rust 学习笔记", 0, 0, 0);
+        engine.add_event(2, "烹饪笔记", 0, 0, 0);
+        engine.add_event(3, "孤立的事件", 0, 0, 0);
+        engine.add_edge(rust, 1, 1.0);
+        engine.maintain_ontology("rust", "编程", "equality", 1.0);
+        // 抑制边不连通
+        engine.maintain_ontology("rust", "烹饪", "inhibition", 1.0);
+        let cooking = engine.feature_id("烹饪").unwrap();
+        engine.add_edge(cooking, 2, 1.0);
+        let programming = engine.feature_id("编程").unwrap();
+
+        let mut expected = vec![vec![1, rust, programming], vec![2, cooking], vec![3]];
+        for component in &mut expected { component.sort_unstable(); }
+        expected.sort();
+        assert_eq!(engine.find_components(), expected);
+
+        let mut engine = AdvancedEngine::open_temp("components_standard");
+        engine.load_standard_data();
+        engine.compile();
+        let components = engine.find_components();
+        assert_eq!(components.iter().map(Vec::len).sum::<usize>(), engine.tdb.node_count());
+        assert!(components.len() < engine.tdb.node_count());
+        assert_eq!(engine.find_components(), components);
+        // 标准数据集的图结构固定，分量数变化说明导入或建边逻辑有回退
+        assert_eq!(components.len(), 5);
+    }
+}
@@ -0,0 +1,410 @@
+use serde::{Deserialize, Serialize};
+use crate::core::engine::MemoryEdgeType;
+use crate::core::simhash::SimHashLayout;
+
+/// 多向量模式下，事件得分如何汇总各分块的向量命中
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MultiVectorPooling {
+    /// 取最相关分块的得分 (默认)
+    #[default]
+    Max,
+    /// 累加所有命中分块的得分
+    Sum,
+}
+
+/// retrieve_batch 的并行粒度
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchParallelism {
+    /// 查询之间用 Rayon 并行 (大量小查询时吞吐更高，默认)
+    #[default]
+    QueryLevel,
+    /// 查询在调用线程上逐个执行，查询之间不并行 (调用方自行控制并发时使用)
+    Sequential,
+}
+
+/// retrieve_normalized 把原始得分映射到 [0, 1] 的方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScoreNormalization {
+    /// 除以理论最大得分 (混合检索基础分按 1 计 + 多模态共振加成上限 + 随机意外加成上限)，超出截断为 1。
+    /// 与结果集无关，不同查询之间可比 (默认)
+    #[default]
+    MaxBoost,
+    /// 结果集内 min-max 缩放: 第一名恒为 1，只反映本次查询内部的相对强弱
+    MinMax,
+}
+
+/// 写入事件时遇到近似重复 (语义区指纹汉明距离不超过 duplicate_hamming_threshold) 的处理方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DuplicatePolicy {
+    /// 不检查，照常写入 (默认)
+    #[default]
+    Allow,
+    /// 拒绝写入，try_add_event 返回 InsertError::Duplicate
+    Reject,
+    /// 不写入新事件，对已有事件记一次访问并返回其 id
+    Merge,
+}
+
+/// 混沌检索 (chaos_level > 0) 时 L1 二值量化粗筛的候选规模
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HybridScanConfig {
+    /// L1 候选占节点总数的比例 (至少 top_k 个)。过小会让量化损失大、
+    /// 但余弦相似度其实很高的向量在 L2 精排前就被丢弃。默认 0.1。
+    pub l1_candidate_ratio: f32,
+    /// 自适应模式: 候选比例逐轮翻倍，直到 L2 精排第 k 名的得分不再变化 (或扫描全量)。
+    /// 以延迟换召回的稳妥性。默认关闭。
+    pub adaptive: bool,
+}
+
+impl Default for HybridScanConfig {
+    fn default() -> Self {
+        Self { l1_candidate_ratio: 0.1, adaptive: false }
+    }
+}
+
+/// 混沌检索的随机意外加成: 打破只按相似度排序的信息茧房
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SerendipityConfig {
+    /// 是否启用。启用后每次检索以 chaos_level 的概率触发一次注入，chaos_level = 0 时从不触发。默认关闭。
+    pub enabled: bool,
+    /// 每次触发随机挑选的候选数。默认 3。
+    pub picks: usize,
+    /// 单个候选的加成上限 (实际加成 = 上限 × chaos_level × 随机系数)。默认 0.3。
+    pub max_boost: f32,
+}
+
+impl Default for SerendipityConfig {
+    fn default() -> Self {
+        Self { enabled: false, picks: 3, max_boost: 0.3 }
+    }
+}
+
+/// 检索扩散 (retrieve 的 PPR 扩散与 retrieve_with_seeds 的种子扩散) 时各类记忆边的强度系数: 传递能量 = 能量 × 边强度 × 系数
+/// (种子扩散另乘跳数衰减)。本体层等非记忆边不受影响。
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EdgeTypeGain {
+    /// 关联边。默认 1.0。
+    pub association: f32,
+    /// 因果边，默认 1.5: 沿因果链联想更强。
+    pub causal: f32,
+    /// 顺序边。默认 1.2。
+    pub sequential: f32,
+    /// 对比边。默认 0.5。
+    pub contrast: f32,
+}
+
+impl Default for EdgeTypeGain {
+    fn default() -> Self {
+        Self { association: 1.0, causal: 1.5, sequential: 1.2, contrast: 0.5 }
+    }
+}
+
+impl EdgeTypeGain {
+    pub fn gain(&self, edge_type: MemoryEdgeType) -> f32 {
+        match edge_type {
+            MemoryEdgeType::Association => self.association,
+            MemoryEdgeType::Causal => self.causal,
+            MemoryEdgeType::Sequential => self.sequential,
+            MemoryEdgeType::Contrast => self.contrast,
+        }
+    }
+
+    /// 按边标签取系数，非记忆边为 1
+    pub fn gain_for_label(&self, label: &str) -> f32 {
+        MemoryEdgeType::from_label(label).map_or(1.0, |t| self.gain(t))
+    }
+}
+
+/// 精排阶段的多模态共振权重与时间衰减参数 (原 retrieve 中的硬编码常量)
+/// 加成 = 各分区汉明相似度 × 对应权重 (情感为 权重 × 强度系数)，权重取非负有限值。
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ResonanceConfig {
+    /// 语义区权重。默认 0.6。
+    pub semantic: f32,
+    /// 时间区权重 (仅查询带时间信息时生效)。默认 0.5。
+    pub temporal: f32,
+    /// 地点区权重 (仅查询带地点时生效)。默认 0.5。
+    pub location: f32,
+    /// 情感共振权重 (存在共同情感时生效)。默认 0.6。
+    pub affective: f32,
+    /// 实体类型区权重 (仅查询带类型时生效)。默认 0.8。
+    pub entity_type: f32,
+    /// 时间衰减系数的下限，取值 [0, 1]，1 表示不衰减。默认 0.8。
+    pub decay_floor: f32,
+    /// 时间衰减的时间常数 (秒)，须为正。默认 31536000 (一年)。
+    pub decay_tau: f32,
+}
+
+impl Default for ResonanceConfig {
+    fn default() -> Self {
+        Self { semantic: 0.6, temporal: 0.5, location: 0.5, affective: 0.6, entity_type: 0.8, decay_floor: 0.8, decay_tau: 31536000.0 }
+    }
+}
+
+impl ResonanceConfig {
+    /// 共振加成的理论上限 (各权重之和)
+    pub fn max_boost(&self) -> f32 {
+        self.semantic + self.temporal + self.location + self.affective + self.entity_type
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        for (name, weight) in [("semantic", self.semantic), ("temporal", self.temporal), ("location", self.location),
+                               ("affective", self.affective), ("entity_type", self.entity_type)] {
+            if !weight.is_finite() || weight < 0.0 {
+                return Err(ConfigError::Invalid(format!("resonance.{} 必须是非负有限值, 当前为 {}", name, weight)));
+            }
+        }
+        if !(0.0..=1.0).contains(&self.decay_floor) {
+            return Err(ConfigError::Invalid(format!("resonance.decay_floor 必须在 [0, 1] 内, 当前为 {}", self.decay_floor)));
+        }
+        if !(self.decay_tau.is_finite() && self.decay_tau > 0.0) {
+            return Err(ConfigError::Invalid(format!("resonance.decay_tau 必须为正, 当前为 {}", self.decay_tau)));
+        }
+        Ok(())
+    }
+}
+
+/// 引擎级调参配置 (检索管线中原本硬编码的常量)
+/// 可从 TOML / JSON 文件加载，缺省字段取默认值。
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EngineConfig {
+    /// 精排窗口: 共振打分后的前 N 个候选参与 DPP 多样性重排
+    ///
+    /// DPP 从窗口中选出 10 条互不冗余的候选放在最前，窗口内其余候选与窗口外的候选按原有顺序接在后面，
+    /// 不会丢弃任何结果。窗口外的候选永远无法被选入头部；调大可以让排名靠后但语义不同的事件有机会进入前 10
+    /// (头部更多样)，代价是 DPP 的核矩阵更新随窗口线性增长 (延迟更高)。默认 50。
+    pub refinement_top_n: usize,
+    /// 多向量模式: add_event 时按句切分，每个分句额外存一个 chunk 向量节点 (ColBERT 式后期交互)
+    /// 长事件的后半句也能被查询向量直接命中。默认关闭 (单向量)。
+    pub multi_vector: bool,
+    /// 多向量模式下的分块得分汇总方式
+    pub multi_vector_pooling: MultiVectorPooling,
+    /// 相关性下限: retrieve 丢弃最终得分低于该值的结果，默认 0.0 (不过滤)
+    ///
+    /// 得分未做 softmax / 归一化，是混合检索得分经时间衰减、多模态共振加成后的原始值，
+    /// 阈值需按这个量纲设置；需要 [0, 1] 得分时使用 retrieve_normalized。
+    pub min_score: f32,
+    /// 结果条数上限: 在最终排序、意外加成、min_score 过滤与 DPP 重排之后截断，None 表示不限 (默认)
+    ///
+    /// 截断结果总是不限条数时结果的前缀；DPP 精排窗口仍由 refinement_top_n 决定，不随之收窄。
+    pub max_results: Option<usize>,
+    /// 情感硬过滤 (SimHash::EMOTION_* 位掩码): 指纹情感区与之无交集的事件不进入结果，0 表示不过滤 (默认)
+    ///
+    /// 在最终排序前过滤，保留下来的事件照常获得情感共振加成；特征节点不受影响。
+    pub require_emotions: u8,
+    /// 扩散激活的跳数: TriviumDB 混合检索的图扩展深度，以及 retrieve_with_seeds 的种子扩散跳数。
+    /// 跳数越多越能联想到间接相关的概念 (女孩 -> Pero -> 猫)，但噪声与延迟随之增加。默认 2。
+    pub diffusion_depth: usize,
+    /// 混沌检索的 L1 粗筛候选规模
+    pub hybrid_scan: HybridScanConfig,
+    /// 批量检索的并行粒度
+    pub batch_parallelism: BatchParallelism,
+    /// 混沌检索的随机意外加成
+    pub serendipity: SerendipityConfig,
+    /// 英文特征词干化: optimize / optimization / optimizing 归并为同一特征，查询词同样处理 (仅用于关键词 / BM25 匹配，向量仍对原文编码)。
+    /// 默认关闭 (保持原有的逐字匹配)。
+    pub english_stemming: bool,
+    /// 时间衰减的参照时刻: retrieve 的 ref_time 为 0 时以此为 "现在"。默认 1777593600 (2026-05-01)。
+    pub decay_reference_time: u64,
+    /// 未来事件 (时间戳晚于参照时刻) 的得分系数，取值 [0, 1]
+    ///
+    /// 叙事时间线中 "现在" 可能位于故事中段，尚未发生的事件不做时间衰减，默认 1.0 即原样保留。
+    /// 调低可以压制 "还没发生" 的事件，0 表示完全排除。
+    pub future_penalty: f32,
+    /// 边的时间衰减: 边在写入 / 合并 / reinforce_edge 时记录强化时间，检索扩散 (retrieve 与 retrieve_with_seeds) 中
+    /// 按 强度 × exp(-距上次强化时长 / resonance.decay_tau) 计算有效强度，长期未强化的联想逐渐减弱但不删除。
+    /// 没有强化记录的边 (如直接经 tdb 写入) 按存储强度计。默认关闭。
+    pub edge_decay: bool,
+    /// 未落盘写入的提醒阈值: 自上次 flush / compile 以来写入的节点数达到该值时 needs_flush 返回 true。
+    /// 引擎不会自行落盘，时机由调用方决定。0 表示不提醒。默认 100000。
+    pub max_unflushed_nodes: usize,
+    /// retrieve_normalized 的得分归一化方式 (retrieve 本身始终返回原始得分)
+    pub score_normalization: ScoreNormalization,
+    /// 指纹位布局 (各分区位数)。默认 语义 24 / 地点 8 / 时间 16 / 情感 8 / 类型 8，更换后需用 rewrite_fingerprints 重算已有指纹
+    pub simhash_layout: SimHashLayout,
+    /// 语义区 SimHash 按词元 IDF 加权，压低高频填充字对指纹的影响
+    /// IDF 表在 compile 时由全部事件文本构建，并据此重算已有指纹的语义区；重新打开存储后需再 compile 一次。默认关闭。
+    pub idf_weighted_simhash: bool,
+    /// 近似重复事件的写入策略，只比较指纹语义区，时间 / 情感不同的同义事件同样视为重复
+    pub duplicate_policy: DuplicatePolicy,
+    /// 近似重复判定的语义区汉明距离上限 (含)。默认 3。
+    pub duplicate_hamming_threshold: u32,
+    /// 检索扩散时各类型记忆边的强度系数
+    pub edge_type_gain: EdgeTypeGain,
+    /// 精排的共振权重与时间衰减参数 (retrieve_with_config 可按次覆盖)
+    pub resonance: ResonanceConfig,
+}
+
+impl Default for EngineConfig {
+    fn default() -> Self {
+        Self {
+            refinement_top_n: 50,
+            multi_vector: false,
+            multi_vector_pooling: MultiVectorPooling::Max,
+            min_score: 0.0,
+            max_results: None,
+            require_emotions: 0,
+            diffusion_depth: 2,
+            hybrid_scan: HybridScanConfig::default(),
+            batch_parallelism: BatchParallelism::QueryLevel,
+            serendipity: SerendipityConfig::default(),
+            english_stemming: false,
+            decay_reference_time: 1777593600,
+            future_penalty: 1.0,
+            edge_decay: false,
+            max_unflushed_nodes: 100_000,
+            score_normalization: ScoreNormalization::MaxBoost,
+            simhash_layout: SimHashLayout::DEFAULT,
+            idf_weighted_simhash: false,
+            duplicate_policy: DuplicatePolicy::Allow,
+            duplicate_hamming_threshold: 3,
+            edge_type_gain: EdgeTypeGain::default(),
+            resonance: ResonanceConfig::default(),
+        }
+    }
+}
+
+/// 配置加载 / 校验错误
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(std::io::Error),
+    Parse(String),
+    Invalid(String),
+}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::Io(e) => write!(f, "无法读取配置文件: {}", e),
+            ConfigError::Parse(e) => write!(f, "配置解析失败: {}", e),
+            ConfigError::Invalid(e) => write!(f, "配置取值非法: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl EngineConfig {
+    pub fn from_toml(path: impl AsRef<std::path::Path>) -> Result<Self, ConfigError> {
+        let text = std::fs::read_to_string(path).map_err(ConfigError::Io)?;
+        Self::from_toml_str(&text)
+    }
+
+    pub fn from_toml_str(s: &str) -> Result<Self, ConfigError> {
+        let config: Self = toml::from_str(s).map_err(|e| ConfigError::Parse(e.to_string()))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn from_json(s: &str) -> Result<Self, ConfigError> {
+        let config: Self = serde_json::from_str(s).map_err(|e| ConfigError::Parse(e.to_string()))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn to_toml(&self) -> String {
+        toml::to_string(self).expect("EngineConfig 总能序列化为 TOML")
+    }
+
+    /// 校验取值范围
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.refinement_top_n == 0 {
+            return Err(ConfigError::Invalid("refinement_top_n 必须大于 0".into()));
+        }
+        if !self.min_score.is_finite() || self.min_score < 0.0 {
+            return Err(ConfigError::Invalid(format!("min_score 必须是非负有限值, 当前为 {}", self.min_score)));
+        }
+        if self.max_results == Some(0) {
+            return Err(ConfigError::Invalid("max_results 必须大于 0 (不限制请省略该字段)".into()));
+        }
+        let ratio = self.hybrid_scan.l1_candidate_ratio;
+        if !(ratio > 0.0 && ratio <= 1.0) {
+            return Err(ConfigError::Invalid(format!("hybrid_scan.l1_candidate_ratio 必须在 (0, 1] 内, 当前为 {}", ratio)));
+        }
+        if !(0.0..=1.0).contains(&self.future_penalty) {
+            return Err(ConfigError::Invalid(format!("future_penalty 必须在 [0, 1] 内, 当前为 {}", self.future_penalty)));
+        }
+        let boost = self.serendipity.max_boost;
+        if !boost.is_finite() || boost < 0.0 {
+            return Err(ConfigError::Invalid(format!("serendipity.max_boost 必须是非负有限值, 当前为 {}", boost)));
+        }
+        for edge_type in MemoryEdgeType::ALL {
+            let gain = self.edge_type_gain.gain(edge_type);
+            if !gain.is_finite() || gain < 0.0 {
+                return Err(ConfigError::Invalid(format!("edge_type_gain.{} 必须是非负有限值, 当前为 {}", edge_type.label(), gain)));
+            }
+        }
+        self.simhash_layout.validate().map_err(ConfigError::Invalid)?;
+        self.resonance.validate()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_toml_round_trip() {
+        let config = EngineConfig {
+            refinement_top_n: 80,
+            multi_vector: true,
+            multi_vector_pooling: MultiVectorPooling::Sum,
+            min_score: 0.5,
+            max_results: Some(20),
+            require_emotions: 0b0000_0101,
+            diffusion_depth: 3,
+            hybrid_scan: HybridScanConfig { l1_candidate_ratio: 0.25, adaptive: true },
+            batch_parallelism: BatchParallelism::Sequential,
+            serendipity: SerendipityConfig { enabled: true, picks: 5, max_boost: 0.5 },
+            english_stemming: true,
+            decay_reference_time: 1700000000,
+            future_penalty: 0.5,
+            edge_decay: true,
+            max_unflushed_nodes: 500,
+            score_normalization: ScoreNormalization::MinMax,
+            simhash_layout: SimHashLayout::new(24, 20, 8, 8, 4).unwrap(),
+            idf_weighted_simhash: true,
+            duplicate_policy: DuplicatePolicy::Merge,
+            duplicate_hamming_threshold: 5,
+            edge_type_gain: EdgeTypeGain { causal: 2.0, contrast: 0.0, ..Default::default() },
+            resonance: ResonanceConfig { semantic: 1.0, temporal: 0.0, decay_floor: 0.5, ..Default::default() },
+        };
+        let path = std::env::temp_dir().join(format!("pedsa_test_config_{}.toml", std::process::id()));
+        std::fs::write(&path, config.to_toml()).unwrap();
+        assert_eq!(EngineConfig::from_toml(&path).unwrap(), config);
+        let _ = std::fs::remove_file(&path);
+
+        // 缺省字段取默认值
+        let partial = EngineConfig::from_toml_str("min_score = 0.3\n[hybrid_scan]\nadaptive = true").unwrap();
+        assert_eq!(partial.refinement_top_n, 50);
+        assert_eq!(partial.min_score, 0.3);
+        assert!(partial.hybrid_scan.adaptive);
+        assert_eq!(partial.hybrid_scan.l1_candidate_ratio, 0.1);
+
+        let json = EngineConfig::from_json(r#"{"multi_vector_pooling": "max", "refinement_top_n": 20}"#).unwrap();
+        assert_eq!(json.refinement_top_n, 20);
+    }
+
+    #[test]
+    fn test_config_validation_rejects_bad_values() {
+        assert!(matches!(EngineConfig::from_toml_str("refinement_top_n = 0"), Err(ConfigError::Invalid(_))));
+        assert!(matches!(EngineConfig::from_toml_str("min_score = -1.0"), Err(ConfigError::Invalid(_))));
+        assert!(matches!(EngineConfig::from_toml_str("[hybrid_scan]\nl1_candidate_ratio = 1.5"), Err(ConfigError::Invalid(_))));
+        assert!(matches!(EngineConfig::from_toml_str("future_penalty = 1.5"), Err(ConfigError::Invalid(_))));
+        assert!(matches!(EngineConfig::from_toml_str("[serendipity]\nmax_boost = -0.1"), Err(ConfigError::Invalid(_))));
+        assert!(matches!(EngineConfig::from_toml_str("[resonance]\ndecay_floor = 1.2"), Err(ConfigError::Invalid(_))));
+        assert!(matches!(EngineConfig::from_toml_str("[resonance]\ntemporal = -0.5"), Err(ConfigError::Invalid(_))));
+        assert!(matches!(EngineConfig::from_toml_str("refinement_top_n = \"many\""), Err(ConfigError::Parse(_))));
+        assert!(matches!(EngineConfig::from_toml("/nonexistent/pedsa.toml"), Err(ConfigError::Io(_))));
+    }
+}
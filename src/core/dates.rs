@@ -0,0 +1,255 @@
+//! 文本日期解析与公历换算 (UTC)
+//! 支持 `YYYY年MM月DD日` (日可省略)、`YYYY-MM-DD`、`YYYY/MM/DD`，非法日期跳过。
+//! 另支持 "三天前" / "5 days ago" 式的数字相对时间 (parse_relative_offset)。
+
+pub fn is_leap_year(year: i32) -> bool {
+    (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+}
+
+pub fn days_in_month(year: i32, month: u32) -> u32 {
+    match month {
+        1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+        4 | 6 | 9 | 11 => 30,
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        _ => 0,
+    }
+}
+
+/// 公历日期距 1970-01-01 的天数 (可为负)
+fn days_from_civil(year: i32, month: u32, day: u32) -> i64 {
+    let y = year as i64 - if month <= 2 { 1 } else { 0 };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let mp = (month as i64 + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+/// 距 1970-01-01 的天数对应的公历日期 (days_from_civil 的逆运算)
+fn civil_from_days(days: i64) -> (i32, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = (yoe + era * 400 + if month <= 2 { 1 } else { 0 }) as i32;
+    (year, month, day)
+}
+
+/// 时间戳所在日期 (UTC)，格式 YYYY-MM-DD，可被 parse_timestamps 解析回当天 00:00
+pub fn format_date(timestamp: u64) -> String {
+    let (year, month, day) = civil_from_days((timestamp / 86400) as i64);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// 日期当天 00:00 UTC 的 Unix 时间戳，月 / 日不合法时返回 None，1970 年之前的日期取 0
+pub fn date_timestamp(year: i32, month: u32, day: u32) -> Option<u64> {
+    if !(1..=12).contains(&month) || day == 0 || day > days_in_month(year, month) {
+        return None;
+    }
+    Some((days_from_civil(year, month, day) * 86400).max(0) as u64)
+}
+
+/// 从 pos 开始读取 1..=max_len 位数字，其后不能紧跟数字
+fn read_number(chars: &[char], pos: usize, max_len: usize) -> Option<(u32, usize)> {
+    let len = chars[pos.min(chars.len())..].iter().take_while(|c| c.is_ascii_digit()).count();
+    if len == 0 || len > max_len { return None; }
+    let value = chars[pos..pos + len].iter().collect::<String>().parse().ok()?;
+    Some((value, pos + len))
+}
+
+fn skip_spaces(chars: &[char], mut pos: usize) -> usize {
+    while chars.get(pos).is_some_and(|c| c.is_whitespace()) { pos += 1; }
+    pos
+}
+
+/// 在 start 处 (年份首位) 尝试解析一个日期，返回 (年, 月, 日, 结束位置)
+fn parse_date_at(chars: &[char], start: usize) -> Option<(i32, u32, u32, usize)> {
+    let (year, pos) = read_number(chars, start, 4)?;
+    if pos - start != 4 { return None; }
+    let sep = *chars.get(pos)?;
+    match sep {
+        '年' => {
+            let (month, pos) = read_number(chars, skip_spaces(chars, pos + 1), 2)?;
+            let pos = skip_spaces(chars, pos);
+            if chars.get(pos) != Some(&'月') { return None; }
+            let end = pos + 1;
+            // 日可省略，默认 1 日
+            let day_start = skip_spaces(chars, end);
+            if let Some((day, pos)) = read_number(chars, day_start, 2) {
+                let pos = skip_spaces(chars, pos);
+                if chars.get(pos) == Some(&'日') { return Some((year as i32, month, day, pos + 1)); }
+            }
+            Some((year as i32, month, 1, end))
+        }
+        '-' | '/' => {
+            let (month, pos) = read_number(chars, pos + 1, 2)?;
+            if chars.get(pos) != Some(&sep) { return None; }
+            let (day, pos) = read_number(chars, pos + 1, 2)?;
+            Some((year as i32, month, day, pos))
+        }
+        _ => None,
+    }
+}
+
+/// 提取文本中的全部日期，按出现顺序返回 Unix 时间戳 (当天 00:00 UTC)
+pub fn parse_timestamps(text: &str) -> Vec<u64> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut timestamps = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let at_number_start = chars[i].is_ascii_digit() && (i == 0 || !chars[i - 1].is_ascii_digit());
+        if at_number_start && let Some((year, month, day, end)) = parse_date_at(&chars, i) {
+            if let Some(ts) = date_timestamp(year, month, day) {
+                timestamps.push(ts);
+            }
+            i = end;
+            continue;
+        }
+        i += 1;
+    }
+    timestamps
+}
+
+const DAY: u64 = 86400;
+const WEEK: u64 = 7 * DAY;
+const MONTH: u64 = 30 * DAY;
+const YEAR: u64 = 365 * DAY;
+
+fn chinese_digit(c: char) -> Option<u64> {
+    Some(match c {
+        '零' => 0, '一' => 1, '二' | '两' => 2, '三' => 3, '四' => 4,
+        '五' => 5, '六' => 6, '七' => 7, '八' => 8, '九' => 9,
+        _ => return None,
+    })
+}
+
+fn is_numeral(c: char) -> bool {
+    c.is_ascii_digit() || c == '十' || c == '百' || chinese_digit(c).is_some()
+}
+
+/// 阿拉伯数字或中文数字 (至多到百位: 三 / 十五 / 二十三 / 一百零五)
+fn parse_numeral(text: &str) -> Option<u64> {
+    if text.chars().all(|c| c.is_ascii_digit()) { return text.parse().ok(); }
+    let (mut total, mut digit) = (0u64, None);
+    for c in text.chars() {
+        match c {
+            '百' => { total += digit.take().unwrap_or(1) * 100; }
+            '十' => { total += digit.take().unwrap_or(1) * 10; }
+            _ => digit = Some(chinese_digit(c)?),
+        }
+    }
+    Some(total + digit.unwrap_or(0))
+}
+
+fn english_number(word: &str) -> Option<u64> {
+    const WORDS: [&str; 13] = ["zero", "one", "two", "three", "four", "five", "six", "seven", "eight", "nine", "ten", "eleven", "twelve"];
+    match word {
+        "a" | "an" => Some(1),
+        _ => WORDS.iter().position(|&w| w == word).map(|n| n as u64).or_else(|| word.parse().ok()),
+    }
+}
+
+/// 中文相对时间 "N(个)天/日/周/星期/礼拜/月/年 前 (以前 / 之前)"，月必须带 "个" 以免与 "三月" 混淆
+fn chinese_relative(chars: &[char]) -> Option<u64> {
+    let mut i = 0;
+    while i < chars.len() {
+        if !is_numeral(chars[i]) || (i > 0 && is_numeral(chars[i - 1])) { i += 1; continue; }
+        let end = i + chars[i..].iter().take_while(|&&c| is_numeral(c)).count();
+        let number: String = chars[i..end].iter().collect();
+        let rest: String = chars[end..].iter().collect();
+        let rest = rest.trim_start();
+        let (counted, rest) = match rest.strip_prefix('个') { Some(r) => (true, r), None => (false, rest) };
+        let unit = [("天", DAY), ("日", DAY), ("周", WEEK), ("星期", WEEK), ("礼拜", WEEK), ("月", MONTH), ("年", YEAR)]
+            .into_iter()
+            .find(|&(name, _)| rest.starts_with(name) && (name != "月" || counted));
+        if let (Some((name, seconds)), Some(n)) = (unit, parse_numeral(&number)) {
+            let after = rest[name.len()..].trim_start();
+            if ["前", "以前", "之前"].iter().any(|p| after.starts_with(p)) {
+                return n.checked_mul(seconds);
+            }
+        }
+        i = end;
+    }
+    None
+}
+
+/// 英文相对时间 "N day(s)/week(s)/month(s)/year(s) ago"，N 可为数字或 one..twelve / a / an
+fn english_relative(text_lower: &str) -> Option<u64> {
+    let words: Vec<&str> = text_lower.split(|c: char| !c.is_alphanumeric()).filter(|w| !w.is_empty()).collect();
+    words.windows(3).find_map(|w| {
+        if w[2] != "ago" { return None; }
+        let seconds = match w[1].trim_end_matches('s') {
+            "day" => DAY,
+            "week" => WEEK,
+            "month" => MONTH,
+            "year" => YEAR,
+            _ => return None,
+        };
+        english_number(w[0])?.checked_mul(seconds)
+    })
+}
+
+/// 解析文本中第一个数字相对时间 ("三天前"、"两周前"、"3个月前"、"5 days ago")，返回距参照时刻的秒数
+/// 月按 30 天、年按 365 天计。没有数字相对时间时返回 None。
+pub fn parse_relative_offset(text: &str) -> Option<u64> {
+    let text_lower = text.to_lowercase();
+    let chars: Vec<char> = text_lower.chars().collect();
+    chinese_relative(&chars).or_else(|| english_relative(&text_lower))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_timestamps() {
+        assert_eq!(date_timestamp(1970, 1, 1), Some(0));
+        assert_eq!(date_timestamp(2024, 3, 1), Some(1709251200));
+        assert_eq!(date_timestamp(2024, 2, 29), Some(1709164800));
+        assert_eq!(date_timestamp(2023, 2, 29), None);
+        assert_eq!(date_timestamp(1900, 2, 29), None);
+        assert_eq!(date_timestamp(2000, 2, 29), Some(951782400));
+
+        assert_eq!(parse_timestamps("2024年3月1日去了上海"), vec![1709251200]);
+        assert_eq!(parse_timestamps("2024年3月 出差"), vec![1709251200]);
+        assert_eq!(parse_timestamps("从 2024-03-01 到 2024/12/31，中间 2024年 6 月 15 日"), vec![1709251200, 1735603200, 1718409600]);
+        // 非法日期跳过，不 panic
+        assert_eq!(parse_timestamps("2024年13月1日 和 2023-02-30 以及 2024-02-29"), vec![1709164800]);
+        assert_eq!(parse_timestamps("1965年5月4日"), vec![0]);
+        assert!(parse_timestamps("没有日期 12024-01-01 2024年 2024-1 年月日").is_empty());
+
+        for ts in [0, 951782400, 1709164800, 1735603200 + 86399] {
+            assert_eq!(parse_timestamps(&format_date(ts)), vec![ts - ts % 86400]);
+        }
+        assert_eq!(format_date(1709251200), "2024-03-01");
+    }
+
+    #[test]
+    fn test_parse_relative_offset() {
+        assert_eq!(parse_relative_offset("三天前去过的地方"), Some(3 * DAY));
+        assert_eq!(parse_relative_offset("15日前"), Some(15 * DAY));
+        assert_eq!(parse_relative_offset("两周前"), Some(2 * WEEK));
+        assert_eq!(parse_relative_offset("两个星期之前"), Some(2 * WEEK));
+        assert_eq!(parse_relative_offset("3个月前"), Some(3 * MONTH));
+        assert_eq!(parse_relative_offset("二十三年以前"), Some(23 * YEAR));
+        assert_eq!(parse_relative_offset("一百零五天前"), Some(105 * DAY));
+        assert_eq!(parse_relative_offset("5 days ago"), Some(5 * DAY));
+        assert_eq!(parse_relative_offset("one week ago"), Some(WEEK));
+        assert_eq!(parse_relative_offset("About 2 Months ago"), Some(2 * MONTH));
+        assert_eq!(parse_relative_offset("a year ago"), Some(YEAR));
+        // 没有 "前" / "ago"，或 "三月" 这类月份名
+        assert_eq!(parse_relative_offset("三天后"), None);
+        assert_eq!(parse_relative_offset("三月前的计划"), None);
+        assert_eq!(parse_relative_offset("5 days later"), None);
+        assert_eq!(parse_relative_offset("前天"), None);
+        // 秒数溢出 u64 时视为无法解析
+        assert_eq!(parse_relative_offset("999999999999999天前"), None);
+        assert_eq!(parse_relative_offset("99999999999999999 days ago"), None);
+    }
+}
@@ -1,102 +1,607 @@
-use ahash::AHashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicBool, Ordering};
+use ahash::{AHashMap, AHashSet};
+use smallvec::SmallVec;
+use twox_hash::XxHash64;
 use triviumdb::Database;
 use serde_json::json;
 
-use crate::core::simhash::SimHash;
+use crate::core::config::{DuplicatePolicy, EngineConfig};
+use crate::core::types::AsyncTaskInterface;
+use crate::core::simhash::{EmotionKeywords, SimHash, SimHashRegion};
 use crate::ml::embedding::CandleModel;
 #[cfg(feature = "gliner")]
 use crate::ml::gliner_ner::GlinerEngine;
-use crate::core::stopwords;
+use crate::core::stopwords::StopwordFilter;
+use crate::core::stemmer;
+use crate::core::dates;
+
+/// 事件文本中没有可识别日期时的默认时间戳 (2023-01-01 00:00 UTC)
+pub const DEFAULT_EVENT_TIMESTAMP: u64 = 1672531200;
+
+/// 批量向量化时每次前向传播的文本条数
+pub(crate) const EMBEDDING_BATCH_SIZE: usize = 32;
+
+/// 边强度统一钳制到 [0, 1] (NaN 视为 0)，所有写边路径共用
+pub fn clamp_strength(strength: f32) -> f32 {
+    if strength.is_nan() { 0.0 } else { strength.clamp(0.0, 1.0) }
+}
+
+/// 记忆层边的语义类型，各对应一个边标签 (关联边沿用原有的 memory_edge)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MemoryEdgeType {
+    /// 关联 (add_edge 的默认类型)
+    Association,
+    /// 因果: 前者导致后者
+    Causal,
+    /// 顺序: 前者先于后者
+    Sequential,
+    /// 对比: 两者相互参照或相反
+    Contrast,
+}
+
+impl MemoryEdgeType {
+    pub const ALL: [MemoryEdgeType; 4] = [Self::Association, Self::Causal, Self::Sequential, Self::Contrast];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Association => "memory_edge",
+            Self::Causal => "memory_causal",
+            Self::Sequential => "memory_sequential",
+            Self::Contrast => "memory_contrast",
+        }
+    }
+
+    /// 边标签对应的记忆边类型，本体层等其他标签返回 None
+    pub fn from_label(label: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|t| t.label() == label)
+    }
+
+    /// 数字编码: 0=关联, 1=因果, 2=顺序, 3=对比 (供 Python 等绑定使用)
+    pub fn from_code(code: u8) -> Option<Self> {
+        Self::ALL.get(code as usize).copied()
+    }
+
+    /// from_code 的逆映射
+    pub fn code(self) -> u8 {
+        self as u8
+    }
+}
+
+/// 写入事件失败的原因
+#[derive(Debug, Clone, PartialEq)]
+pub enum InsertError {
+    /// id 已被另一个节点占用 (node_type 为已有节点的类型)
+    IdExists { id: i64, node_type: String },
+    /// duplicate_policy 为 Reject 时，新事件与已有事件 existing 近似重复
+    Duplicate { id: i64, existing: i64 },
+    /// 底层存储写入失败
+    Storage(String),
+}
+
+impl std::fmt::Display for InsertError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InsertError::IdExists { id, node_type } => write!(f, "节点 id {} 已被 {} 节点占用", id, node_type),
+            InsertError::Duplicate { id, existing } => write!(f, "事件 {} 与已有事件 {} 近似重复", id, existing),
+            InsertError::Storage(e) => write!(f, "存储写入失败: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for InsertError {}
+
+/// 打开存储失败的原因 (try_open)
+#[derive(Debug)]
+pub enum StorageError {
+    Io(std::io::Error),
+    /// 文件头魔数不是 TVDB
+    BadMagic,
+    UnsupportedVersion(u16),
+    /// 文件比头部声明的数据块短
+    TruncatedFile,
+    /// 头部的块偏移顺序错乱或落在文件头内
+    MisalignedOffset,
+    DimensionMismatch { expected: usize, found: usize },
+    /// 快照文件与 save 时记录的校验和不一致 (写入中断或文件损坏)，内容为出错的文件
+    Corrupt(String),
+    /// 其余 TriviumDB 错误 (锁被占用、payload 解析失败等)
+    Storage(String),
+}
+
+impl std::fmt::Display for StorageError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StorageError::Io(e) => write!(f, "读取存储文件失败: {}", e),
+            StorageError::BadMagic => write!(f, "文件头魔数错误，不是 TriviumDB 存储文件"),
+            StorageError::UnsupportedVersion(v) => write!(f, "不支持的存储格式版本 {}", v),
+            StorageError::TruncatedFile => write!(f, "存储文件被截断"),
+            StorageError::MisalignedOffset => write!(f, "文件头中的块偏移无效"),
+            StorageError::DimensionMismatch { expected, found } => write!(f, "向量维度不匹配: 期望 {}, 文件中为 {}", expected, found),
+            StorageError::Corrupt(file) => write!(f, "存储文件 {} 校验和不匹配，可能写入中断或已损坏", file),
+            StorageError::Storage(e) => write!(f, "存储打开失败: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for StorageError {}
+
+/// TriviumDB 0.4 的文件头: magic(4) version(2) dim(4) next_id(8) node_count(8) payload/vector/edge 偏移(各 8)
+const STORE_MAGIC: &[u8; 4] = b"TVDB";
+const STORE_VERSION: u16 = 2;
+const STORE_HEADER_SIZE: usize = 50;
+/// 每个 payload 记录至少 12 字节 (节点 id + JSON 长度)
+const PAYLOAD_RECORD_MIN: u64 = 12;
+/// 新建存储的默认向量维度 (BGE-small)，其他维度的模型用 try_open_with_dim 打开
+pub const DEFAULT_VECTOR_DIM: usize = 512;
+
+/// 在交给 TriviumDB 之前校验文件头，使损坏或不可信的文件以错误返回而不是越界读取
+fn validate_store_header(path: &str, expected_dim: usize) -> Result<(), StorageError> {
+    let bytes = match std::fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(StorageError::Io(e)),
+    };
+    if bytes.len() < STORE_HEADER_SIZE {
+        // 长度不足时先看魔数，区分 "被截断" 与 "根本不是存储文件"
+        return Err(if bytes.len() >= 4 && &bytes[..4] != STORE_MAGIC { StorageError::BadMagic } else { StorageError::TruncatedFile });
+    }
+    if &bytes[..4] != STORE_MAGIC { return Err(StorageError::BadMagic); }
+    let u64_at = |at: usize| u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap());
+    let version = u16::from_le_bytes([bytes[4], bytes[5]]);
+    if version != STORE_VERSION { return Err(StorageError::UnsupportedVersion(version)); }
+    let dim = u32::from_le_bytes(bytes[6..10].try_into().unwrap()) as usize;
+    if dim != expected_dim { return Err(StorageError::DimensionMismatch { expected: expected_dim, found: dim }); }
+
+    let (node_count, payload_offset, vector_offset, edge_offset) = (u64_at(18), u64_at(26), u64_at(34), u64_at(42));
+    let file_len = bytes.len() as u64;
+    // vector_offset 为 0 表示向量在独立的 .vec 文件中
+    let payload_end = if vector_offset == 0 { edge_offset } else { vector_offset };
+    if payload_offset != STORE_HEADER_SIZE as u64 || payload_end < payload_offset || edge_offset < payload_end {
+        return Err(StorageError::MisalignedOffset);
+    }
+    let vector_len = if vector_offset == 0 { 0 } else { node_count.saturating_mul((dim * std::mem::size_of::<f32>()) as u64) };
+    if node_count.saturating_mul(PAYLOAD_RECORD_MIN) > payload_end - payload_offset
+        || (vector_offset != 0 && vector_offset.saturating_add(vector_len) > edge_offset)
+        || edge_offset > file_len {
+        return Err(StorageError::TruncatedFile);
+    }
+    Ok(())
+}
 
 pub struct AdvancedEngine {
+    /// 底层 TriviumDB 存储，供需要直接读写节点 / 边的高级用法
     pub tdb: Database<f32>,
-    pub keyword_to_node: AHashMap<String, i64>,
+    /// 归一化关键词 (见 feature_key) -> 特征节点 id，外部通过 feature_id 查询
+    pub(crate) keyword_to_node: AHashMap<String, i64>,
+    /// 特征节点 id -> 指向它的归一化关键词 (含合并后的别名，按字典序)，keyword_to_node 的反向索引
+    pub(crate) node_to_keywords: AHashMap<i64, SmallVec<[String; 4]>>,
+    /// 标签 -> 携带该标签的事件 id (compile 时全量重建)
+    pub(crate) tag_index: AHashMap<String, Vec<i64>>,
+    /// 按 (时间戳, id) 排序的全部事件，供 events_in_range 做时间区间查询 (compile 时全量重建，写入 / 删除时增量维护)
+    pub(crate) event_timeline: Vec<(u64, i64)>,
+    /// 节点 -> 入边条数，供检索扩散的反向抑制 (compile 时全量重建，经引擎接口增删边 / 节点时增量维护)
+    pub(crate) in_degrees: AHashMap<u64, usize>,
+    /// 上次构建文本索引后又登记了关键词 / 文本: 新内容在 AC 自动机与 BM25 统计中尚不可见
+    text_index_stale: bool,
+    /// 索引过期的警告只打印一次
+    pub(crate) stale_warned: AtomicBool,
+    /// 上次 compile 以来图谱有改动 (节点 / 边的增删)，compile_if_dirty 据此跳过无意义的重编译
+    dirty: bool,
+    /// 上次 flush 以来写入的节点数
+    unflushed_nodes: usize,
+    /// 语义指纹的词元 IDF 表 (config.idf_weighted_simhash 开启时 compile 构建，否则为空)
+    pub(crate) token_idf: AHashMap<String, f32>,
     pub embedding_model: Option<CandleModel>,
     #[cfg(feature = "gliner")]
     pub gliner_engine: Option<GlinerEngine>,
+    pub config: EngineConfig,
+    /// 情感关键词表 (默认内置 Plutchik 表)，add_event 自动提取情感与查询指纹共用
+    pub emotion_keywords: EmotionKeywords,
+    /// 特征关键词的停用词过滤 (默认内置中英文表，可增删或换成 StopwordFilter::empty())
+    pub stopwords: StopwordFilter,
+    /// 后台维护排期 (可随时替换，None 表示不排期)，请求由 run_pending_maintenance 在引擎线程上执行
+    pub async_task: Option<Box<dyn AsyncTaskInterface + Send + Sync>>,
+    /// 底层存储文件路径 (save 时判断是否需要复制)
+    pub(crate) path: String,
+}
+
+impl Default for AdvancedEngine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// 默认存储路径 (new / builder 未指定 path 时)
+const DEFAULT_STORE_PATH: &str = ".trivium_pedsa";
+
+/// 一次性构造配置完整的引擎，免去打开后再逐个改公开字段
+/// `AdvancedEngine::builder().path(p).with_embedding_model(m).with_config(cfg).build()`
+pub struct AdvancedEngineBuilder {
+    path: String,
+    vector_dim: usize,
+    embedding_model: Option<CandleModel>,
+    async_task: Option<Box<dyn AsyncTaskInterface + Send + Sync>>,
+    config: EngineConfig,
+    emotion_keywords: EmotionKeywords,
+    stopwords: StopwordFilter,
+}
+
+impl Default for AdvancedEngineBuilder {
+    fn default() -> Self {
+        Self {
+            path: DEFAULT_STORE_PATH.to_string(),
+            vector_dim: DEFAULT_VECTOR_DIM,
+            embedding_model: None,
+            async_task: None,
+            config: EngineConfig::default(),
+            emotion_keywords: EmotionKeywords::default(),
+            stopwords: StopwordFilter::default(),
+        }
+    }
+}
+
+impl AdvancedEngineBuilder {
+    pub fn path(mut self, path: &str) -> Self {
+        self.path = path.to_string();
+        self
+    }
+
+    /// 存储的向量维度，默认 DEFAULT_VECTOR_DIM (见 try_open_with_dim)
+    pub fn vector_dim(mut self, dim: usize) -> Self {
+        self.vector_dim = dim;
+        self
+    }
+
+    pub fn with_embedding_model(mut self, model: CandleModel) -> Self {
+        self.embedding_model = Some(model);
+        self
+    }
+
+    pub fn with_async_task(mut self, task: Box<dyn AsyncTaskInterface + Send + Sync>) -> Self {
+        self.async_task = Some(task);
+        self
+    }
+
+    pub fn with_config(mut self, config: EngineConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub fn with_emotion_keywords(mut self, emotion_keywords: EmotionKeywords) -> Self {
+        self.emotion_keywords = emotion_keywords;
+        self
+    }
+
+    pub fn with_stopwords(mut self, stopwords: StopwordFilter) -> Self {
+        self.stopwords = stopwords;
+        self
+    }
+
+    /// 打开存储并装配各组件: 配置非法、模型维度与存储不符时返回错误
+    pub fn build(self) -> Result<AdvancedEngine, StorageError> {
+        self.config.validate().map_err(|e| StorageError::Storage(e.to_string()))?;
+        let mut engine = AdvancedEngine::try_open_with_dim(&self.path, self.vector_dim)?;
+        if let Some(model) = self.embedding_model {
+            engine.set_embedding_model(model)?;
+        }
+        engine.async_task = self.async_task;
+        engine.config = self.config;
+        engine.emotion_keywords = self.emotion_keywords;
+        engine.stopwords = self.stopwords;
+        Ok(engine)
+    }
 }
 
 impl AdvancedEngine {
     pub fn new() -> Self {
-        let db = Database::open(".trivium_pedsa", 512).unwrap();
+        Self::builder().build().unwrap_or_else(|e| panic!("无法打开存储 {}: {}", DEFAULT_STORE_PATH, e))
+    }
+
+    pub fn builder() -> AdvancedEngineBuilder {
+        AdvancedEngineBuilder::default()
+    }
+
+    /// 在指定路径打开 (或创建) 底层 TriviumDB 存储
+    /// 文本索引 (关键词 AC 自动机 + BM25) 只在内存中，打开时按 payload 重建，
+    /// 保证落盘 / 重开前后的检索结果一致
+    /// 打开失败时 panic，需要处理损坏文件时使用 try_open
+    pub fn open(path: &str) -> Self {
+        Self::try_open(path).unwrap_or_else(|e| panic!("无法打开存储 {}: {}", path, e))
+    }
+
+    /// 同 open，但先校验文件头 (魔数 / 版本 / 维度 / 块偏移)，损坏或不兼容的文件返回 StorageError
+    pub fn try_open(path: &str) -> Result<Self, StorageError> {
+        Self::try_open_with_dim(path, DEFAULT_VECTOR_DIM)
+    }
+
+    /// 同 try_open，但按给定的向量维度打开 (如 1024 维的 BGE-M3)
+    /// 维度记录在存储文件头中，已有存储的维度与 dim 不一致时返回 DimensionMismatch。
+    pub fn try_open_with_dim(path: &str, dim: usize) -> Result<Self, StorageError> {
+        validate_store_header(path, dim)?;
+        let mut db = Database::open(path, dim).map_err(|e| match e {
+            triviumdb::TriviumError::Io(e) => StorageError::Io(e),
+            e => StorageError::Storage(e.to_string()),
+        })?;
+        Self::remove_orphan_temp_files(path);
         let mut keyword_to_node = AHashMap::new();
         
         for id in db.all_node_ids() {
-            if let Some(payload) = db.get_payload(id) {
-                if payload.get("type").and_then(|v| v.as_str()) == Some("feature") {
-                    if let Some(content) = payload.get("content").and_then(|v| v.as_str()) {
-                        keyword_to_node.insert(content.to_lowercase(), id as i64);
+            let Some(payload) = db.get_payload(id) else { continue; };
+            let Some(content) = payload.get("content").and_then(|v| v.as_str()) else { continue; };
+            match payload.get("type").and_then(|v| v.as_str()) {
+                Some("feature") => {
+                    let keyword = content.to_lowercase();
+                    db.index_keyword(id, &keyword).ok();
+                    keyword_to_node.insert(keyword, id as i64);
+                    // merge_similar_features 合并掉的关键词
+                    for alias in payload.get("aliases").and_then(|v| v.as_array()).into_iter().flatten().filter_map(|v| v.as_str()) {
+                        keyword_to_node.insert(alias.to_string(), id as i64);
                     }
                 }
+                Some("event") => { db.index_text(id, content).ok(); }
+                _ => {}
             }
         }
+        if db.node_count() > 0 {
+            db.build_text_index().ok();
+        }
         
-        Self {
+        let mut engine = Self {
             tdb: db,
             keyword_to_node,
+            node_to_keywords: AHashMap::new(),
+            tag_index: AHashMap::new(),
+            event_timeline: Vec::new(),
+            in_degrees: AHashMap::new(),
+            text_index_stale: false,
+            stale_warned: AtomicBool::new(false),
+            dirty: false,
+            unflushed_nodes: 0,
+            token_idf: AHashMap::new(),
             embedding_model: None,
             #[cfg(feature = "gliner")]
             gliner_engine: None,
+            config: EngineConfig::default(),
+            emotion_keywords: EmotionKeywords::default(),
+            stopwords: StopwordFilter::default(),
+            async_task: None,
+            path: path.to_string(),
+        };
+        engine.rebuild_tag_index();
+        engine.rebuild_node_keywords();
+        engine.rebuild_event_timeline();
+        engine.rebuild_in_degrees();
+        Ok(engine)
+    }
+
+    /// 崩溃恢复: 持有文件锁后清理上次 flush 中途崩溃遗留的临时文件
+    /// TriviumDB 落盘顺序为 写 .tmp → fsync → rename → 清空 WAL。残留 .tmp 时旧的 .tdb/.vec 仍完整,
+    /// 因此临时文件无论是否写完都直接丢弃，回退到上一次完整落盘的状态。
+    fn remove_orphan_temp_files(path: &str) -> usize {
+        let mut removed = 0;
+        for tmp in [format!("{}.tmp", path), format!("{}.vec.tmp", path)] {
+            if std::fs::remove_file(&tmp).is_ok() {
+                println!("🧹 [Recovery] 已清理未完成的持久化临时文件: {}", tmp);
+                removed += 1;
+            }
         }
+        removed
     }
 
+    /// 测试专用: 在系统临时目录下打开一个全新的独立存储 (每个测试独占文件锁)
+    #[cfg(test)]
+    pub(crate) fn open_temp(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("pedsa_test_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        Self::open(dir.join("db").to_str().unwrap())
+    }
+
+    /// 测试专用: 为 ids 中的每个 id 写入一条互不相关的背景事件，给文本与共振打分提供对照样本
+    #[cfg(test)]
+    pub(crate) fn add_background_noise(&mut self, ids: std::ops::Range<i64>) {
+        for id in ids {
+            self.add_event(id, &format!("无关的日常记录 {}", id), 0, 0, 0);
+        }
+    }
+
+    /// 取文本中第一个日期的时间戳 (见 dates::parse_timestamps)，没有日期时为 DEFAULT_EVENT_TIMESTAMP
     pub fn extract_timestamp(text: &str) -> u64 {
-        let default_ts = 1672531200;
-        for (year_idx, _) in text.match_indices("年") {
-            if year_idx >= 4 && text.is_char_boundary(year_idx - 4) {
-                if let Ok(year) = text[year_idx-4..year_idx].parse::<i32>() {
-                    let mut day = 1;
-                    let rest = &text[year_idx+3..];
-                    if let Some(month_idx) = rest.find("月") {
-                        if month_idx <= 5 {
-                            let m_str = rest[..month_idx].trim();
-                            if let Ok(month) = m_str.parse::<i32>() {
-                                let rest_day = &rest[month_idx+3..];
-                                if let Some(day_idx) = rest_day.find("日") {
-                                    if day_idx <= 5 {
-                                        let d_str = rest_day[..day_idx].trim();
-                                        if let Ok(d) = d_str.parse::<i32>() {
-                                            day = d;
-                                        }
-                                    }
-                                }
-                                return (year as u64 - 1970) * 31536000 + (month as u64) * 2592000 + (day as u64) * 86400;
-                            }
-                        }
-                    }
-                }
-            }
+        dates::parse_timestamps(text).first().copied().unwrap_or(DEFAULT_EVENT_TIMESTAMP)
+    }
+
+    /// 存储的向量维度 (记录在存储文件头中)
+    pub fn vector_dim(&self) -> usize {
+        self.tdb.dim()
+    }
+
+    /// 挂载嵌入模型，模型输出维度必须与存储维度一致，否则原样拒绝
+    pub fn set_embedding_model(&mut self, model: CandleModel) -> Result<(), StorageError> {
+        if model.dimension != self.vector_dim() {
+            return Err(StorageError::DimensionMismatch { expected: self.vector_dim(), found: model.dimension });
         }
-        default_ts
+        self.embedding_model = Some(model);
+        Ok(())
     }
 
+    /// 模型维度与存储维度不一致时返回 None (按无模型处理)，不截断也不补零
     pub fn calculate_chaos(&self, text: &str) -> Option<Vec<f32>> {
-        let model = self.embedding_model.as_ref()?;
-        let weighted_ranges = Vec::new();
-        model.vectorize_weighted(text, &weighted_ranges)
+        let model = self.embedding_model.as_ref().filter(|m| m.dimension == self.vector_dim())?;
+        model.vectorize_long(text)
     }
 
-    pub fn add_feature(&mut self, id: i64, keyword: &str) {
+    /// 批量计算向量 (单次前向传播)，结果同时写入嵌入缓存，随后的 calculate_chaos 直接命中
+    /// 超过模型滑窗的长文本按 vectorize_long 分窗编码，与 calculate_chaos 一致。
+    pub fn calculate_chaos_batch(&self, texts: &[&str]) -> Option<Vec<Vec<f32>>> {
+        self.embedding_model.as_ref().filter(|m| m.dimension == self.vector_dim())?.vectorize_batch_long(texts)
+    }
+
+    /// 特征关键词的归一化形式 (keyword_to_node 的键): 小写，开启 english_stemming 时再做词干化
+    pub fn feature_key(&self, keyword: &str) -> String {
         let keyword_lower = keyword.to_lowercase();
-        if stopwords::is_stopword(&keyword_lower) { return; }
-
-        let _ = self.tdb.insert_with_id(
-            id as u64,
-            &vec![0.0; 512],
-            json!({
-                "type": "feature",
-                "content": keyword_lower,
-                "fingerprint": SimHash::compute(&keyword_lower),
-                "timestamp": 0
-            })
-        );
+        if self.config.english_stemming { stemmer::stem_text(&keyword_lower) } else { keyword_lower }
+    }
+
+    /// 按关键词查找特征节点 id (大小写与词干化规则同 add_feature，含合并后的别名)
+    pub fn feature_id(&self, keyword: &str) -> Option<i64> {
+        self.keyword_to_node.get(&self.feature_key(keyword)).copied()
+    }
+
+    /// 指向该特征节点的全部关键词 (主关键词与别名)，非特征节点为空
+    pub fn keywords_for(&self, id: i64) -> &[String] {
+        self.node_to_keywords.get(&id).map_or(&[], |keywords| keywords.as_slice())
+    }
+
+    /// 由 keyword_to_node 全量重建反向索引
+    pub(crate) fn rebuild_node_keywords(&mut self) {
+        self.node_to_keywords.clear();
+        for (keyword, &id) in &self.keyword_to_node {
+            self.node_to_keywords.entry(id).or_default().push(keyword.clone());
+        }
+        for keywords in self.node_to_keywords.values_mut() {
+            keywords.sort();
+        }
+    }
+
+    pub fn feature_count(&self) -> usize {
+        self.keyword_to_node.len()
+    }
+
+    /// 所有已登记的特征关键词 (归一化形式)
+    pub fn feature_keywords(&self) -> impl Iterator<Item = &str> {
+        self.keyword_to_node.keys().map(String::as_str)
+    }
+
+    pub fn add_feature(&mut self, id: i64, keyword: &str) {
+        if self.stopwords.is_stopword(keyword) { return; }
+        let keyword_lower = self.feature_key(keyword);
+
+        let mut payload = json!({
+            "type": "feature",
+            "content": keyword_lower,
+            "fingerprint": self.fingerprint(&keyword_lower, 0, 0, 0),
+            "timestamp": 0
+        });
+        // 词干化后保留原词形用于展示
+        if keyword_lower != keyword.to_lowercase() {
+            payload["display"] = json!(keyword);
+        }
+        let _ = self.tdb.insert_with_id(id as u64, &vec![0.0; self.vector_dim()], payload);
         self.tdb.index_keyword(id as u64, &keyword_lower).ok();
+        self.mark_text_index_stale();
+        self.unflushed_nodes += 1;
+        let keywords = self.node_to_keywords.entry(id).or_default();
+        if let Err(pos) = keywords.binary_search(&keyword_lower) {
+            keywords.insert(pos, keyword_lower.clone());
+        }
         self.keyword_to_node.insert(keyword_lower, id);
     }
 
+    /// 写入事件; id 已被占用时不做任何改动，只打印警告 (需要区分结果时用 try_add_event)
     pub fn add_event(&mut self, id: i64, summary: &str, explicit_timestamp: u64, explicit_emotion: u8, explicit_type: u8) {
+        if let Err(e) = self.try_add_event(id, summary, explicit_timestamp, explicit_emotion, explicit_type) {
+            println!("⚠️ [Insert] 跳过事件 {}: {}", id, e);
+        }
+    }
+
+    /// 写入事件，id 已被任何节点 (事件 / 特征 / chunk) 占用时返回 IdExists，不会覆盖
+    /// 多向量模式下分块的 chunk id 已被其他节点占用时同样返回 IdExists (id 为冲突的 chunk id)。
+    /// 按 config.duplicate_policy 处理近似重复: Reject 返回 Duplicate，Merge 返回已有事件的 id
+    pub fn try_add_event(&mut self, id: i64, summary: &str, explicit_timestamp: u64, explicit_emotion: u8, explicit_type: u8) -> Result<i64, InsertError> {
+        if let Some(node_type) = self.node_type(id) {
+            return Err(InsertError::IdExists { id, node_type });
+        }
+        if let Some((chunk, node_type)) = self.chunk_id_conflict(id, summary) {
+            return Err(InsertError::IdExists { id: chunk as i64, node_type });
+        }
+        if self.config.duplicate_policy != DuplicatePolicy::Allow
+            && let Some(existing) = self.is_near_duplicate(summary, self.config.duplicate_hamming_threshold)
+        {
+            if self.config.duplicate_policy == DuplicatePolicy::Reject {
+                return Err(InsertError::Duplicate { id, existing });
+            }
+            let now = if explicit_timestamp > 0 { explicit_timestamp } else { Self::extract_timestamp(summary) };
+            self.record_access(existing, now);
+            return Ok(existing);
+        }
+        let (vec, payload) = self.event_node(summary, explicit_timestamp, explicit_emotion, explicit_type);
+        let timestamp = payload["timestamp"].as_u64().unwrap_or(0);
+        self.tdb.insert_with_id(id as u64, &vec, payload).map_err(|e| InsertError::Storage(e.to_string()))?;
+        self.timeline_insert(timestamp, id);
+        self.tdb.index_text(id as u64, summary).ok();
+        self.mark_text_index_stale();
+        self.unflushed_nodes += 1;
+
+        if self.config.multi_vector {
+            self.add_event_chunks(id, summary);
+        }
+        Ok(id)
+    }
+
+    /// 写入或显式覆盖事件: id 不存在时同 try_add_event; 已是事件时替换内容、指纹与向量，
+    /// 保留边、时序脊梁、标签与访问记录; 已被特征等其他类型节点占用时返回 IdExists。
+    /// 注意旧文本在 BM25 索引中的词频要到下次 open 重建索引时才会清除。
+    pub fn add_or_replace_event(&mut self, id: i64, summary: &str, explicit_timestamp: u64, explicit_emotion: u8, explicit_type: u8) -> Result<i64, InsertError> {
+        match self.node_type(id) {
+            None => return self.try_add_event(id, summary, explicit_timestamp, explicit_emotion, explicit_type),
+            Some(node_type) if node_type != "event" => return Err(InsertError::IdExists { id, node_type }),
+            Some(_) => {}
+        }
+        if let Some((chunk, node_type)) = self.chunk_id_conflict(id, summary) {
+            return Err(InsertError::IdExists { id: chunk as i64, node_type });
+        }
+        let old = self.tdb.get_payload(id as u64).unwrap_or_default();
+        let (vec, mut payload) = self.event_node(summary, explicit_timestamp, explicit_emotion, explicit_type);
+        for key in ["prev_event", "next_event", "tags", "access_count", "last_access"] {
+            if let Some(v) = old.get(key) { payload[key] = v.clone(); }
+        }
+        let timestamp = payload["timestamp"].as_u64().unwrap_or(0);
+        self.tdb.update_vector(id as u64, &vec).map_err(|e| InsertError::Storage(e.to_string()))?;
+        self.tdb.update_payload(id as u64, payload).map_err(|e| InsertError::Storage(e.to_string()))?;
+        self.timeline_remove(id);
+        self.timeline_insert(timestamp, id);
+        self.tdb.index_text(id as u64, summary).ok();
+        self.mark_text_index_stale();
+        self.unflushed_nodes += 1;
+
+        self.remove_event_chunks(id);
+        if self.config.multi_vector {
+            self.add_event_chunks(id, summary);
+        }
+        Ok(id)
+    }
+
+    /// 语义区指纹与 summary 的汉明距离不超过 hamming_threshold 的已有事件中最接近的一个 (同距离取较小 id)
+    /// 只比较语义区，时间 / 情感 / 类型不同的同义事件也算重复。
+    pub fn is_near_duplicate(&self, summary: &str, hamming_threshold: u32) -> Option<i64> {
+        let mask = self.config.simhash_layout.mask(SimHashRegion::Semantic);
+        let query = self.fingerprint(summary, 0, 0, 0) & mask;
+        self.tdb.all_node_ids().into_iter()
+            .filter_map(|id| {
+                let payload = self.tdb.get_payload(id)?;
+                if payload.get("type")?.as_str()? != "event" { return None; }
+                let fp = payload.get("fingerprint")?.as_u64()?;
+                let distance = ((fp & mask) ^ query).count_ones();
+                (distance <= hamming_threshold).then_some((distance, id as i64))
+            })
+            .min()
+            .map(|(_, id)| id)
+    }
+
+    /// 节点的 type 字段 (feature / event / chunk，节点不存在时为 None)
+    /// scan_vectors 等返回 SearchHit 的接口可直接读 payload，此方法供只拿到 id 的调用方分层。
+    pub fn node_type(&self, id: i64) -> Option<String> {
+        if id < 0 { return None; }
+        let payload = self.tdb.get_payload(id as u64)?;
+        Some(payload.get("type").and_then(|v| v.as_str()).unwrap_or("unknown").to_string())
+    }
+
+    /// 计算事件节点的向量与 payload (时间戳 / 情感 / 类型缺省时自动提取)
+    fn event_node(&self, summary: &str, explicit_timestamp: u64, explicit_emotion: u8, explicit_type: u8) -> (Vec<f32>, serde_json::Value) {
+        // GLiNER 开启时可能用抽取到的时间实体改写
+        #[cfg_attr(not(feature = "gliner"), allow(unused_mut))]
         let mut timestamp = if explicit_timestamp > 0 { explicit_timestamp } else { Self::extract_timestamp(summary) };
-        let emotion_val = if explicit_emotion > 0 { explicit_emotion } else { SimHash::extract_emotion(summary) };
+        let emotion_val = if explicit_emotion > 0 { explicit_emotion } else { self.emotion_keywords.extract(summary) };
 
         #[cfg(feature = "gliner")]
         let type_val = if explicit_type > 0 { 
@@ -113,25 +618,379 @@ impl AdvancedEngine {
         #[cfg(not(feature = "gliner"))]
         let type_val = if explicit_type > 0 { explicit_type } else { SimHash::TYPE_UNKNOWN };
 
-        let fingerprint = SimHash::compute_multimodal(summary, timestamp, emotion_val, type_val);
-        let payload = json!({
+        let fingerprint = self.fingerprint(summary, timestamp, emotion_val, type_val);
+        let mut payload = json!({
              "type": "event",
              "content": summary,
              "timestamp": timestamp,
              "fingerprint": fingerprint,
              "emotions": emotion_val
         });
+        // 自动提取的情感额外记录各轴强度 (关键词命中次数)，显式传入的情感只有位图
+        if explicit_emotion == 0 && emotion_val != 0 {
+            payload["emotion_intensity"] = json!(self.emotion_keywords.extract_weighted(summary));
+        }
+
+        let vec = self.calculate_chaos(summary).unwrap_or_else(|| vec![0.0; self.vector_dim()]);
+        (vec, payload)
+    }
+
+    /// 同 add_event，并给事件打上任意标签 (项目 / 人物 / 话题等，大小写不敏感)
+    pub fn add_event_with_tags(&mut self, id: i64, summary: &str, explicit_timestamp: u64, explicit_emotion: u8, explicit_type: u8, tags: &[&str]) {
+        if let Err(e) = self.try_add_event(id, summary, explicit_timestamp, explicit_emotion, explicit_type) {
+            println!("⚠️ [Insert] 跳过事件 {}: {}", id, e);
+            return;
+        }
+        let tags: Vec<String> = tags.iter().map(|t| t.trim().to_lowercase()).filter(|t| !t.is_empty()).collect();
+        if tags.is_empty() { return; }
+        if let Some(mut payload) = self.tdb.get_payload(id as u64) {
+            payload["tags"] = json!(tags);
+            let _ = self.tdb.update_payload(id as u64, payload);
+        }
+        for tag in tags {
+            self.tag_index.entry(tag).or_default().push(id);
+        }
+    }
+
+    /// 从事件 payload 全量重建标签索引
+    pub fn rebuild_tag_index(&mut self) {
+        self.tag_index.clear();
+        for id in self.tdb.all_node_ids() {
+            let Some(payload) = self.tdb.get_payload(id) else { continue; };
+            let Some(tags) = payload.get("tags").and_then(|v| v.as_array()) else { continue; };
+            for tag in tags.iter().filter_map(|t| t.as_str()) {
+                self.tag_index.entry(tag.to_string()).or_default().push(id as i64);
+            }
+        }
+        for ids in self.tag_index.values_mut() {
+            ids.sort();
+            ids.dedup();
+        }
+    }
+
+    /// 多向量模式: 将事件按句切分，每个分句存为一个挂在父事件下的 chunk 向量节点
+    /// chunk 节点不进入文本索引，仅参与向量检索，命中后在 retrieve 中折叠回父事件。
+    fn add_event_chunks(&mut self, id: i64, summary: &str) {
+        let chunks = Self::split_sentences(summary);
+        if chunks.len() < 2 { return; }
+        for (i, chunk) in chunks.iter().enumerate() {
+            let Some(vec) = self.calculate_chaos(chunk) else { return; };
+            let _ = self.tdb.insert_with_id(Self::chunk_id(id, i), &vec, json!({
+                "type": "chunk",
+                "parent": id,
+                "content": chunk,
+            }));
+        }
+    }
+
+    /// chunk 节点 id 由 (父事件 id, 分句序号) 哈希得出，与调用方指定的 id 共用同一空间，
+    /// 写入前用 chunk_id_conflict 检查、删除前用 is_chunk_of 确认归属
+    fn chunk_id(id: i64, i: usize) -> u64 {
+        let mut s = XxHash64::with_seed(0);
+        (id, i).hash(&mut s);
+        (s.finish() as i64).unsigned_abs()
+    }
 
-        if let Some(vec) = self.calculate_chaos(summary) {
-             let _ = self.tdb.insert_with_id(id as u64, &vec, payload);
+    /// chunk_id 处的节点确实是 parent 的分块
+    fn is_chunk_of(&self, chunk_id: u64, parent: i64) -> bool {
+        self.tdb.get_payload(chunk_id).is_some_and(|p| {
+            p.get("type").and_then(|v| v.as_str()) == Some("chunk") && p.get("parent").and_then(|v| v.as_i64()) == Some(parent)
+        })
+    }
+
+    /// 多向量模式下 summary 的分块 id 中第一个被其他节点 (不是 id 自己的 chunk) 占用的，及该节点的类型
+    fn chunk_id_conflict(&self, id: i64, summary: &str) -> Option<(u64, String)> {
+        if !self.config.multi_vector { return None; }
+        let chunks = Self::split_sentences(summary).len();
+        if chunks < 2 { return None; }
+        (0..chunks).map(|i| Self::chunk_id(id, i))
+            .filter(|&chunk| !self.is_chunk_of(chunk, id))
+            .find_map(|chunk| {
+                let payload = self.tdb.get_payload(chunk)?;
+                Some((chunk, payload.get("type").and_then(|v| v.as_str()).unwrap_or_default().to_string()))
+            })
+    }
+
+    /// 删除一个事件节点及其 chunk 分块，并把时序脊梁上的前后事件直接相连
+    pub fn remove_event(&mut self, id: i64) -> bool {
+        let Some(payload) = self.tdb.get_payload(id as u64) else { return false; };
+        if payload.get("type").and_then(|v| v.as_str()) != Some("event") { return false; }
+
+        let prev = payload.get("prev_event").and_then(|v| v.as_i64());
+        let next = payload.get("next_event").and_then(|v| v.as_i64());
+        for (neighbor, key, value) in [(prev, "next_event", next), (next, "prev_event", prev)] {
+            if let Some(neighbor) = neighbor
+                && let Some(mut p) = self.tdb.get_payload(neighbor as u64) {
+                match value {
+                    Some(v) => p[key] = json!(v),
+                    None => { p.as_object_mut().map(|o| o.remove(key)); }
+                }
+                let _ = self.tdb.update_payload(neighbor as u64, p);
+            }
+        }
+
+        for ids in self.tag_index.values_mut() {
+            ids.retain(|&x| x != id);
+        }
+        self.timeline_remove(id);
+        self.remove_event_chunks(id);
+        self.dirty = true;
+        self.unflushed_nodes += 1;
+        self.delete_raw_node(id as u64)
+    }
+
+    /// 删除任意类型的节点: 事件同 remove_event；特征同时移除关键词 (含别名) 映射；其余节点直接删除
+    /// 删除对内存中与已落盘的节点同样生效，连带的边由 TriviumDB 一并移除，下次 flush 时物理丢弃。
+    pub fn delete_node(&mut self, id: i64) -> bool {
+        match self.node_type(id).as_deref() {
+            None => false,
+            Some("event") => self.remove_event(id),
+            Some(node_type) => {
+                if node_type == "feature" {
+                    self.keyword_to_node.retain(|_, &mut node| node != id);
+                    self.node_to_keywords.remove(&id);
+                }
+                let deleted = self.delete_raw_node(id as u64);
+                if deleted {
+                    self.unflushed_nodes += 1;
+                    self.dirty = true;
+                }
+                deleted
+            }
+        }
+    }
+
+    pub(crate) fn remove_event_chunks(&mut self, id: i64) {
+        let mut i = 0;
+        while self.is_chunk_of(Self::chunk_id(id, i), id) {
+            self.delete_raw_node(Self::chunk_id(id, i));
+            i += 1;
+        }
+    }
+
+    /// 记录一次对事件的访问 (被检索并实际使用)，访问越多遗忘越慢
+    pub fn record_access(&mut self, id: i64, now: u64) {
+        if let Some(mut payload) = self.tdb.get_payload(id as u64) {
+            let count = payload.get("access_count").and_then(|v| v.as_u64()).unwrap_or(0);
+            payload["access_count"] = json!(count + 1);
+            payload["last_access"] = json!(now);
+            let _ = self.tdb.update_payload(id as u64, payload);
+        }
+    }
+
+    /// 按中英文句末标点切分文本 (去除空白分句)
+    pub fn split_sentences(text: &str) -> Vec<&str> {
+        text.split(['。', '！', '？', '；', '!', '?', ';', '\n'])
+            .map(|s| s.trim())
+            .filter(|s| !s.is_empty())
+            .collect()
+    }
+
+    /// 为所有事件重新计算向量 (例如先无模型入库，之后再加载模型)，返回重建的事件数
+    pub fn rebuild_chaos_store(&mut self) -> usize {
+        if self.embedding_model.is_none() {
+            println!("⚠️ [Rebuild] 未加载嵌入模型，跳过向量重建");
+            return 0;
+        }
+        let events: Vec<(u64, String)> = self.tdb.all_node_ids().into_iter().filter_map(|id| {
+            let payload = self.tdb.get_payload(id)?;
+            if payload.get("type").and_then(|v| v.as_str()) != Some("event") { return None; }
+            Some((id, payload.get("content")?.as_str()?.to_string()))
+        }).collect();
+
+        let total = events.len();
+        let mut rebuilt = 0;
+        let mut done = 0;
+        for batch in events.chunks(EMBEDDING_BATCH_SIZE) {
+            let texts: Vec<&str> = batch.iter().map(|(_, content)| content.as_str()).collect();
+            if let Some(vectors) = self.calculate_chaos_batch(&texts) {
+                for ((id, _), vec) in batch.iter().zip(&vectors) {
+                    if self.tdb.update_vector(*id, vec).is_ok() { rebuilt += 1; }
+                }
+            }
+            if self.config.multi_vector {
+                for (id, content) in batch {
+                    self.add_event_chunks(*id as i64, content);
+                }
+            }
+            let prev = done;
+            done += batch.len();
+            if done / 1000 > prev / 1000 || done == total {
+                println!("🔄 [Rebuild] 向量重建进度: {}/{}", done, total);
+            }
+        }
+        rebuilt
+    }
+
+    /// 按当前布局计算多模态指纹，IDF 表非空时语义区加权
+    pub(crate) fn fingerprint(&self, text: &str, timestamp: u64, emotion_val: u8, type_val: u8) -> u64 {
+        let layout = &self.config.simhash_layout;
+        if self.token_idf.is_empty() {
+            layout.compute_multimodal(text, timestamp, emotion_val, type_val)
         } else {
-             let _ = self.tdb.insert_with_id(id as u64, &vec![0.0; 512], payload);
+            layout.compute_multimodal_weighted(text, timestamp, emotion_val, type_val, &self.token_idf)
+        }
+    }
+
+    /// 查询指纹，与 fingerprint 使用同一布局与 IDF 表
+    pub(crate) fn query_fingerprint(&self, query: &str, ref_time: u64) -> u64 {
+        let layout = &self.config.simhash_layout;
+        let fp = if self.token_idf.is_empty() {
+            layout.compute_for_query(query, ref_time)
+        } else {
+            layout.compute_for_query_weighted(query, ref_time, &self.token_idf)
+        };
+        // 情感区按引擎的关键词表重算，与入库时的提取规则一致
+        layout.with_region(fp, SimHashRegion::Affective, self.emotion_keywords.extract(query) as u64)
+    }
+
+    /// 由全部事件文本重建 IDF 表，并重算所有节点指纹的语义区 (其余分区保持不变)
+    fn rebuild_token_idf(&mut self) {
+        let ids = self.tdb.all_node_ids();
+        let contents: Vec<String> = ids.iter()
+            .filter_map(|&id| self.tdb.get_payload(id))
+            .filter(|p| p.get("type").and_then(|v| v.as_str()) == Some("event"))
+            .filter_map(|p| p.get("content")?.as_str().map(String::from))
+            .collect();
+        self.token_idf = SimHash::build_idf(contents.iter().map(String::as_str));
+        for id in ids {
+            let Some(mut payload) = self.tdb.get_payload(id) else { continue; };
+            let Some(fp) = payload.get("fingerprint").and_then(|v| v.as_u64()) else { continue; };
+            let Some(content) = payload.get("content").and_then(|v| v.as_str()) else { continue; };
+            let content = content.to_lowercase();
+            payload["fingerprint"] = json!(self.config.simhash_layout.with_semantic(fp, &content, Some(&self.token_idf)));
+            let _ = self.tdb.update_payload(id, payload);
+        }
+    }
+
+    /// SimHash 算法变更后原地迁移指纹: 对每个带指纹的节点用 f(content) 重算并写回 payload
+    /// 向量、文本与边保持不变。返回改写的节点数。
+    pub fn rewrite_fingerprints(&mut self, f: impl Fn(&str) -> u64) -> usize {
+        let mut rewritten = 0;
+        for id in self.tdb.all_node_ids() {
+            let Some(mut payload) = self.tdb.get_payload(id) else { continue; };
+            if payload.get("fingerprint").is_none() { continue; }
+            let Some(content) = payload.get("content").and_then(|v| v.as_str()) else { continue; };
+            payload["fingerprint"] = serde_json::json!(f(content));
+            if self.tdb.update_payload(id, payload).is_ok() {
+                rewritten += 1;
+            }
+        }
+        rewritten
+    }
+
+    /// 记录 src -> dst 的联想在 now 时刻被强化 (被使用)，存于源节点 payload 的 edge_reinforced 表
+    /// 经引擎写入边 (add_edge / maintain_ontology 等) 时自动以写入时刻记录一次。
+    pub fn reinforce_edge(&mut self, src: i64, dst: i64, now: u64) {
+        let Some(mut payload) = self.tdb.get_payload(src as u64) else { return; };
+        if !payload.get("edge_reinforced").is_some_and(|v| v.is_object()) {
+            payload["edge_reinforced"] = json!({});
+        }
+        payload["edge_reinforced"][dst.to_string()] = json!(now);
+        let _ = self.tdb.update_payload(src as u64, payload);
+    }
+
+    /// 检索时的有效边强度: 开启 config.edge_decay 且边有强化记录时乘以遗忘曲线系数
+    /// exp(-距上次强化时长 / config.resonance.decay_tau)，与事件的时间衰减同一时间常数
+    pub fn effective_edge_strength(&self, src: u64, edge: &triviumdb::node::Edge, now: u64) -> f32 {
+        if !self.config.edge_decay { return edge.weight; }
+        self.decayed_strength(self.tdb.get_payload(src).as_ref(), edge, now)
+    }
+
+    /// src 的全部出边及其有效强度 (见 effective_edge_strength)，源节点 payload 只读取一次
+    pub(crate) fn edges_with_strength(&self, src: u64, now: u64) -> Vec<(triviumdb::node::Edge, f32)> {
+        let edges = self.tdb.get_edges(src);
+        let payload = if self.config.edge_decay { self.tdb.get_payload(src) } else { None };
+        edges.into_iter().map(|edge| {
+            let strength = if self.config.edge_decay { self.decayed_strength(payload.as_ref(), &edge, now) } else { edge.weight };
+            (edge, strength)
+        }).collect()
+    }
+
+    fn decayed_strength(&self, src_payload: Option<&serde_json::Value>, edge: &triviumdb::node::Edge, now: u64) -> f32 {
+        let reinforced = src_payload.and_then(|p| p.get("edge_reinforced")?.get(edge.target_id.to_string())?.as_u64());
+        match reinforced {
+            Some(ts) if ts < now => edge.weight * (-((now - ts) as f32) / self.config.resonance.decay_tau).exp(),
+            _ => edge.weight,
         }
-        self.tdb.index_text(id as u64, summary).ok();
     }
 
     pub fn add_edge(&mut self, src: i64, tgt: i64, weight: f32) {
-        let _ = self.tdb.link(src as u64, tgt as u64, "memory_edge", weight);
+        self.add_edge_typed(src, tgt, weight, MemoryEdgeType::Association);
+    }
+
+    /// 写入带语义类型的记忆边。不同类型使用不同标签，同一对节点可同时存在多种类型的边；
+    /// 检索扩散与种子扩散时都按 config.edge_type_gain 对各类型的边强度加权 (如沿因果链放大)。
+    pub fn add_edge_typed(&mut self, src: i64, tgt: i64, weight: f32, edge_type: MemoryEdgeType) {
+        self.upsert_link(src as u64, tgt as u64, edge_type.label(), weight);
+    }
+
+    /// 写入一条边: 同标签的 src -> dst 边已存在时合并为一条 (强度取较大值)
+    /// TriviumDB 的 link 不去重，重复写入会让入度 (反向抑制因子) 失真。
+    /// 新建与合并都算一次强化，以当前时刻记入 edge_reinforced (见 reinforce_edge)。
+    /// 返回 (原有强度, 写入后强度)，原先不存在该边时原有强度为 None
+    pub(crate) fn upsert_link(&mut self, src: u64, dst: u64, label: &str, weight: f32) -> (Option<f32>, f32) {
+        let weight = clamp_strength(weight);
+        self.dirty = true;
+        if self.tdb.contains(dst) {
+            let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+            self.reinforce_edge(src as i64, dst as i64, now);
+        }
+        let existing: Vec<_> = self.tdb.get_edges(src).into_iter().filter(|e| e.target_id == dst).collect();
+        let old = existing.iter().filter(|e| e.label == label).map(|e| e.weight).reduce(f32::max);
+        let Some(old_weight) = old else {
+            self.link_raw(src, dst, label, weight);
+            return (None, weight);
+        };
+        self.unlink_raw(src, dst);
+        for edge in existing.iter().filter(|e| e.label != label) {
+            self.link_raw(src, dst, &edge.label, edge.weight);
+        }
+        let merged = weight.max(old_weight);
+        self.link_raw(src, dst, label, merged);
+        (old, merged)
+    }
+
+    /// 直接写入一条边 (不去重)，同时计入目标入度
+    pub(crate) fn link_raw(&mut self, src: u64, dst: u64, label: &str, weight: f32) {
+        if self.tdb.link(src, dst, label, weight).is_ok() {
+            *self.in_degrees.entry(dst).or_insert(0) += 1;
+        }
+    }
+
+    /// 移除 src -> dst 的全部边并扣减目标入度，返回移除的条数
+    pub(crate) fn unlink_raw(&mut self, src: u64, dst: u64) -> usize {
+        let removed = self.tdb.get_edges(src).iter().filter(|e| e.target_id == dst).count();
+        if removed > 0 && self.tdb.unlink(src, dst).is_ok() {
+            if let Some(degree) = self.in_degrees.get_mut(&dst) {
+                *degree = degree.saturating_sub(removed);
+            }
+            return removed;
+        }
+        0
+    }
+
+    /// 删除底层节点 (连带其出入边)，出边目标的入度随之扣减
+    pub(crate) fn delete_raw_node(&mut self, id: u64) -> bool {
+        let edges = self.tdb.get_edges(id);
+        if self.tdb.delete(id).is_err() { return false; }
+        for edge in edges {
+            if let Some(degree) = self.in_degrees.get_mut(&edge.target_id) {
+                *degree = degree.saturating_sub(1);
+            }
+        }
+        self.in_degrees.remove(&id);
+        true
+    }
+
+    /// 从全部出边重建入度表 (直接经 tdb 写入的边在此之后才计入)
+    pub(crate) fn rebuild_in_degrees(&mut self) {
+        self.in_degrees.clear();
+        for id in self.tdb.all_node_ids() {
+            for edge in self.tdb.get_edges(id) {
+                *self.in_degrees.entry(edge.target_id).or_insert(0) += 1;
+            }
+        }
     }
 
     pub fn build_temporal_backbone(&mut self) {
@@ -139,11 +998,10 @@ impl AdvancedEngine {
         let mut events: Vec<(i64, u64)> = Vec::new();
         
         for id in self.tdb.all_node_ids() {
-            if let Some(payload) = self.tdb.get_payload(id) {
-                if payload.get("type").and_then(|v| v.as_str()) == Some("event") {
-                    let ts = payload.get("timestamp").and_then(|v| v.as_u64()).unwrap_or(0);
-                    events.push((id as i64, ts));
-                }
+            if let Some(payload) = self.tdb.get_payload(id)
+                && payload.get("type").and_then(|v| v.as_str()) == Some("event") {
+                let ts = payload.get("timestamp").and_then(|v| v.as_u64()).unwrap_or(0);
+                events.push((id as i64, ts));
             }
         }
         
@@ -163,8 +1021,134 @@ impl AdvancedEngine {
         println!("✅ 时序脊梁构建完成，已串联 {} 个事件节点。", events.len());
     }
 
+    /// 沿时序脊梁向后走至多 n 步，返回途经的事件 (不含起点)，按时间先后排列
+    pub fn events_after(&self, id: i64, n: usize) -> Vec<i64> {
+        self.walk_backbone(id, n, "next_event")
+    }
+
+    /// 沿时序脊梁向前走至多 n 步，返回途经的事件 (不含起点)，由近及远排列
+    pub fn events_before(&self, id: i64, n: usize) -> Vec<i64> {
+        self.walk_backbone(id, n, "prev_event")
+    }
+
+    /// 按 payload 中的 prev_event / next_event 指针遍历，遇到链尾、非事件节点或环时停止。
+    /// 起点不是事件，或存在多个事件而起点没有任何指针 (时序脊梁尚未构建) 时返回空。
+    fn walk_backbone(&self, id: i64, n: usize, key: &str) -> Vec<i64> {
+        let Some(start) = self.tdb.get_payload(id as u64) else { return Vec::new(); };
+        if start.get("type").and_then(|v| v.as_str()) != Some("event") { return Vec::new(); }
+        if start.get("prev_event").is_none() && start.get("next_event").is_none() {
+            if self.event_timeline.len() > 1 {
+                println!("⚠️ [Backbone] 事件 {} 没有时序指针，请先调用 build_temporal_backbone", id);
+            }
+            return Vec::new();
+        }
+        let mut visited = AHashSet::from([id]);
+        let mut steps = Vec::new();
+        let mut current = start;
+        while steps.len() < n {
+            let Some(next) = current.get(key).and_then(|v| v.as_i64()) else { break; };
+            if !visited.insert(next) { break; }
+            let Some(payload) = self.tdb.get_payload(next as u64) else { break; };
+            if payload.get("type").and_then(|v| v.as_str()) != Some("event") { break; }
+            steps.push(next);
+            current = payload;
+        }
+        steps
+    }
+
+    /// 时间戳落在 [start_ts, end_ts] (含两端) 内的事件，按时间先后排列 (同一时刻按 id)
+    /// 基于有序时间线二分查找，与 SimHash 时间区的模糊共振不同，是精确的时间切片。
+    pub fn events_in_range(&self, start_ts: u64, end_ts: u64) -> Vec<i64> {
+        if start_ts > end_ts { return Vec::new(); }
+        let lo = self.event_timeline.partition_point(|&(ts, _)| ts < start_ts);
+        let hi = self.event_timeline.partition_point(|&(ts, _)| ts <= end_ts);
+        self.event_timeline[lo..hi].iter().map(|&(_, id)| id).collect()
+    }
+
+    /// 从事件 payload 全量重建有序时间线
+    pub(crate) fn rebuild_event_timeline(&mut self) {
+        self.event_timeline = self.tdb.all_node_ids().into_iter()
+            .filter_map(|id| {
+                let payload = self.tdb.get_payload(id)?;
+                if payload.get("type")?.as_str()? != "event" { return None; }
+                Some((payload.get("timestamp").and_then(|v| v.as_u64()).unwrap_or(0), id as i64))
+            })
+            .collect();
+        self.event_timeline.sort_unstable();
+    }
+
+    fn timeline_insert(&mut self, timestamp: u64, id: i64) {
+        if let Err(pos) = self.event_timeline.binary_search(&(timestamp, id)) {
+            self.event_timeline.insert(pos, (timestamp, id));
+        }
+    }
+
+    fn timeline_remove(&mut self, id: i64) {
+        self.event_timeline.retain(|&(_, x)| x != id);
+    }
+
+    fn mark_text_index_stale(&mut self) {
+        self.text_index_stale = true;
+        self.dirty = true;
+        self.stale_warned.store(false, Ordering::Relaxed);
+    }
+
+    /// 是否有尚未编入文本索引的关键词 / 文本 (需 compile 或 refresh_text_index)
+    pub fn is_text_index_stale(&self) -> bool {
+        self.text_index_stale
+    }
+
+    /// 只重建文本索引 (AC 自动机 + BM25)，不落盘; 索引未过期时不做任何事
+    pub fn refresh_text_index(&mut self) {
+        if self.text_index_stale {
+            self.tdb.build_text_index().ok();
+            self.text_index_stale = false;
+        }
+    }
+
+    /// 自上次 flush / compile 以来写入的节点数
+    pub fn unflushed_nodes(&self) -> usize {
+        self.unflushed_nodes
+    }
+
+    /// 未落盘写入达到 config.max_unflushed_nodes，建议调用方择机 flush
+    pub fn needs_flush(&self) -> bool {
+        self.config.max_unflushed_nodes > 0 && self.unflushed_nodes >= self.config.max_unflushed_nodes
+    }
+
+    /// 落盘 (不重建索引)
+    pub fn flush(&mut self) -> Result<(), String> {
+        self.tdb.flush().map_err(|e| e.to_string())?;
+        self.unflushed_nodes = 0;
+        Ok(())
+    }
+
+    /// 上次 compile 之后是否写入过节点或边
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    /// 仅在有改动时 compile (LLM 维护会话中可在每批操作后调用，多次改动合并为一次重建)
+    /// 返回是否实际执行了 compile。
+    pub fn compile_if_dirty(&mut self) -> bool {
+        if !self.dirty { return false; }
+        self.compile();
+        true
+    }
+
     pub fn compile(&mut self) {
         self.tdb.build_text_index().ok();
+        self.text_index_stale = false;
+        self.dirty = false;
+        self.rebuild_tag_index();
+        self.rebuild_node_keywords();
+        self.rebuild_event_timeline();
+        self.rebuild_in_degrees();
+        if self.config.idf_weighted_simhash {
+            self.rebuild_token_idf();
+        } else {
+            self.token_idf.clear();
+        }
         
         #[cfg(feature = "gliner")]
         {
@@ -181,6 +1165,524 @@ impl AdvancedEngine {
             }
         }
         self.tdb.flush().unwrap();
+        self.unflushed_nodes = 0;
         println!("🚀 引擎编译/落盘完成：共 {} 个底层存储节点", self.tdb.node_count());
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::simhash::SimHashRegion;
+
+    #[test]
+    fn test_rebuild_chaos_store_after_loading_model() {
+        let Ok(model) = CandleModel::new() else {
+            eprintln!("Model failed to load, skipping rebuild test");
+            return;
+        };
+        let mut engine = AdvancedEngine::open_temp("rebuild_chaos");
+        engine.add_event(1, "傍晚在厨房烤了一炉松软的蔓越莓面包", 0, 0, 0);
+        engine.add_event(2, "周末去郊外爬山，山顶的风景非常开阔", 0, 0, 0);
+        engine.add_event(3, "地铁里人很多，通勤花了一个小时", 0, 0, 0);
+        engine.compile();
+        let is_empty = |engine: &AdvancedEngine, id: u64| engine.tdb.get(id).unwrap().vector.iter().all(|&x| x == 0.0);
+        assert!((1..=3).all(|id| is_empty(&engine, id)));
+        assert_eq!(engine.rebuild_chaos_store(), 0);
+
+        engine.embedding_model = Some(model);
+        assert_eq!(engine.rebuild_chaos_store(), 3);
+        assert!((1..=3).all(|id| !is_empty(&engine, id)));
+
+        // 查询与事件没有共同的字面词，只能依靠向量召回
+        let results = engine.retrieve("烘焙甜点", 0, 0.0);
+        assert_eq!(results.first().map(|&(id, _)| id), Some(1));
+    }
+
+    #[test]
+    fn test_edge_mutations_apply_without_recompile() {
+        let mut engine = AdvancedEngine::open_temp("edge_mutation");
+        let pero = engine.get_or_create_feature("Pero");
+        engine.add_event(1, "换了一条蓝色的裙子", 0, 0, 0);
+        engine.add_background_noise(100..120);
+        engine.compile();
+        assert!(engine.retrieve("Pero", 0, 0.0).iter().all(|&(id, _)| id != 1));
+
+        // compile 之后新增的边立即参与扩散
+        engine.add_edge(pero, 1, 0.5);
+        engine.add_edge(pero, 1, 0.9);
+        engine.maintain_ontology("Pero", "女孩", "representation", 0.6);
+        engine.maintain_ontology("Pero", "女孩", "representation", 0.8);
+        assert!(engine.retrieve("Pero", 0, 0.0).iter().any(|&(id, _)| id == 1));
+
+        // 重复写入不产生重复边 (不会重复累加入度)
+        let edges = engine.tdb.get_edges(pero as u64);
+        let to_event: Vec<_> = edges.iter().filter(|e| e.target_id == 1).collect();
+        assert_eq!(to_event.len(), 1);
+        assert_eq!(to_event[0].weight, 0.9);
+        let girl = engine.keyword_to_node["女孩"] as u64;
+        let to_girl: Vec<_> = edges.iter().filter(|e| e.target_id == girl).collect();
+        assert_eq!(to_girl.len(), 1);
+        assert_eq!(to_girl[0].weight, 0.8);
+    }
+
+    #[test]
+    fn test_edge_strength_is_clamped() {
+        let mut engine = AdvancedEngine::open_temp("clamp_strength");
+        let change = engine.maintain_ontology("Rust", "编程语言", "representation", 1.5);
+        assert_eq!(change.new_strength, 1.0);
+        let pero = engine.get_or_create_feature("Pero");
+        engine.add_event(1, "一件事", 0, 0, 0);
+        engine.add_edge(pero, 1, -0.3);
+        assert_eq!(engine.tdb.get_edges(pero as u64)[0].weight, 0.0);
+        assert_eq!(clamp_strength(f32::NAN), 0.0);
+        assert_eq!(clamp_strength(f32::INFINITY), 1.0);
+    }
+
+    #[test]
+    fn test_open_recovers_from_interrupted_flush() {
+        let dir = std::env::temp_dir().join(format!("pedsa_test_crash_recovery_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let path = dir.join("db").to_str().unwrap().to_string();
+        {
+            let mut engine = AdvancedEngine::open(&path);
+            engine.add_event(1, "落盘前的事件", 0, 0, 0);
+            engine.tdb.flush().unwrap();
+        }
+        // 模拟下一次 flush 写完临时文件但在 rename 前崩溃
+        std::fs::write(format!("{}.tmp", path), b"half written tdb").unwrap();
+        std::fs::write(format!("{}.vec.tmp", path), b"half written vec").unwrap();
+
+        let engine = AdvancedEngine::open(&path);
+        assert!(!std::path::Path::new(&format!("{}.tmp", path)).exists());
+        assert!(!std::path::Path::new(&format!("{}.vec.tmp", path)).exists());
+        assert_eq!(engine.tdb.get_payload(1).unwrap()["content"], "落盘前的事件");
+    }
+
+    #[test]
+    fn test_rewrite_fingerprints_in_place() {
+        let mut engine = AdvancedEngine::open_temp("rewrite_fingerprints");
+        engine.get_or_create_feature("Pero");
+        engine.add_event(1, "第一件事", 100, SimHash::EMOTION_JOY, 0);
+        engine.add_event(2, "完全不同的第二件事", 200, 0, 0);
+        let vector = engine.tdb.get(1).unwrap().vector;
+
+        assert_eq!(engine.rewrite_fingerprints(|_| 0xABCD), 3);
+        let fps: Vec<u64> = engine.tdb.all_node_ids().into_iter()
+            .filter_map(|id| engine.tdb.get_payload(id)?.get("fingerprint")?.as_u64())
+            .collect();
+        assert_eq!(fps.len(), 3);
+        assert!(fps.iter().all(|fp| (fp ^ fps[0]).count_ones() == 0));
+        assert_eq!(engine.tdb.get(1).unwrap().vector, vector);
+        assert_eq!(engine.tdb.get_payload(2).unwrap()["content"], "完全不同的第二件事");
+    }
+
+    #[test]
+    fn test_reopen_preserves_retrieval() {
+        let dir = std::env::temp_dir().join(format!("pedsa_test_reopen_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let path = dir.join("db").to_str().unwrap().to_string();
+        let (baseline, in_memory) = {
+            let mut engine = AdvancedEngine::open(&path);
+            let pero = engine.get_or_create_feature("Pero");
+            engine.add_event(1, "和 Pero 一起去看了烟花", 0, 0, 0);
+            engine.add_event(2, "Pero 今天换了一条蓝色的裙子", 0, 0, 0);
+            engine.add_edge(pero, 1, 1.0);
+            engine.add_background_noise(100..120);
+            engine.compile();
+            let baseline = engine.retrieve("Pero 烟花", 0, 0.0);
+            engine.add_event(3, "周末去郊外爬山", 0, 0, 0);
+            engine.compile();
+            (baseline, engine.retrieve("Pero 烟花", 0, 0.0))
+        };
+
+        let engine = AdvancedEngine::open(&path);
+        let ids = |results: &[(i64, f32)]| results.iter().map(|&(id, _)| id).collect::<Vec<_>>();
+        let reopened = engine.retrieve("Pero 烟花", 0, 0.0);
+        assert!(!baseline.is_empty());
+        assert_eq!(ids(&reopened), ids(&baseline));
+        assert_eq!(reopened.len(), in_memory.len());
+        for (&(_, before), &(_, after)) in in_memory.iter().zip(&reopened) {
+            assert!((before - after).abs() < 1e-4);
+        }
+        assert_eq!(ids(&engine.retrieve("爬山", 0, 0.0)).first(), Some(&3));
+        assert_eq!(engine.tdb.node_count(), 24);
+        assert_eq!(engine.verify(), Ok(()));
+    }
+
+    #[test]
+    fn test_add_event_rejects_id_collision() {
+        let mut engine = AdvancedEngine::open_temp("id_collision");
+        let pero = engine.get_or_create_feature("Pero");
+        assert_eq!(engine.try_add_event(1, "第一件事", 100, 0, 0), Ok(1));
+        assert_eq!(engine.try_add_event(1, "另一件事", 200, 0, 0), Err(InsertError::IdExists { id: 1, node_type: "event".to_string() }));
+        assert_eq!(engine.tdb.get_payload(1).unwrap()["content"], "第一件事");
+        // 特征节点不会被事件覆盖
+        assert_eq!(engine.try_add_event(pero, "冒用特征 id 的事件", 0, 0, 0), Err(InsertError::IdExists { id: pero, node_type: "feature".to_string() }));
+        assert!(engine.add_or_replace_event(pero, "冒用特征 id 的事件", 0, 0, 0).is_err());
+        assert_eq!(engine.node_type(pero).as_deref(), Some("feature"));
+        assert_eq!(engine.node_type(1).as_deref(), Some("event"));
+        assert_eq!(engine.node_type(12345), None);
+
+        // 显式覆盖: 内容更新，边与时序指针保留
+        engine.add_event(2, "第二件事", 300, 0, 0);
+        engine.add_edge(pero, 1, 1.0);
+        engine.build_temporal_backbone();
+        assert_eq!(engine.add_or_replace_event(1, "改写后的第一件事", 100, 0, 0), Ok(1));
+        let payload = engine.tdb.get_payload(1).unwrap();
+        assert_eq!(payload["content"], "改写后的第一件事");
+        assert_eq!(payload["next_event"], 2);
+        assert_eq!(engine.tdb.get_edges(pero as u64).len(), 1);
+        assert_eq!(engine.verify(), Ok(()));
+    }
+
+    #[test]
+    fn test_near_duplicate_policy() {
+        let mut engine = AdvancedEngine::open_temp("near_duplicate");
+        engine.add_event(1, "周末和 Pero 去海边散步", 100, SimHash::EMOTION_JOY, 0);
+        engine.add_event(2, "在家写 Rust 代码", 200, 0, 0);
+        // 只比较语义区: 时间与情感不同的同一句话仍是重复
+        assert_eq!(engine.is_near_duplicate("周末和 Pero 去海边散步", 0), Some(1));
+        assert_eq!(engine.is_near_duplicate("晚上读一本历史书", 0), None);
+
+        // 默认 Allow 照常写入
+        assert_eq!(engine.try_add_event(3, "在家写 Rust 代码", 300, 0, 0), Ok(3));
+        engine.config.duplicate_policy = DuplicatePolicy::Reject;
+        assert_eq!(engine.try_add_event(4, "周末和 Pero 去海边散步", 999, SimHash::EMOTION_SADNESS, 0),
+                   Err(InsertError::Duplicate { id: 4, existing: 1 }));
+        assert_eq!(engine.node_type(4), None);
+        assert_eq!(engine.try_add_event(5, "晚上读一本历史书", 400, 0, 0), Ok(5));
+
+        engine.config.duplicate_policy = DuplicatePolicy::Merge;
+        assert_eq!(engine.try_add_event(6, "周末和 Pero 去海边散步", 500, 0, 0), Ok(1));
+        assert_eq!(engine.node_type(6), None);
+        let payload = engine.tdb.get_payload(1).unwrap();
+        assert_eq!(payload["access_count"], 1);
+        assert_eq!(payload["last_access"], 500);
+    }
+
+    #[test]
+    fn test_events_in_range() {
+        let mut engine = AdvancedEngine::open_temp("events_in_range");
+        engine.add_event(1, "2024-05-20 出发前的准备", 0, 0, 0);
+        engine.add_event(2, "2024-08-01 到达海边", 0, 0, 0);
+        engine.add_event(3, "2024-06-15 买了车票", 0, 0, 0);
+        engine.add_event(4, "2024-09-30 假期结束", 0, 0, 0);
+        engine.add_event(5, "2024-10-01 回到公司", 0, 0, 0);
+        engine.add_event(6, "同一天的另一件事", dates::date_timestamp(2024, 8, 1).unwrap(), 0, 0);
+
+        let june = dates::date_timestamp(2024, 6, 1).unwrap();
+        let sep_end = dates::date_timestamp(2024, 9, 30).unwrap();
+        assert_eq!(engine.events_in_range(june, sep_end), vec![3, 2, 6, 4]);
+        assert!(engine.events_in_range(sep_end, june).is_empty());
+
+        assert_eq!(engine.add_or_replace_event(3, "2024-10-02 改签了车票", 0, 0, 0), Ok(3));
+        engine.remove_event(4);
+        assert_eq!(engine.events_in_range(june, sep_end), vec![2, 6]);
+        let all = engine.events_in_range(0, u64::MAX);
+        assert_eq!(all, vec![1, 2, 6, 5, 3]);
+        engine.compile();
+        assert_eq!(engine.events_in_range(0, u64::MAX), all);
+    }
+
+    #[test]
+    fn test_walk_temporal_backbone() {
+        let mut engine = AdvancedEngine::open_temp("walk_backbone");
+        for (id, ts) in [(1, 300), (2, 100), (3, 500), (4, 200), (5, 400)] {
+            engine.add_event(id, &format!("第 {} 件事", id), ts, 0, 0);
+        }
+        // 脊梁尚未构建
+        assert!(engine.events_after(2, 3).is_empty());
+        engine.build_temporal_backbone();
+
+        assert_eq!(engine.events_after(2, 2), vec![4, 1]);
+        assert_eq!(engine.events_after(1, 10), vec![5, 3]);
+        assert_eq!(engine.events_before(3, 3), vec![5, 1, 4]);
+        assert!(engine.events_before(2, 5).is_empty());
+        assert!(engine.events_after(3, 0).is_empty());
+        assert!(engine.events_after(999, 2).is_empty());
+
+        // 人为造成的环不会死循环
+        let mut payload = engine.tdb.get_payload(3).unwrap();
+        payload["next_event"] = json!(4);
+        engine.tdb.update_payload(3, payload).unwrap();
+        assert_eq!(engine.events_after(4, 10), vec![1, 5, 3]);
+    }
+
+    #[test]
+    fn test_compile_if_dirty_coalesces_rebuilds() {
+        let mut engine = AdvancedEngine::open_temp("compile_if_dirty");
+        assert!(!engine.compile_if_dirty());
+        engine.add_event(1, "和 Pero 一起去看了烟花", 0, 0, 0);
+        assert!(engine.is_dirty());
+        assert!(engine.compile_if_dirty());
+        assert!(!engine.is_dirty() && !engine.compile_if_dirty());
+
+        // 一批维护操作只触发一次重建
+        engine.maintain_ontology("烟花", "夏天", "representation", 0.6);
+        engine.maintain_ontology("Pero", "猫", "representation", 0.5);
+        assert!(engine.compile_if_dirty());
+        assert!(!engine.compile_if_dirty());
+        assert!(engine.feature_id("夏天").is_some());
+
+        let pero = engine.feature_id("Pero").unwrap();
+        assert!(engine.delete_node(pero));
+        assert!(engine.compile_if_dirty());
+        engine.record_access(1, 1_700_000_000);
+        assert!(!engine.is_dirty());
+    }
+
+    #[test]
+    fn test_feature_added_after_compile_needs_refresh() {
+        let mut engine = AdvancedEngine::open_temp("stale_index");
+        engine.add_event(1, "和 Pero 一起去看了烟花", 0, 0, 0);
+        engine.add_background_noise(100..120);
+        engine.compile();
+        assert!(!engine.is_text_index_stale());
+
+        let fireworks = engine.get_or_create_feature("烟花");
+        engine.add_edge(fireworks, 1, 1.0);
+        assert!(engine.is_text_index_stale());
+        // 过期索引下检索不会 panic 或错配，新特征只是暂不可见
+        let stale = engine.retrieve_typed("烟花", 0, 0.0, crate::core::retrieval::ResultFilter::FeaturesOnly);
+        assert!(stale.iter().all(|&(id, _)| id != fireworks));
+
+        engine.refresh_text_index();
+        assert!(!engine.is_text_index_stale());
+        let fresh = engine.retrieve_typed("烟花", 0, 0.0, crate::core::retrieval::ResultFilter::FeaturesOnly);
+        assert!(fresh.iter().any(|&(id, _)| id == fireworks));
+    }
+
+    #[test]
+    fn test_needs_flush_signal() {
+        let mut engine = AdvancedEngine::open_temp("needs_flush");
+        engine.config.max_unflushed_nodes = 3;
+        engine.add_event(1, "第一件事", 0, 0, 0);
+        engine.get_or_create_feature("Pero");
+        assert!(!engine.needs_flush());
+        engine.add_event(2, "第二件事", 0, 0, 0);
+        assert!(engine.needs_flush());
+        // 被拒绝的写入不计数
+        engine.add_event(2, "重复 id", 0, 0, 0);
+        assert_eq!(engine.unflushed_nodes(), 3);
+
+        engine.flush().unwrap();
+        assert_eq!(engine.unflushed_nodes(), 0);
+        assert!(!engine.needs_flush());
+        engine.config.max_unflushed_nodes = 0;
+        engine.add_event(3, "第三件事", 0, 0, 0);
+        assert!(!engine.needs_flush());
+    }
+
+    #[test]
+    fn test_compile_builds_idf_and_reweights_fingerprints() {
+        let mut engine = AdvancedEngine::open_temp("idf_simhash");
+        engine.config.idf_weighted_simhash = true;
+        engine.add_event(1, "今天的晚饭是红烧肉", 1700000000, SimHash::EMOTION_JOY, 0);
+        engine.add_event(2, "今天的天气不错", 0, 0, 0);
+        engine.add_event(3, "今天的会议推迟了", 0, 0, 0);
+        let before = engine.tdb.get_payload(1).unwrap()["fingerprint"].as_u64().unwrap();
+        engine.compile();
+
+        assert!(engine.token_idf["今"] < engine.token_idf["肉"]);
+        let after = engine.tdb.get_payload(1).unwrap()["fingerprint"].as_u64().unwrap();
+        let layout = engine.config.simhash_layout;
+        let semantic = layout.mask(SimHashRegion::Semantic);
+        assert_eq!(after & !semantic, before & !semantic);
+        assert_eq!(after & semantic, SimHash::compute_text_hash_weighted("今天的晚饭是红烧肉", layout.semantic_bits, Some(&engine.token_idf)));
+        assert_eq!(engine.fingerprint("今天的晚饭是红烧肉", 1700000000, SimHash::EMOTION_JOY, 0), after);
+        assert_eq!(engine.retrieve("红烧肉", 0, 0.0).first().map(|r| r.0), Some(1));
+
+        engine.config.idf_weighted_simhash = false;
+        engine.compile();
+        assert!(engine.token_idf.is_empty());
+    }
+
+    #[test]
+    fn test_try_open_rejects_corrupt_files() {
+        let dir = std::env::temp_dir().join(format!("pedsa_test_corrupt_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("db");
+        let path_str = path.to_str().unwrap();
+        {
+            let mut engine = AdvancedEngine::open(path_str);
+            engine.add_event(1, "完好的事件", 0, 0, 0);
+            engine.compile();
+        }
+        let good = std::fs::read(&path).unwrap();
+        let reopen = |bytes: &[u8]| {
+            std::fs::write(&path, bytes).unwrap();
+            AdvancedEngine::try_open(path_str).err()
+        };
+        let patched = |at: usize, value: &[u8]| {
+            let mut bytes = good.clone();
+            bytes[at..at + value.len()].copy_from_slice(value);
+            bytes
+        };
+
+        assert!(matches!(reopen(&good[..20]), Some(StorageError::TruncatedFile)));
+        assert!(matches!(reopen(&good[..good.len() - 1]), Some(StorageError::TruncatedFile)));
+        assert!(matches!(reopen(&patched(0, b"NOPE")), Some(StorageError::BadMagic)));
+        assert!(matches!(reopen(&patched(4, &3u16.to_le_bytes())), Some(StorageError::UnsupportedVersion(3))));
+        assert!(matches!(reopen(&patched(6, &128u32.to_le_bytes())), Some(StorageError::DimensionMismatch { expected: 512, found: 128 })));
+        assert!(matches!(reopen(&patched(26, &8u64.to_le_bytes())), Some(StorageError::MisalignedOffset)));
+        assert!(matches!(reopen(&patched(18, &u64::MAX.to_le_bytes())), Some(StorageError::TruncatedFile)));
+
+        assert!(reopen(&good).is_none());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_runtime_vector_dim() {
+        let dir = std::env::temp_dir().join(format!("pedsa_test_vector_dim_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let (path, saved) = (dir.join("db"), dir.join("saved/db"));
+        let (path, saved) = (path.to_str().unwrap(), saved.to_str().unwrap());
+        {
+            let mut engine = AdvancedEngine::try_open_with_dim(path, 128).unwrap();
+            assert_eq!(engine.vector_dim(), 128);
+            engine.add_event(1, "低维存储里的事件", 0, 0, 0);
+            assert_eq!(engine.tdb.get(1).unwrap().vector.len(), 128);
+            // 512 维的模型不能挂到 128 维的存储上，未挂载的模型也不会被用来产出错误维度的向量
+            if let Ok(model) = CandleModel::new() {
+                assert!(matches!(engine.set_embedding_model(model), Err(StorageError::DimensionMismatch { expected: 128, found: 512 })));
+                assert!(engine.embedding_model.is_none());
+            }
+            engine.compile();
+            engine.save(saved).unwrap();
+        }
+        assert!(matches!(AdvancedEngine::try_open(path).err(), Some(StorageError::DimensionMismatch { expected: 512, found: 128 })));
+        assert_eq!(AdvancedEngine::try_open_with_dim(path, 128).unwrap().node_type(1).as_deref(), Some("event"));
+        // 会话清单记录维度，load 无需调用方再指定
+        assert_eq!(AdvancedEngine::load(saved).unwrap().vector_dim(), 128);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_builder_assembles_engine() {
+        use std::time::Duration;
+        use crate::core::types::ThreadedAsyncTask;
+
+        let dir = std::env::temp_dir().join(format!("pedsa_test_builder_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let path = dir.join("db");
+        let path = path.to_str().unwrap();
+        let config = EngineConfig { min_score: 0.2, diffusion_depth: 3, ..Default::default() };
+        let mut emotion_keywords = EmotionKeywords::default();
+        emotion_keywords.table.entry(SimHash::EMOTION_ANGER).or_default().push("红温".to_string());
+
+        let mut builder = AdvancedEngine::builder()
+            .path(path)
+            .with_config(config.clone())
+            .with_emotion_keywords(emotion_keywords.clone())
+            .with_async_task(Box::new(ThreadedAsyncTask::spawn(Duration::from_secs(3600), 0.1, 0)));
+        if let Ok(model) = CandleModel::new() {
+            builder = builder.with_embedding_model(model);
+        }
+        let engine = builder.build().unwrap();
+        assert_eq!(engine.config, config);
+        assert_eq!(engine.emotion_keywords, emotion_keywords);
+        assert!(engine.async_task.is_some());
+        assert_eq!(engine.path, path);
+        drop(engine);
+
+        let invalid = EngineConfig { refinement_top_n: 0, ..Default::default() };
+        assert!(matches!(AdvancedEngine::builder().path(path).with_config(invalid).build(), Err(StorageError::Storage(_))));
+        if let Ok(model) = CandleModel::new() {
+            let mismatched = AdvancedEngine::builder().path(path).vector_dim(1024).with_embedding_model(model).build();
+            assert!(matches!(mismatched, Err(StorageError::DimensionMismatch { .. })));
+        }
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_chunk_ids_never_touch_other_nodes() {
+        let mut engine = AdvancedEngine::open_temp("chunk_collision");
+        engine.add_event(1, "第一句。第二句。", 0, 0, 0);
+        // 占用事件 1 / 事件 2 的分块 id 的特征节点
+        let taken = AdvancedEngine::chunk_id(1, 0) as i64;
+        engine.add_feature(taken, "占位特征");
+        let other = AdvancedEngine::chunk_id(2, 1) as i64;
+        engine.add_feature(other, "另一个占位特征");
+
+        // 删除事件时不会误删 id 恰好等于 chunk id 的节点
+        assert!(engine.remove_event(1));
+        assert_eq!(engine.node_type(taken).as_deref(), Some("feature"));
+
+        // 多向量模式下写入会先检查分块 id 是否被占用
+        engine.config.multi_vector = true;
+        assert_eq!(engine.try_add_event(2, "第一句。第二句。", 0, 0, 0),
+            Err(InsertError::IdExists { id: other, node_type: "feature".to_string() }));
+        assert_eq!(engine.node_type(2), None);
+        assert_eq!(engine.try_add_event(3, "只有一句", 0, 0, 0), Ok(3));
+    }
+
+    #[test]
+    fn test_in_degrees_track_edge_changes() {
+        let mut engine = AdvancedEngine::open_temp("in_degrees");
+        for id in 1..=4 {
+            engine.add_event(id, &format!("事件 {}", id), 0, 0, 0);
+        }
+        engine.add_edge(1, 3, 0.5);
+        // 同标签重复写入合并为一条，不重复计入入度
+        engine.add_edge(1, 3, 0.9);
+        engine.add_edge(2, 3, 0.5);
+        engine.add_edge(3, 4, 0.001);
+        engine.maintain_ontology("猫", "狗", "equality", 0.5);
+        assert_eq!(engine.in_degrees[&3], 2);
+
+        engine.weaken_ontology("猫", "狗", 0.1);
+        assert_eq!(engine.apply_global_decay_and_pruning(0.0, 100), 1);
+        assert!(engine.delete_node(1));
+        assert_eq!(engine.in_degrees[&3], 1);
+
+        // 增量维护的结果与全量重建一致
+        let nonzero = |engine: &AdvancedEngine| {
+            let mut degrees: Vec<(u64, usize)> = engine.in_degrees.iter().filter(|&(_, &d)| d > 0).map(|(&id, &d)| (id, d)).collect();
+            degrees.sort_unstable();
+            degrees
+        };
+        let incremental = nonzero(&engine);
+        engine.rebuild_in_degrees();
+        assert_eq!(incremental, nonzero(&engine));
+    }
+
+    #[test]
+    fn test_delete_node_any_type() {
+        let dir = std::env::temp_dir().join(format!("pedsa_test_delete_node_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let path = dir.join("db");
+        let path = path.to_str().unwrap();
+        let feature = {
+            let mut engine = AdvancedEngine::open(path);
+            engine.add_event(1, "红烧肉的做法", 0, 0, 0);
+            engine.add_event(2, "周末爬山", 0, 0, 0);
+            let feature = engine.get_or_create_feature("红烧肉");
+            engine.add_edge(feature, 1, 1.0);
+            engine.compile();
+            feature
+        };
+
+        // 已落盘的节点同样可以删除
+        let mut engine = AdvancedEngine::open(path);
+        assert_eq!(engine.keywords_for(feature), ["红烧肉"]);
+        assert!(engine.keywords_for(1).is_empty());
+        assert!(engine.delete_node(feature));
+        assert_eq!(engine.feature_id("红烧肉"), None);
+        assert!(engine.keywords_for(feature).is_empty());
+        assert!(!engine.delete_node(feature));
+        assert!(engine.delete_node(2));
+        assert!(!engine.delete_node(404));
+        engine.compile();
+        drop(engine);
+
+        let engine = AdvancedEngine::open(path);
+        assert_eq!(engine.node_type(feature), None);
+        assert_eq!(engine.node_type(2), None);
+        assert_eq!(engine.node_type(1).as_deref(), Some("event"));
+        assert!(engine.tdb.get_edges(1).is_empty());
+        drop(engine);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
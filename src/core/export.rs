@@ -0,0 +1,172 @@
+//! 图导出 (GraphML / JSON)，供 Gephi、Cytoscape 等外部工具可视化
+//! 导出特征与事件节点及其间的全部边，chunk 等派生节点不导出；边按标签区分本体层与记忆层。
+
+use std::io::Write;
+use serde::Serialize;
+use crate::core::engine::AdvancedEngine;
+
+/// 本体层的边标签 (maintain_ontology 写入)，其余标签都属于记忆层
+const ONTOLOGY_LABELS: [&str; 3] = ["equality", "inhibition", "representation"];
+
+#[derive(Debug, Serialize)]
+struct ExportNode {
+    id: u64,
+    #[serde(rename = "type")]
+    node_type: String,
+    content: String,
+    timestamp: u64,
+    fingerprint: u64,
+}
+
+#[derive(Debug, Serialize)]
+struct ExportEdge {
+    source: u64,
+    target: u64,
+    edge_type: String,
+    connection_strength: f32,
+    /// ontology / memory
+    layer: &'static str,
+}
+
+#[derive(Debug, Serialize)]
+struct ExportGraph {
+    nodes: Vec<ExportNode>,
+    edges: Vec<ExportEdge>,
+}
+
+fn edge_layer(label: &str) -> &'static str {
+    if ONTOLOGY_LABELS.contains(&label) { "ontology" } else { "memory" }
+}
+
+fn escape_xml(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+impl AdvancedEngine {
+    /// 按 id 排序的节点与按 (源, 目标, 标签) 排序的边
+    fn export_graph(&self) -> ExportGraph {
+        let mut nodes: Vec<ExportNode> = self.tdb.all_node_ids().into_iter().filter_map(|id| {
+            let payload = self.tdb.get_payload(id)?;
+            let node_type = payload.get("type")?.as_str()?;
+            if !matches!(node_type, "feature" | "event") { return None; }
+            Some(ExportNode {
+                id,
+                node_type: node_type.to_string(),
+                content: payload.get("content").and_then(|v| v.as_str()).unwrap_or_default().to_string(),
+                timestamp: payload.get("timestamp").and_then(|v| v.as_u64()).unwrap_or(0),
+                fingerprint: payload.get("fingerprint").and_then(|v| v.as_u64()).unwrap_or(0),
+            })
+        }).collect();
+        nodes.sort_by_key(|n| n.id);
+
+        let exported = |id: u64| nodes.binary_search_by_key(&id, |n| n.id).is_ok();
+        let mut edges: Vec<ExportEdge> = nodes.iter()
+            .flat_map(|n| self.tdb.get_edges(n.id).into_iter().map(move |e| (n.id, e)))
+            .filter(|(_, e)| exported(e.target_id))
+            .map(|(source, e)| ExportEdge {
+                source,
+                target: e.target_id,
+                layer: edge_layer(&e.label),
+                edge_type: e.label,
+                connection_strength: e.weight,
+            })
+            .collect();
+        edges.sort_by(|a, b| (a.source, a.target, &a.edge_type).cmp(&(b.source, b.target, &b.edge_type)));
+        ExportGraph { nodes, edges }
+    }
+
+    /// 以 JSON 导出图: {"nodes": [{id, type, content, timestamp, fingerprint}], "edges": [{source, target, edge_type, connection_strength, layer}]}
+    pub fn export_json<W: Write>(&self, writer: W) -> std::io::Result<()> {
+        serde_json::to_writer_pretty(writer, &self.export_graph()).map_err(std::io::Error::other)
+    }
+
+    /// 以 GraphML 导出图 (有向图，节点 id 为 n<节点id>)
+    /// 指纹是 u64，超出 GraphML long 的范围，以十进制字符串存放。
+    pub fn export_graphml<W: Write>(&self, mut writer: W) -> std::io::Result<()> {
+        let graph = self.export_graph();
+        writeln!(writer, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(writer, r#"<graphml xmlns="http://graphml.graphdrawing.org/xmlns">"#)?;
+        for (id, domain, name, ty) in [
+            ("type", "node", "type", "string"),
+            ("content", "node", "content", "string"),
+            ("timestamp", "node", "timestamp", "long"),
+            ("fingerprint", "node", "fingerprint", "string"),
+            ("edge_type", "edge", "edge_type", "string"),
+            ("connection_strength", "edge", "connection_strength", "float"),
+            ("layer", "edge", "layer", "string"),
+        ] {
+            writeln!(writer, r#"  <key id="{}" for="{}" attr.name="{}" attr.type="{}"/>"#, id, domain, name, ty)?;
+        }
+        writeln!(writer, r#"  <graph id="pedsa" edgedefault="directed">"#)?;
+        for node in &graph.nodes {
+            writeln!(writer, r#"    <node id="n{}">"#, node.id)?;
+            writeln!(writer, r#"      <data key="type">{}</data>"#, node.node_type)?;
+            writeln!(writer, r#"      <data key="content">{}</data>"#, escape_xml(&node.content))?;
+            writeln!(writer, r#"      <data key="timestamp">{}</data>"#, node.timestamp)?;
+            writeln!(writer, r#"      <data key="fingerprint">{}</data>"#, node.fingerprint)?;
+            writeln!(writer, "    </node>")?;
+        }
+        for edge in &graph.edges {
+            writeln!(writer, r#"    <edge source="n{}" target="n{}">"#, edge.source, edge.target)?;
+            writeln!(writer, r#"      <data key="edge_type">{}</data>"#, escape_xml(&edge.edge_type))?;
+            writeln!(writer, r#"      <data key="connection_strength">{}</data>"#, edge.connection_strength)?;
+            writeln!(writer, r#"      <data key="layer">{}</data>"#, edge.layer)?;
+            writeln!(writer, "    </edge>")?;
+        }
+        writeln!(writer, "  </graph>")?;
+        writeln!(writer, "</graphml>")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_export_json_and_graphml() {
+        let mut engine = AdvancedEngine::open_temp("graph_export");
+        engine.add_event(1, "和 <Pero> & 猫去海边", 0, 0, 0);
+        engine.add_event(2, "在家写 Rust", 0, 0, 0);
+        let pero = engine.get_or_create_feature("pero");
+        engine.add_edge(pero, 1, 0.9);
+        engine.add_edge(1, 2, 0.4);
+        engine.maintain_ontology("pero", "佩罗", "equality", 1.0);
+        engine.config.multi_vector = true;
+        engine.add_event(3, "第一句。第二句。", 0, 0, 0);
+
+        let mut json = Vec::new();
+        engine.export_json(&mut json).unwrap();
+        let value: serde_json::Value = serde_json::from_slice(&json).unwrap();
+        let nodes = value["nodes"].as_array().unwrap();
+        // chunk 节点不导出
+        assert_eq!(nodes.len(), 5);
+        assert!(nodes.iter().any(|n| n["id"] == 1 && n["type"] == "event" && n["content"] == "和 <Pero> & 猫去海边"));
+        let edges = value["edges"].as_array().unwrap();
+        assert_eq!(edges.len(), 4);
+        let layer = |src: i64, tgt: i64| edges.iter().find(|e| e["source"] == src && e["target"] == tgt).map(|e| e["layer"].clone());
+        assert_eq!(layer(pero, 1), Some("memory".into()));
+        assert_eq!(layer(1, 2), Some("memory".into()));
+        assert_eq!(layer(pero, engine.feature_id("佩罗").unwrap()), Some("ontology".into()));
+        assert!(edges.iter().any(|e| e["edge_type"] == "equality" && e["connection_strength"] == 1.0));
+
+        let mut graphml = Vec::new();
+        engine.export_graphml(&mut graphml).unwrap();
+        let graphml = String::from_utf8(graphml).unwrap();
+        assert_eq!(graphml.matches("<node ").count(), 5);
+        assert_eq!(graphml.matches("<edge ").count(), 4);
+        assert!(graphml.contains("和 &lt;Pero&gt; &amp; 猫去海边"));
+        assert!(graphml.contains(&format!(r#"<edge source="n{}" target="n1">"#, pero)));
+        assert!(graphml.trim_end().ends_with("</graphml>"));
+    }
+}
@@ -0,0 +1,109 @@
+use crate::core::engine::AdvancedEngine;
+
+/// verify 发现的一致性问题
+#[derive(Debug, Clone, PartialEq)]
+pub enum IntegrityError {
+    /// 节点向量维度与库维度不符
+    VectorDim { id: u64, dim: usize, expected: usize },
+    /// 节点缺少 payload 或 payload 没有 type 字段
+    MissingType { id: u64 },
+    /// 边指向不存在的节点
+    DanglingEdge { src: u64, dst: u64 },
+    /// 时序脊梁 prev_event / next_event 指向不存在的事件
+    DanglingBackbone { id: u64, neighbor: i64 },
+    /// 时序脊梁前后指针不对称 (A.next = B 但 B.prev != A)
+    AsymmetricBackbone { id: u64, next: i64 },
+    /// chunk 分块的父事件不存在
+    OrphanChunk { id: u64, parent: i64 },
+    /// keyword_to_node 指向不存在或内容 / 别名均不符的特征节点
+    StaleKeyword { keyword: String, id: i64 },
+}
+
+impl AdvancedEngine {
+    /// 一致性自检: 逐项检查存储、图谱与内存索引是否互相吻合，不会 panic
+    pub fn verify(&self) -> Result<(), Vec<IntegrityError>> {
+        let mut errors = Vec::new();
+        let expected = self.tdb.dim();
+
+        for id in self.tdb.all_node_ids() {
+            let Some(node) = self.tdb.get(id) else {
+                errors.push(IntegrityError::MissingType { id });
+                continue;
+            };
+            if node.vector.len() != expected {
+                errors.push(IntegrityError::VectorDim { id, dim: node.vector.len(), expected });
+            }
+            for edge in &node.edges {
+                if !self.tdb.contains(edge.target_id) {
+                    errors.push(IntegrityError::DanglingEdge { src: id, dst: edge.target_id });
+                }
+            }
+
+            let payload = &node.payload;
+            match payload.get("type").and_then(|v| v.as_str()) {
+                Some("event") => {
+                    for key in ["prev_event", "next_event"] {
+                        let Some(neighbor) = payload.get(key).and_then(|v| v.as_i64()) else { continue; };
+                        let neighbor_type = self.tdb.get_payload(neighbor as u64)
+                            .and_then(|p| p.get("type").and_then(|v| v.as_str()).map(str::to_string));
+                        if neighbor_type.as_deref() != Some("event") {
+                            errors.push(IntegrityError::DanglingBackbone { id, neighbor });
+                        } else if key == "next_event"
+                            && self.tdb.get_payload(neighbor as u64).and_then(|p| p.get("prev_event").and_then(|v| v.as_i64())) != Some(id as i64) {
+                            errors.push(IntegrityError::AsymmetricBackbone { id, next: neighbor });
+                        }
+                    }
+                }
+                Some("chunk") => {
+                    let parent = payload.get("parent").and_then(|v| v.as_i64()).unwrap_or(-1);
+                    if parent < 0 || !self.tdb.contains(parent as u64) {
+                        errors.push(IntegrityError::OrphanChunk { id, parent });
+                    }
+                }
+                Some(_) => {}
+                None => errors.push(IntegrityError::MissingType { id }),
+            }
+        }
+
+        for (keyword, &id) in &self.keyword_to_node {
+            let known = self.tdb.get_payload(id as u64).is_some_and(|p| {
+                p.get("content").and_then(|v| v.as_str()) == Some(keyword.as_str())
+                    || p.get("aliases").and_then(|v| v.as_array()).is_some_and(|a| a.iter().any(|v| v.as_str() == Some(keyword.as_str())))
+            });
+            if !known {
+                errors.push(IntegrityError::StaleKeyword { keyword: keyword.clone(), id });
+            }
+        }
+
+        if errors.is_empty() { Ok(()) } else { Err(errors) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verify_reports_inconsistencies() {
+        let mut engine = AdvancedEngine::open_temp("verify");
+        let pero = engine.get_or_create_feature("Pero");
+        engine.add_event(1, "第一件事", 100, 0, 0);
+        engine.add_event(2, "第二件事", 200, 0, 0);
+        engine.add_edge(pero, 1, 1.0);
+        engine.build_temporal_backbone();
+        assert_eq!(engine.verify(), Ok(()));
+
+        // 人为破坏: 时序指针指向不存在的事件、索引指向已删除的特征、孤立 chunk
+        let mut payload = engine.tdb.get_payload(2).unwrap();
+        payload["next_event"] = serde_json::json!(999);
+        engine.tdb.update_payload(2, payload).unwrap();
+        engine.keyword_to_node.insert("ghost".to_string(), 12345);
+        engine.tdb.insert_with_id(50, &vec![0.0; 512], serde_json::json!({"type": "chunk", "parent": 777})).unwrap();
+
+        let errors = engine.verify().unwrap_err();
+        assert_eq!(errors.len(), 3);
+        assert!(errors.contains(&IntegrityError::DanglingBackbone { id: 2, neighbor: 999 }));
+        assert!(errors.contains(&IntegrityError::StaleKeyword { keyword: "ghost".to_string(), id: 12345 }));
+        assert!(errors.contains(&IntegrityError::OrphanChunk { id: 50, parent: 777 }));
+    }
+}
@@ -0,0 +1,348 @@
+//! Markdown 导入 / 导出: 标题为事件，`[[关键词]]` 为特征关联，导出再导入得到等价的图
+//!
+//! ```text
+//! - [[孤立特征]]
+//! - [[pero]] --equality 1--> [[佩罗]]
+//! - #100 --memory_edge 0.6--> #205
+//!
+//! # 事件摘要
+//! ---
+//! id: 100
+//! date: 2024-03-01
+//! ---
+//! 正文中的 [[rust]] 与 [[内存安全|0.8]] 都是指向该事件的特征关联
+//! ```
+//!
+//! 第一个标题之前的列表项是孤立特征与其余的边 (端点写作 `[[特征]]` 或 `#事件id`)，其他文字忽略。
+//! 标题后可紧跟 front-matter: `id` 缺省时按标题哈希生成 (重复标题依次加后缀区分)，
+//! `date` 为 YYYY-MM-DD 或 Unix 秒，`emotion` 为显式情感位图。
+
+use std::fmt::Write;
+use std::hash::{Hash, Hasher};
+use ahash::{AHashMap, AHashSet};
+use twox_hash::XxHash64;
+use crate::core::dates;
+use crate::core::engine::AdvancedEngine;
+
+/// 特征 -> 事件的关联边标签，在事件段落中写作 [[关键词]]
+const FEATURE_LINK_LABEL: &str = "memory_edge";
+
+/// 边的端点: 特征按关键词，事件按 id
+#[derive(Debug, Clone, PartialEq)]
+enum NodeRef {
+    Feature(String),
+    Event(i64),
+}
+
+#[derive(Debug, Default)]
+struct ParsedEvent {
+    summary: String,
+    id: Option<i64>,
+    timestamp: u64,
+    emotion: u8,
+    links: Vec<(String, f32)>,
+}
+
+#[derive(Debug, Default)]
+struct ParsedDocument {
+    features: Vec<String>,
+    edges: Vec<(NodeRef, String, f32, NodeRef)>,
+    events: Vec<ParsedEvent>,
+}
+
+/// `# 标题` (1-6 级) 的标题文字
+fn heading(line: &str) -> Option<&str> {
+    let level = line.bytes().take_while(|&b| b == b'#').count();
+    if !(1..=6).contains(&level) || !line[level..].starts_with(' ') { return None; }
+    let text = line[level..].trim();
+    (!text.is_empty()).then_some(text)
+}
+
+/// `关键词` 或 `关键词|强度`
+fn parse_link(inner: &str) -> (String, f32) {
+    if let Some((keyword, weight)) = inner.rsplit_once('|')
+        && let Ok(weight) = weight.trim().parse::<f32>() {
+        return (keyword.trim().to_string(), weight);
+    }
+    (inner.trim().to_string(), 1.0)
+}
+
+/// 一行中的全部 [[...]] 链接
+fn parse_links(line: &str) -> Vec<(String, f32)> {
+    let mut links = Vec::new();
+    let mut rest = line;
+    while let Some(start) = rest.find("[[") {
+        let Some(len) = rest[start + 2..].find("]]") else { break; };
+        let (keyword, weight) = parse_link(&rest[start + 2..start + 2 + len]);
+        if !keyword.is_empty() { links.push((keyword, weight)); }
+        rest = &rest[start + 4 + len..];
+    }
+    links
+}
+
+fn parse_node(token: &str) -> Option<NodeRef> {
+    if let Some(keyword) = token.strip_prefix("[[").and_then(|t| t.strip_suffix("]]")) {
+        let keyword = keyword.trim();
+        return (!keyword.is_empty()).then(|| NodeRef::Feature(keyword.to_string()));
+    }
+    token.strip_prefix('#')?.parse().ok().map(NodeRef::Event)
+}
+
+/// 标题前的列表项: `[[特征]]` 或 `端点 --标签 强度--> 端点`，其他内容忽略
+fn parse_list_item(doc: &mut ParsedDocument, item: &str) -> Result<(), String> {
+    let item = item.trim();
+    let Some((src, rest)) = item.split_once(" --").filter(|(_, rest)| rest.contains("--> ")) else {
+        if let Some(NodeRef::Feature(keyword)) = parse_node(item) { doc.features.push(keyword); }
+        return Ok(());
+    };
+    let (spec, tgt) = rest.split_once("--> ").unwrap_or_default();
+    let (label, weight) = spec.trim().split_once(' ').ok_or_else(|| format!("关系需写作 --标签 强度-->: {}", item))?;
+    let weight: f32 = weight.trim().parse().map_err(|_| format!("无法解析强度 {}", weight.trim()))?;
+    let node = |token: &str| parse_node(token.trim()).ok_or_else(|| format!("无法识别的端点 {} (应为 [[关键词]] 或 #事件id)", token.trim()));
+    doc.edges.push((node(src)?, label.to_string(), weight, node(tgt)?));
+    Ok(())
+}
+
+fn parse_front_matter(event: &mut ParsedEvent, line: &str) -> Result<(), String> {
+    let Some((key, value)) = line.split_once(':') else { return Err(format!("front-matter 需写作 key: value: {}", line)); };
+    let value = value.trim();
+    match key.trim() {
+        "id" => event.id = Some(value.parse().map_err(|_| format!("无法解析 id {}", value))?),
+        "date" => {
+            event.timestamp = match value.parse::<u64>() {
+                Ok(ts) => ts,
+                Err(_) => match dates::parse_timestamps(value).as_slice() {
+                    [ts] => *ts,
+                    _ => return Err(format!("无法解析日期 {}", value)),
+                },
+            };
+        }
+        "emotion" => event.emotion = value.parse().map_err(|_| format!("无法解析情感位图 {}", value))?,
+        _ => {}
+    }
+    Ok(())
+}
+
+fn parse_markdown(text: &str) -> Result<ParsedDocument, String> {
+    let mut doc = ParsedDocument::default();
+    let lines: Vec<&str> = text.lines().map(str::trim).collect();
+    let mut i = 0;
+    while i < lines.len() {
+        let line = lines[i];
+        i += 1;
+        if let Some(summary) = heading(line) {
+            let mut event = ParsedEvent { summary: summary.to_string(), ..Default::default() };
+            // 紧随标题的 front-matter
+            if lines.get(i) == Some(&"---") {
+                let start = i;
+                i += 1;
+                loop {
+                    let Some(&line) = lines.get(i) else { return Err(format!("第 {} 行: front-matter 未闭合", start + 1)); };
+                    i += 1;
+                    if line == "---" { break; }
+                    if !line.is_empty() {
+                        parse_front_matter(&mut event, line).map_err(|e| format!("第 {} 行: {}", i, e))?;
+                    }
+                }
+            }
+            doc.events.push(event);
+        } else if let Some(event) = doc.events.last_mut() {
+            event.links.extend(parse_links(line));
+        } else if let Some(item) = line.strip_prefix("- ") {
+            parse_list_item(&mut doc, item).map_err(|e| format!("第 {} 行: {}", i, e))?;
+        }
+    }
+    Ok(doc)
+}
+
+/// 未写 id 的事件按标题 (重复标题带序号后缀) 生成 id，与特征 id 的哈希空间错开
+fn heading_event_id(key: &str) -> i64 {
+    let mut s = XxHash64::with_seed(0);
+    ("event", key).hash(&mut s);
+    (s.finish() as i64).abs()
+}
+
+/// 边强度的文本形式: f32 的最短往返表示，导入后逐位相同
+fn format_weight(weight: f32) -> String {
+    format!("{}", weight)
+}
+
+impl AdvancedEngine {
+    /// 从 Markdown 导入事件、特征与边 (格式见模块文档)，返回导入的事件数
+    /// 已存在的事件 id 按 add_or_replace_event 覆盖内容，边按 upsert_link 合并，重复导入同一文档结果不变。
+    /// 导入后重建时序脊梁；新内容需 compile 后才能被文本检索命中。
+    pub fn import_markdown(&mut self, text: &str) -> Result<usize, String> {
+        let doc = parse_markdown(text)?;
+        for keyword in &doc.features {
+            self.get_or_create_feature(keyword);
+        }
+        let mut heading_counts: AHashMap<&str, usize> = AHashMap::new();
+        for event in &doc.events {
+            let id = event.id.unwrap_or_else(|| {
+                let n = heading_counts.entry(&event.summary).or_insert(0);
+                *n += 1;
+                if *n == 1 { heading_event_id(&event.summary) } else { heading_event_id(&format!("{} ({})", event.summary, n)) }
+            });
+            self.add_or_replace_event(id, &event.summary, event.timestamp, event.emotion, 0).map_err(|e| e.to_string())?;
+            for (keyword, weight) in &event.links {
+                let feature = self.get_or_create_feature(keyword);
+                if feature >= 0 {
+                    self.upsert_link(feature as u64, id as u64, FEATURE_LINK_LABEL, *weight);
+                }
+            }
+        }
+        for (src, label, weight, tgt) in &doc.edges {
+            match (self.resolve_node_ref(src), self.resolve_node_ref(tgt)) {
+                (Some(src), Some(tgt)) => { self.upsert_link(src, tgt, label, *weight); }
+                _ => println!("⚠️ [Markdown] 跳过边 {:?} -> {:?}: 端点不存在或为停用词", src, tgt),
+            }
+        }
+        if !doc.events.is_empty() {
+            self.build_temporal_backbone();
+        }
+        Ok(doc.events.len())
+    }
+
+    fn resolve_node_ref(&mut self, node: &NodeRef) -> Option<u64> {
+        match node {
+            NodeRef::Feature(keyword) => u64::try_from(self.get_or_create_feature(keyword)).ok(),
+            NodeRef::Event(id) => (self.node_type(*id).as_deref() == Some("event")).then_some(*id as u64),
+        }
+    }
+
+    /// 把特征、事件与它们之间的边导出为 Markdown (chunk 等派生节点不导出)
+    /// 事件按 (时间戳, id) 排序，边与链接按文本排序，同一张图的导出结果恒定。
+    pub fn export_markdown(&self) -> String {
+        let mut keywords: AHashMap<u64, String> = AHashMap::new();
+        let mut events: Vec<(u64, u64, String, u8)> = Vec::new();
+        for id in self.tdb.all_node_ids() {
+            let Some(payload) = self.tdb.get_payload(id) else { continue; };
+            let content = payload.get("content").and_then(|v| v.as_str()).unwrap_or_default();
+            match payload.get("type").and_then(|v| v.as_str()) {
+                Some("feature") => { keywords.insert(id, content.to_string()); }
+                Some("event") => {
+                    let timestamp = payload.get("timestamp").and_then(|v| v.as_u64()).unwrap_or(0);
+                    let emotion = payload.get("emotions").and_then(|v| v.as_u64()).unwrap_or(0) as u8;
+                    // 标题只能是单行
+                    let summary = content.split_whitespace().collect::<Vec<_>>().join(" ");
+                    events.push((timestamp, id, summary, emotion));
+                }
+                _ => {}
+            }
+        }
+        events.sort_by_key(|&(timestamp, id, _, _)| (timestamp, id));
+        let event_ids: AHashSet<u64> = events.iter().map(|&(_, id, _, _)| id).collect();
+        let node_ref = |id: u64| match keywords.get(&id) {
+            Some(keyword) => Some(format!("[[{}]]", keyword)),
+            None => event_ids.contains(&id).then(|| format!("#{}", id)),
+        };
+
+        let mut relations = Vec::new();
+        let mut linked: AHashSet<u64> = AHashSet::new();
+        let mut event_links: AHashMap<u64, Vec<String>> = AHashMap::new();
+        for &src in keywords.keys().chain(&event_ids) {
+            let Some(src_ref) = node_ref(src) else { continue; };
+            for edge in self.tdb.get_edges(src) {
+                let Some(tgt_ref) = node_ref(edge.target_id) else { continue; };
+                linked.extend([src, edge.target_id]);
+                if keywords.contains_key(&src) && event_ids.contains(&edge.target_id) && edge.label == FEATURE_LINK_LABEL {
+                    let link = if edge.weight == 1.0 { src_ref.clone() } else { format!("[[{}|{}]]", keywords[&src], format_weight(edge.weight)) };
+                    event_links.entry(edge.target_id).or_default().push(link);
+                } else {
+                    relations.push(format!("- {} --{} {}--> {}", src_ref, edge.label, format_weight(edge.weight), tgt_ref));
+                }
+            }
+        }
+        let mut isolated: Vec<String> = keywords.iter()
+            .filter(|(id, _)| !linked.contains(id))
+            .map(|(_, keyword)| format!("- [[{}]]", keyword))
+            .collect();
+        isolated.sort();
+        relations.sort();
+
+        let mut out = String::new();
+        for line in isolated.iter().chain(&relations) {
+            let _ = writeln!(out, "{}", line);
+        }
+        for (timestamp, id, summary, emotion) in &events {
+            if !out.is_empty() { out.push('\n'); }
+            let date = if timestamp % 86400 == 0 { dates::format_date(*timestamp) } else { timestamp.to_string() };
+            let _ = writeln!(out, "# {}\n---\nid: {}\ndate: {}", summary, id, date);
+            // 与自动提取结果不同的情感 (add_event 时显式传入) 才需要记录
+            if *emotion != self.emotion_keywords.extract(summary) {
+                let _ = writeln!(out, "emotion: {}", emotion);
+            }
+            out.push_str("---\n");
+            if let Some(links) = event_links.get_mut(id) {
+                links.sort();
+                let _ = writeln!(out, "{}", links.join(" "));
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::simhash::SimHash;
+
+    #[test]
+    fn test_markdown_round_trip() {
+        let mut engine = AdvancedEngine::open_temp("markdown_export");
+        engine.add_event(1, "2024年3月1日 和 Pero 去上海看海", 0, 0, 0);
+        engine.add_event(2, "在家写 Rust", 1_700_000_123, 0, 0);
+        engine.add_event(3, "在家写 Rust", 1_700_000_500, SimHash::EMOTION_JOY, 0);
+        for (keyword, event, weight) in [("pero", 1, 1.0), ("上海", 1, 0.8), ("rust", 2, 1.0), ("rust", 3, 0.35)] {
+            let feature = engine.get_or_create_feature(keyword);
+            engine.add_edge(feature, event, weight);
+        }
+        engine.maintain_ontology("Pero", "佩罗", "equality", 1.0);
+        engine.maintain_ontology("女孩", "Pero", "representation", 0.6);
+        engine.maintain_ontology("rust", "c++", "inhibition", 0.3);
+        engine.get_or_create_feature("孤立的概念");
+        engine.add_edge(1, 2, 0.4);
+
+        let exported = engine.export_markdown();
+        let mut copy = AdvancedEngine::open_temp("markdown_import");
+        assert_eq!(copy.import_markdown(&exported), Ok(3), "{}", exported);
+        assert_eq!(copy.export_markdown(), exported);
+        for id in [1u64, 2, 3] {
+            let (a, b) = (engine.tdb.get_payload(id).unwrap(), copy.tdb.get_payload(id).unwrap());
+            for key in ["content", "timestamp", "emotions", "fingerprint"] {
+                assert_eq!(a[key], b[key], "{} {}", id, key);
+            }
+        }
+        assert_eq!(copy.tdb.get_edges(1), engine.tdb.get_edges(1));
+        assert!(copy.feature_id("孤立的概念").is_some());
+
+        // 重复导入不产生重复节点或边
+        assert_eq!(copy.import_markdown(&exported), Ok(3));
+        assert_eq!(copy.export_markdown(), exported);
+    }
+
+    #[test]
+    fn test_import_handwritten_markdown() {
+        let mut engine = AdvancedEngine::open_temp("markdown_handwritten");
+        let doc = "我的笔记\n- [[海边]] --representation 0.5--> [[夏天]]\n\n\
+                   # 海边散步\n---\ndate: 2024-03-01\n---\n今天 [[海边]] 风很大，捡到了 [[贝壳|0.7]]\n\n\
+                   ## 海边散步\n又去了一次 [[海边]]\n";
+        assert_eq!(engine.import_markdown(doc), Ok(2));
+        let events: Vec<u64> = engine.tdb.all_node_ids().into_iter()
+            .filter(|&id| engine.node_type(id as i64).as_deref() == Some("event"))
+            .collect();
+        assert_eq!(events.len(), 2);
+        let first = heading_event_id("海边散步") as u64;
+        assert!(events.contains(&first) && events.contains(&(heading_event_id("海边散步 (2)") as u64)));
+        assert_eq!(engine.tdb.get_payload(first).unwrap()["timestamp"], 1709251200);
+
+        let shell = engine.feature_id("贝壳").unwrap() as u64;
+        assert!(engine.tdb.get_edges(shell).iter().any(|e| e.target_id == first && e.weight == 0.7));
+        let sea = engine.feature_id("海边").unwrap() as u64;
+        assert_eq!(engine.tdb.get_edges(sea).len(), 3);
+
+        assert!(engine.import_markdown("# 未闭合\n---\ndate: 2024-03-01\n").is_err());
+        assert!(engine.import_markdown("# 坏日期\n---\ndate: 明天\n---\n").is_err());
+        assert!(engine.import_markdown("- [[a]] --equality 很强--> [[b]]\n").is_err());
+    }
+}
@@ -0,0 +1,250 @@
+//! 合并两个引擎 (如分会话 / 分数据源构建的图谱)
+//! 特征按归一化关键词对齐 (keyword_to_node)，事件按 id 对齐，边按 upsert_link 合并 (同标签取较大强度)。
+//! 节点的向量与 payload 直接复制，不重新推理嵌入；合并后重建时序脊梁并 compile。
+
+use ahash::AHashMap;
+use crate::core::engine::{AdvancedEngine, StorageError};
+
+/// 两侧存在同 id 事件时保留哪一个
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MergePolicy {
+    /// 保留时间戳较新的一方，相同时保留本引擎的
+    #[default]
+    KeepNewer,
+    KeepSelf,
+    KeepOther,
+}
+
+/// merge 的结果统计
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MergeStats {
+    /// 本引擎原先没有、从对方复制过来的事件
+    pub events_added: usize,
+    /// 同 id 冲突时被对方版本覆盖的事件
+    pub events_replaced: usize,
+    /// 本引擎原先没有对应关键词、新建的特征
+    pub features_added: usize,
+    /// 写入 (新建或合并) 的边数
+    pub edges_merged: usize,
+}
+
+impl AdvancedEngine {
+    /// 把 other 的特征、事件与边并入本引擎，同 id 事件按时间戳较新者保留 (见 merge_with)
+    pub fn merge(&mut self, other: AdvancedEngine) -> Result<MergeStats, StorageError> {
+        self.merge_with(other, MergePolicy::KeepNewer)
+    }
+
+    /// 同 merge，同 id 事件按 policy 取舍
+    /// 对方的特征按关键词 (含别名) 映射到本引擎已有的特征，同一关键词在两侧 id 不同时以本引擎为准；
+    /// 对方事件 id 已被本引擎的特征等非事件节点占用时跳过该事件及其边。chunk 节点随父事件复制。
+    pub fn merge_with(&mut self, other: AdvancedEngine, policy: MergePolicy) -> Result<MergeStats, StorageError> {
+        if other.vector_dim() != self.vector_dim() {
+            return Err(StorageError::DimensionMismatch { expected: self.vector_dim(), found: other.vector_dim() });
+        }
+        let mut stats = MergeStats::default();
+        // 对方节点 id -> 本引擎节点 id
+        let mut id_map: AHashMap<u64, u64> = AHashMap::new();
+
+        let mut other_features: Vec<(i64, &[String])> = other.node_to_keywords.iter().map(|(&id, keywords)| (id, keywords.as_slice())).collect();
+        other_features.sort_unstable_by_key(|&(id, _)| id);
+        for (other_id, keywords) in other_features {
+            let Some(payload) = other.tdb.get_payload(other_id as u64) else { continue; };
+            let id = match keywords.iter().find_map(|k| self.feature_id(k)) {
+                Some(id) => id,
+                None if self.node_type(other_id).is_none() => {
+                    let display = payload.get("display").or_else(|| payload.get("content")).and_then(|v| v.as_str()).unwrap_or_default();
+                    self.add_feature(other_id, display);
+                    if self.node_type(other_id).is_none() { continue; }
+                    stats.features_added += 1;
+                    other_id
+                }
+                None => {
+                    println!("⚠️ [Merge] 跳过特征 {:?}: id {} 已被其他节点占用", keywords, other_id);
+                    continue;
+                }
+            };
+            for keyword in keywords {
+                if self.feature_id(keyword).is_none() {
+                    self.tdb.index_keyword(id as u64, keyword).ok();
+                    self.keyword_to_node.insert(self.feature_key(keyword), id);
+                }
+            }
+            id_map.insert(other_id as u64, id as u64);
+        }
+
+        let mut taken = Vec::new();
+        for other_id in other.tdb.all_node_ids() {
+            let Some(node) = other.tdb.get(other_id) else { continue; };
+            if node.payload.get("type").and_then(|v| v.as_str()) != Some("event") { continue; }
+            let mut payload = node.payload;
+            if let Some(fields) = payload.as_object_mut() {
+                // 时序指针在合并后重建
+                fields.remove("prev_event");
+                fields.remove("next_event");
+            }
+            let content = payload.get("content").and_then(|v| v.as_str()).unwrap_or_default().to_string();
+            match self.node_type(other_id as i64).as_deref() {
+                None => {
+                    self.tdb.insert_with_id(other_id, &node.vector, payload).map_err(|e| StorageError::Storage(e.to_string()))?;
+                    stats.events_added += 1;
+                }
+                Some("event") => {
+                    let timestamp = |p: &serde_json::Value| p.get("timestamp").and_then(|v| v.as_u64()).unwrap_or(0);
+                    let replace = match policy {
+                        MergePolicy::KeepNewer => self.tdb.get_payload(other_id).is_some_and(|mine| timestamp(&payload) > timestamp(&mine)),
+                        MergePolicy::KeepSelf => false,
+                        MergePolicy::KeepOther => true,
+                    };
+                    id_map.insert(other_id, other_id);
+                    if !replace { continue; }
+                    self.tdb.update_vector(other_id, &node.vector).map_err(|e| StorageError::Storage(e.to_string()))?;
+                    self.tdb.update_payload(other_id, payload).map_err(|e| StorageError::Storage(e.to_string()))?;
+                    self.remove_event_chunks(other_id as i64);
+                    stats.events_replaced += 1;
+                }
+                Some(node_type) => {
+                    println!("⚠️ [Merge] 跳过事件 {}: id 已被 {} 节点占用", other_id, node_type);
+                    continue;
+                }
+            }
+            self.tdb.index_text(other_id, &content).ok();
+            id_map.insert(other_id, other_id);
+            taken.push(other_id);
+        }
+
+        if !taken.is_empty() {
+            taken.sort_unstable();
+            for chunk_id in other.tdb.all_node_ids() {
+                let Some(node) = other.tdb.get(chunk_id) else { continue; };
+                if node.payload.get("type").and_then(|v| v.as_str()) != Some("chunk") { continue; }
+                let Some(parent) = node.payload.get("parent").and_then(|v| v.as_u64()) else { continue; };
+                if taken.binary_search(&parent).is_ok() && self.node_type(chunk_id as i64).is_none() {
+                    let _ = self.tdb.insert_with_id(chunk_id, &node.vector, node.payload);
+                }
+            }
+        }
+
+        let mut sources: Vec<(u64, u64)> = id_map.iter().map(|(&from, &to)| (from, to)).collect();
+        sources.sort_unstable();
+        for (other_src, src) in sources {
+            for edge in other.tdb.get_edges(other_src) {
+                let Some(&tgt) = id_map.get(&edge.target_id) else { continue; };
+                if src != tgt {
+                    self.upsert_link(src, tgt, &edge.label, edge.weight);
+                    stats.edges_merged += 1;
+                }
+            }
+        }
+
+        if !taken.is_empty() {
+            self.build_temporal_backbone();
+        }
+        self.compile();
+        println!("🔗 [Merge] 新增事件 {}，覆盖事件 {}，新增特征 {}，合并边 {}",
+            stats.events_added, stats.events_replaced, stats.features_added, stats.edges_merged);
+        Ok(stats)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::dataset::{get_social_domain_data, get_tech_domain_data, RawEdge, RawEvent};
+
+    /// (事件 id, 内容, 时间戳)、特征关键词、(源, 目标, 标签, 强度) 各自排序后的快照
+    #[allow(clippy::type_complexity)]
+    fn snapshot(engine: &AdvancedEngine) -> (Vec<(u64, String, u64)>, Vec<String>, Vec<(u64, u64, String, u32)>) {
+        let mut events = Vec::new();
+        let mut edges = Vec::new();
+        for id in engine.tdb.all_node_ids() {
+            let payload = engine.tdb.get_payload(id).unwrap();
+            if payload["type"] == "event" {
+                events.push((id, payload["content"].as_str().unwrap().to_string(), payload["timestamp"].as_u64().unwrap()));
+            }
+            edges.extend(engine.tdb.get_edges(id).into_iter().map(|e| (id, e.target_id, e.label, e.weight.to_bits())));
+        }
+        events.sort();
+        edges.sort();
+        let mut keywords: Vec<String> = engine.feature_keywords().map(str::to_string).collect();
+        keywords.sort();
+        (events, keywords, edges)
+    }
+
+    #[test]
+    fn test_merge_domains_matches_combined_load() {
+        let (tech_events, mut tech_edges) = get_tech_domain_data();
+        let (social_events, mut social_edges) = get_social_domain_data();
+        // 跨领域的边在单独加载时端点缺失、不会写入，只比较领域内部的边
+        let within = |events: &[RawEvent], edges: &mut Vec<RawEdge>| {
+            edges.retain(|e| [e.src, e.tgt].iter().all(|id| events.iter().any(|ev| ev.id == *id)));
+        };
+        within(&tech_events, &mut tech_edges);
+        within(&social_events, &mut social_edges);
+
+        let mut combined = AdvancedEngine::open_temp("merge_combined");
+        combined.load_raw_events(&tech_events);
+        combined.load_raw_events(&social_events);
+        combined.load_raw_edges(&tech_edges);
+        combined.load_raw_edges(&social_edges);
+        combined.build_temporal_backbone();
+        combined.compile();
+
+        let mut tech = AdvancedEngine::open_temp("merge_tech");
+        tech.load_raw_events(&tech_events);
+        tech.load_raw_edges(&tech_edges);
+        tech.compile();
+        let mut social = AdvancedEngine::open_temp("merge_social");
+        social.load_raw_events(&social_events);
+        social.load_raw_edges(&social_edges);
+        social.compile();
+
+        let stats = tech.merge(social).unwrap();
+        assert_eq!(stats.events_added, social_events.len());
+        assert_eq!(stats.events_replaced, 0);
+        assert_eq!(snapshot(&tech), snapshot(&combined));
+        assert!(!tech.is_dirty());
+        let first = tech.events_in_range(0, u64::MAX)[0];
+        assert_eq!(tech.events_after(first, usize::MAX).len(), tech_events.len() + social_events.len() - 1);
+    }
+
+    #[test]
+    fn test_merge_resolves_id_and_keyword_conflicts() {
+        let mut mine = AdvancedEngine::open_temp("merge_conflict_mine");
+        mine.add_event(1, "旧的记录", 100, 0, 0);
+        mine.add_event(3, "只在本地的记录", 300, 0, 0);
+        mine.add_feature(7, "pero");
+        mine.add_edge(7, 1, 0.4);
+        mine.compile();
+
+        let mut theirs = AdvancedEngine::open_temp("merge_conflict_theirs");
+        theirs.add_event(1, "新的记录", 200, 0, 0);
+        theirs.add_event(3, "更早的记录", 50, 0, 0);
+        theirs.add_event(2, "对方的记录", 150, 0, 0);
+        // 同一关键词在对方是另一个 id
+        theirs.add_feature(9, "Pero");
+        theirs.add_edge(9, 1, 0.8);
+        theirs.add_edge(9, 2, 0.5);
+        theirs.compile();
+
+        let stats = mine.merge(theirs).unwrap();
+        assert_eq!(stats, MergeStats { events_added: 1, events_replaced: 1, features_added: 0, edges_merged: 2 });
+        let content = |engine: &AdvancedEngine, id: u64| engine.tdb.get_payload(id).unwrap()["content"].as_str().unwrap().to_string();
+        assert_eq!(content(&mine, 1), "新的记录");
+        assert_eq!(content(&mine, 3), "只在本地的记录");
+        assert_eq!(mine.feature_id("PERO"), Some(7));
+        assert_eq!(mine.node_type(9), None);
+        let edges = mine.tdb.get_edges(7);
+        assert_eq!(edges.iter().find(|e| e.target_id == 1).map(|e| e.weight), Some(0.8));
+        assert_eq!(edges.iter().find(|e| e.target_id == 2).map(|e| e.weight), Some(0.5));
+        // 事件 1 采用对方较新的时间戳，时序脊梁按合并后的时间重排
+        assert_eq!(mine.events_in_range(0, u64::MAX), vec![2, 1, 3]);
+        assert_eq!(mine.events_after(2, 5), vec![1, 3]);
+
+        let mut keep_self = AdvancedEngine::open_temp("merge_keep_self");
+        keep_self.add_event(1, "本地版本", 100, 0, 0);
+        let mut newer = AdvancedEngine::open_temp("merge_keep_self_other");
+        newer.add_event(1, "对方版本", 200, 0, 0);
+        assert_eq!(keep_self.merge_with(newer, MergePolicy::KeepSelf).unwrap().events_replaced, 0);
+        assert_eq!(content(&keep_self, 1), "本地版本");
+    }
+}
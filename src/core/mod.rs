@@ -1,6 +1,18 @@
 pub mod types;
+pub mod config;
 pub mod simhash;
 pub mod stopwords;
+pub mod stemmer;
+pub mod dates;
 pub mod engine;
 pub mod retrieval;
+pub mod query;
+pub mod integrity;
 pub mod ontology;
+pub mod path;
+pub mod community;
+pub mod persist;
+pub mod markdown;
+pub mod export;
+pub mod merge;
+pub mod shared;
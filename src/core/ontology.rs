@@ -1,13 +1,50 @@
 use std::hash::{Hash, Hasher};
+use ahash::AHashMap;
 use twox_hash::XxHash64;
 use crate::core::engine::AdvancedEngine;
-use crate::core::stopwords;
+use crate::core::simhash::SimHashRegion;
+
+/// 本体边强度低于该阈值时视为已失效, 直接剪除
+pub const ONTOLOGY_PRUNE_THRESHOLD: f32 = 0.1;
+
+/// ontology_neighbors 返回的关系编码
+pub const ONTOLOGY_REPRESENTATION: u8 = 0;
+pub const ONTOLOGY_EQUALITY: u8 = 1;
+pub const ONTOLOGY_INHIBITION: u8 = 2;
+
+/// 本体边标签对应的关系编码，记忆层标签为 None
+pub fn ontology_edge_code(label: &str) -> Option<u8> {
+    match label {
+        "representation" => Some(ONTOLOGY_REPRESENTATION),
+        "equality" => Some(ONTOLOGY_EQUALITY),
+        "inhibition" => Some(ONTOLOGY_INHIBITION),
+        _ => None,
+    }
+}
+
+/// maintain_ontology 对图谱的实际改动
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct OntologyChange {
+    /// 新建了 source -> target 边 (false 表示强化已有边)
+    pub created: bool,
+    pub old_strength: Option<f32>,
+    pub new_strength: f32,
+    /// 双向关系 (equality / inhibition) 新建了反向边
+    pub reverse_created: bool,
+    /// 源或目标是停用词，未做任何改动
+    pub rejected: bool,
+    /// weaken_ontology 削弱后低于剪枝阈值，边已移除 (此时 new_strength 为 0)
+    pub pruned: bool,
+}
 
 impl AdvancedEngine {
-    pub fn maintain_ontology(&mut self, source: &str, target: &str, relation_type: &str, strength: f32) {
+    pub fn maintain_ontology(&mut self, source: &str, target: &str, relation_type: &str, strength: f32) -> OntologyChange {
         println!("🤖 [LLM Maintenance] 发现新关联: {} -> {} (type: {}, strength: {})", source, target, relation_type, strength);
         let src_id = self.get_or_create_feature(source);
         let tgt_id = self.get_or_create_feature(target);
+        if src_id < 0 || tgt_id < 0 {
+            return OntologyChange { rejected: true, ..Default::default() };
+        }
         let edge_type = match relation_type.to_lowercase().as_str() {
             "equality" | "equal" => "equality",
             "inhibition" | "conflict" => "inhibition",
@@ -15,69 +52,462 @@ impl AdvancedEngine {
         };
         // Use Triviumdb exact graph relations. TriviumDB supports PPR/teleporting 
         // without edge weights having to be purely u16!
-        let _ = self.tdb.link(src_id as u64, tgt_id as u64, edge_type, strength);
+        let (old_strength, new_strength) = self.upsert_link(src_id as u64, tgt_id as u64, edge_type, strength);
+        let mut reverse_created = false;
         if edge_type == "equality" || edge_type == "inhibition" {
-            let _ = self.tdb.link(tgt_id as u64, src_id as u64, edge_type, strength);
+            reverse_created = self.upsert_link(tgt_id as u64, src_id as u64, edge_type, strength).0.is_none();
         }
+        OntologyChange { created: old_strength.is_none(), old_strength, new_strength, reverse_created, rejected: false, pruned: false }
     }
 
-    #[allow(dead_code)]
-    pub fn apply_global_decay_and_pruning(&mut self, _decay_rate: f32, _threshold: u16) -> usize {
-        // Since we delegated topology to TriviumDB, TriviumDB should handle edge decay.
-        // For now, we skip manual edge decay from high level Rust.
-        println!("[PEDSA Memory] Simulated Pruning executed (Delegated to TriviumDB Compaction).");
-        0
+    /// 显式削弱一条本体关联: source -> target 的本体边 (equality / inhibition / representation) 强度乘以 factor (< 1.0)，
+    /// 低于剪枝阈值则直接移除。记忆边不受影响；反向只削弱与正向同标签的双向关系 (equality / inhibition)。
+    /// 返回正向最强一条本体边的变化，关键词未知或两者间没有本体边时为 None。
+    pub fn weaken_ontology(&mut self, source: &str, target: &str, factor: f32) -> Option<OntologyChange> {
+        let src_id = self.feature_id(source)? as u64;
+        let tgt_id = self.feature_id(target)? as u64;
+        let factor = factor.clamp(0.0, 1.0);
+        let forward: Vec<_> = self.tdb.get_edges(src_id).into_iter().filter(|e| e.target_id == tgt_id).collect();
+        let old_strength = forward.iter().filter(|e| ontology_edge_code(&e.label).is_some()).map(|e| e.weight).reduce(f32::max)?;
+        let symmetric: Vec<String> = forward.iter()
+            .filter(|e| e.label == "equality" || e.label == "inhibition")
+            .map(|e| e.label.clone())
+            .collect();
+
+        for (from, to, reverse) in [(src_id, tgt_id, false), (tgt_id, src_id, true)] {
+            let edges = if reverse { self.tdb.get_edges(from).into_iter().filter(|e| e.target_id == to).collect() } else { forward.clone() };
+            let affected = |label: &str| if reverse { symmetric.iter().any(|l| l == label) } else { ontology_edge_code(label).is_some() };
+            if !edges.iter().any(|e| affected(&e.label)) { continue; }
+            // unlink 会移除 from -> to 的全部边，未受影响的边原样写回
+            self.unlink_raw(from, to);
+            for edge in edges {
+                let weight = if affected(&edge.label) { edge.weight * factor } else { edge.weight };
+                if weight >= ONTOLOGY_PRUNE_THRESHOLD || !affected(&edge.label) {
+                    self.link_raw(from, to, &edge.label, weight);
+                }
+            }
+        }
+        let weakened = old_strength * factor;
+        let pruned = weakened < ONTOLOGY_PRUNE_THRESHOLD;
+        if pruned {
+            println!("✂️ [Weaken] 关联 '{}' -> '{}' 强度过低, 已剪除", source, target);
+        }
+        Some(OntologyChange { old_strength: Some(old_strength), new_strength: if pruned { 0.0 } else { weakened }, pruned, ..Default::default() })
+    }
+
+    /// 全局 LTD (长时程抑制): 所有边的强度乘以 (1 - decay_rate)，
+    /// 衰减后按 u16 刻度 (强度 × 65535) 低于 threshold 的边被剪除。返回剪除的边数。
+    pub fn apply_global_decay_and_pruning(&mut self, decay_rate: f32, threshold: u16) -> usize {
+        let factor = 1.0 - decay_rate.clamp(0.0, 1.0);
+        let mut pruned = 0;
+        for id in self.tdb.all_node_ids() {
+            let edges = self.tdb.get_edges(id);
+            if edges.is_empty() { continue; }
+            // unlink 会移除 id -> target 的全部边，按目标分组后重写幸存的边
+            let mut targets: Vec<u64> = edges.iter().map(|e| e.target_id).collect();
+            targets.sort_unstable();
+            targets.dedup();
+            for target in targets {
+                self.unlink_raw(id, target);
+            }
+            for edge in edges {
+                let weight = edge.weight * factor;
+                if weight * 65535.0 < threshold as f32 {
+                    pruned += 1;
+                } else {
+                    self.link_raw(id, edge.target_id, &edge.label, weight);
+                }
+            }
+        }
+        println!("✂️ [LTD] 全局衰减 {:.2}，剪除 {} 条弱边", decay_rate, pruned);
+        pruned
+    }
+
+    /// 请求后台排期器尽快安排一次维护 (未设置 async_task 时忽略)
+    pub fn schedule_maintenance(&self, context: &str) {
+        if let Some(task) = &self.async_task {
+            task.schedule_maintenance(context);
+        }
+    }
+
+    /// 执行 async_task 已排期的全部维护请求，返回剪除的边数；由调用方在空闲时调用
+    pub fn run_pending_maintenance(&mut self) -> usize {
+        let Some(task) = &self.async_task else { return 0; };
+        let requests = task.drain_requests();
+        requests.iter().map(|r| self.apply_global_decay_and_pruning(r.decay_rate, r.threshold)).sum()
+    }
+
+    /// 合并近似重复的特征: 语义区 SimHash 汉明距离 <= hamming_threshold 的特征归并到 id 最小的代表特征
+    /// 重复特征的出入边改挂到代表特征 (同标签取较大强度)，节点删除，原关键词记入代表的 aliases，
+    /// keyword_to_node 中的原关键词改指向代表。两两比较，O(特征数²)。返回被合并掉的特征数。
+    pub fn merge_similar_features(&mut self, hamming_threshold: u32) -> usize {
+        let mut features: Vec<(u64, u64)> = self.tdb.all_node_ids().into_iter().filter_map(|id| {
+            let payload = self.tdb.get_payload(id)?;
+            if payload.get("type").and_then(|v| v.as_str()) != Some("feature") { return None; }
+            Some((id, payload.get("fingerprint")?.as_u64()?))
+        }).collect();
+        features.sort_unstable();
+
+        let semantic_mask = self.config.simhash_layout.mask(SimHashRegion::Semantic);
+        let mut canonical_of: AHashMap<u64, u64> = AHashMap::new();
+        for (i, &(canon, fp)) in features.iter().enumerate() {
+            if canonical_of.contains_key(&canon) { continue; }
+            for &(other, other_fp) in &features[i + 1..] {
+                if !canonical_of.contains_key(&other) && ((fp ^ other_fp) & semantic_mask).count_ones() <= hamming_threshold {
+                    canonical_of.insert(other, canon);
+                }
+            }
+        }
+        if canonical_of.is_empty() { return 0; }
+
+        let incoming: Vec<(u64, triviumdb::node::Edge)> = self.tdb.all_node_ids().into_iter()
+            .filter(|src| !canonical_of.contains_key(src))
+            .flat_map(|src| self.tdb.get_edges(src).into_iter().map(move |e| (src, e)))
+            .filter(|(_, e)| canonical_of.contains_key(&e.target_id))
+            .collect();
+        let mut duplicates: Vec<(u64, u64)> = canonical_of.iter().map(|(&dup, &canon)| (dup, canon)).collect();
+        duplicates.sort_unstable();
+
+        for &(dup, canon) in &duplicates {
+            for edge in self.tdb.get_edges(dup) {
+                let target = canonical_of.get(&edge.target_id).copied().unwrap_or(edge.target_id);
+                if target != canon {
+                    self.upsert_link(canon, target, &edge.label, edge.weight);
+                }
+            }
+        }
+        for (src, edge) in incoming {
+            let canon = canonical_of[&edge.target_id];
+            if src != canon {
+                self.unlink_raw(src, edge.target_id);
+                self.upsert_link(src, canon, &edge.label, edge.weight);
+            }
+        }
+
+        for &(dup, canon) in &duplicates {
+            let Some(keyword) = self.tdb.get_payload(dup)
+                .and_then(|p| p.get("content").and_then(|v| v.as_str()).map(str::to_string)) else { continue; };
+            self.delete_raw_node(dup);
+            if let Some(mut payload) = self.tdb.get_payload(canon) {
+                let mut aliases: Vec<serde_json::Value> = payload.get("aliases").and_then(|v| v.as_array()).cloned().unwrap_or_default();
+                aliases.push(serde_json::json!(keyword));
+                payload["aliases"] = serde_json::json!(aliases);
+                let _ = self.tdb.update_payload(canon, payload);
+            }
+        }
+        for id in self.keyword_to_node.values_mut() {
+            if let Some(&canon) = canonical_of.get(&(*id as u64)) {
+                *id = canon as i64;
+            }
+        }
+        self.rebuild_node_keywords();
+        println!("🧬 [Merge] 已合并 {} 个近似重复特征", duplicates.len());
+        duplicates.len()
+    }
+
+    /// 遗忘: 删除活力低于 threshold 的事件，返回被遗忘的事件 id
+    /// 活力 = exp(-Δt / (τ · (1 + 访问次数)))，Δt 为距最近一次访问 (无访问则为事件时间) 的时长，
+    /// 每次访问都会延长记忆的稳定期 (间隔重复)。τ 取 config.resonance.decay_tau，与 retrieve 的时间衰减一致。
+    pub fn forget(&mut self, threshold: f32, now: u64) -> Vec<i64> {
+        let tau = self.config.resonance.decay_tau;
+        let mut forgotten: Vec<i64> = self.tdb.all_node_ids().into_iter().filter_map(|id| {
+            let payload = self.tdb.get_payload(id)?;
+            if payload.get("type").and_then(|v| v.as_str()) != Some("event") { return None; }
+            let timestamp = payload.get("timestamp").and_then(|v| v.as_u64()).unwrap_or(0);
+            let last_access = payload.get("last_access").and_then(|v| v.as_u64()).unwrap_or(0);
+            let access_count = payload.get("access_count").and_then(|v| v.as_u64()).unwrap_or(0);
+            let delta_t = now.saturating_sub(timestamp.max(last_access)) as f32;
+            let vitality = (-delta_t / (tau * (1.0 + access_count as f32))).exp();
+            (vitality < threshold).then_some(id as i64)
+        }).collect();
+        forgotten.sort();
+        for &id in &forgotten {
+            self.remove_event(id);
+        }
+        if !forgotten.is_empty() {
+            println!("🍂 [Forget] 已遗忘 {} 条低活力记忆", forgotten.len());
+        }
+        forgotten
     }
 
     pub fn get_or_create_feature(&mut self, word: &str) -> i64 {
-        let word_lower = word.to_lowercase();
-        if stopwords::is_stopword(&word_lower) { return -1; }
-        if let Some(&id) = self.keyword_to_node.get(&word_lower) {
+        if self.stopwords.is_stopword(word) { return -1; }
+        let key = self.feature_key(word);
+        if let Some(&id) = self.keyword_to_node.get(&key) {
             id
         } else {
             let mut s = XxHash64::with_seed(0);
-            word_lower.hash(&mut s);
+            key.hash(&mut s);
             let id = (s.finish() as i64).abs();
-            self.add_feature(id, &word_lower);
+            self.add_feature(id, word);
             id
         }
     }
 
+    /// 关键词的一跳本体邻居: (目标关键词, 强度 0-1, 关系编码 ONTOLOGY_*)，按强度降序，同强度按关键词排序
+    /// 只列本体层的边 (equality / inhibition / representation)，不含特征到事件的记忆边；未知关键词返回空。
+    pub fn ontology_neighbors(&self, keyword: &str) -> Vec<(String, f32, u8)> {
+        let Some(src_id) = self.feature_id(keyword) else { return Vec::new(); };
+        let mut neighbors: Vec<(String, f32, u8)> = self.tdb.get_edges(src_id as u64).into_iter()
+            .filter_map(|edge| {
+                let code = ontology_edge_code(&edge.label)?;
+                let payload = self.tdb.get_payload(edge.target_id)?;
+                let content = payload.get("content")?.as_str()?.to_string();
+                Some((content, edge.weight, code))
+            })
+            .collect();
+        neighbors.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)).then(a.2.cmp(&b.2)));
+        neighbors
+    }
+
+    /// 把关键词的本体邻居 (见 ontology_neighbors) 格式化为提交给仲裁 LLM 的上下文，每行一条关联
     #[allow(dead_code)]
     pub fn trigger_arbitration(&self, source: &str) -> Option<String> {
-        let src_id = self.keyword_to_node.get(&source.to_lowercase())?;
-        let mut context_lines = Vec::new();
-        // Since nodes is gone, we fetch via get_edges
-        for edge in self.tdb.get_edges(*src_id as u64) {
-            if let Some(payload) = self.tdb.get_payload(edge.target_id) {
-                if let Some(content) = payload.get("content").and_then(|v| v.as_str()) {
-                    context_lines.push(format!("{} -> {} (Strength: {:.2})", source, content, edge.weight));
-                }
-            }
-        }
+        let context_lines: Vec<String> = self.ontology_neighbors(source).into_iter()
+            .map(|(content, strength, _)| format!("{} -> {} (Strength: {:.2})", source, content, strength))
+            .collect();
         if context_lines.is_empty() { return None; }
-        Some(context_lines.join("
-"))
+        Some(context_lines.join("\n"))
     }
 
     #[allow(dead_code)]
     pub fn apply_arbitration(&mut self, source: &str, delete_targets: Vec<String>) {
-        if let Some(&src_id) = self.keyword_to_node.get(&source.to_lowercase()) {
+        if let Some(&src_id) = self.keyword_to_node.get(&self.feature_key(source)) {
+            let mut removed = Vec::new();
             for target_str in delete_targets {
-                if let Some(&tgt_id) = self.keyword_to_node.get(&target_str.to_lowercase()) {
-                    let _ = self.tdb.unlink(src_id as u64, tgt_id as u64);
+                if let Some(&tgt_id) = self.keyword_to_node.get(&self.feature_key(&target_str))
+                    && self.unlink_raw(src_id as u64, tgt_id as u64) > 0 {
+                    // 目标可能以别名给出，报告时统一用节点登记的首个关键词
+                    removed.push(self.keywords_for(tgt_id).first().cloned().unwrap_or(target_str));
                 }
             }
-            println!("✂️ [Arbitration] 已从 '{}' 移除了过时关联", source);
+            println!("✂️ [Arbitration] 已从 '{}' 移除了过时关联: {}", source, removed.join(", "));
         }
     }
 
+    /// 执行一条 LLM 维护指令，返回 (图谱改动, replace 时提交给仲裁的上下文)
     #[allow(dead_code)]
-    pub fn execute_maintenance(&mut self, action: &str, source: &str, target: &str, relation_type: &str, strength: f32, _reason: &str) -> Option<String> {
+    pub fn execute_maintenance(&mut self, action: &str, source: &str, target: &str, relation_type: &str, strength: f32, _reason: &str) -> (Option<OntologyChange>, Option<String>) {
         match action.to_lowercase().as_str() {
-            "upsert" => { self.maintain_ontology(source, target, relation_type, strength); None },
-            "replace" => { self.maintain_ontology(source, target, relation_type, strength); self.trigger_arbitration(source) },
-            _ => None
+            "upsert" => (Some(self.maintain_ontology(source, target, relation_type, strength)), None),
+            "replace" => {
+                let change = self.maintain_ontology(source, target, relation_type, strength);
+                (Some(change), self.trigger_arbitration(source))
+            }
+            _ => (None, None)
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::simhash::SimHash;
+
+    #[test]
+    fn test_maintain_ontology_reports_change() {
+        let mut engine = AdvancedEngine::open_temp("ontology_change");
+        let strength = |engine: &AdvancedEngine, src: &str, tgt: &str| {
+            let (src, tgt) = (engine.keyword_to_node[src] as u64, engine.keyword_to_node[tgt] as u64);
+            engine.tdb.get_edges(src).iter().find(|e| e.target_id == tgt).map(|e| e.weight)
+        };
+
+        let created = engine.maintain_ontology("Pero", "女孩", "representation", 0.6);
+        assert_eq!(created, OntologyChange { created: true, old_strength: None, new_strength: 0.6, reverse_created: false, rejected: false, pruned: false });
+        assert_eq!(strength(&engine, "pero", "女孩"), Some(0.6));
+
+        let (change, context) = engine.execute_maintenance("upsert", "Pero", "女孩", "representation", 0.9, "");
+        assert_eq!(change, Some(OntologyChange { created: false, old_strength: Some(0.6), new_strength: 0.9, reverse_created: false, rejected: false, pruned: false }));
+        assert!(context.is_none());
+        assert_eq!(strength(&engine, "pero", "女孩"), Some(0.9));
+
+        // 强化只增不减
+        let weaker = engine.maintain_ontology("Pero", "女孩", "representation", 0.3);
+        assert_eq!((weaker.old_strength, weaker.new_strength), (Some(0.9), 0.9));
+
+        let equal = engine.maintain_ontology("TS", "TypeScript", "equality", 1.0);
+        assert!(equal.created && equal.reverse_created);
+        assert_eq!(strength(&engine, "typescript", "ts"), Some(1.0));
+
+        assert!(engine.maintain_ontology("的", "女孩", "representation", 1.0).rejected);
+    }
+
+    #[test]
+    fn test_ontology_neighbors_sorted_by_strength() {
+        let mut engine = AdvancedEngine::open_temp("ontology_neighbors");
+        engine.maintain_ontology("Pero", "女孩", "representation", 0.6);
+        engine.maintain_ontology("Pero", "佩罗", "equality", 1.0);
+        engine.maintain_ontology("Pero", "猫", "inhibition", 0.8);
+        engine.add_event(1, "Pero 去了海边", 0, 0, 0);
+        let pero = engine.feature_id("pero").unwrap();
+        engine.add_edge(pero, 1, 0.9);
+
+        assert_eq!(engine.ontology_neighbors("PERO"), vec![
+            ("佩罗".to_string(), 1.0, ONTOLOGY_EQUALITY),
+            ("猫".to_string(), 0.8, ONTOLOGY_INHIBITION),
+            ("女孩".to_string(), 0.6, ONTOLOGY_REPRESENTATION),
+        ]);
+        assert!(engine.ontology_neighbors("未知").is_empty());
+        assert_eq!(engine.trigger_arbitration("Pero").as_deref(), Some("Pero -> 佩罗 (Strength: 1.00)\nPero -> 猫 (Strength: 0.80)\nPero -> 女孩 (Strength: 0.60)"));
+        assert_eq!(engine.trigger_arbitration("未知"), None);
+    }
+
+    #[test]
+    fn test_forget_removes_stale_unaccessed_events() {
+        let mut engine = AdvancedEngine::open_temp("forget");
+        let year = 31536000;
+        let now = 10 * year;
+        engine.add_event(1, "很久以前的一件小事", now - 3 * year, 0, 0);
+        engine.add_event(2, "很久以前但经常回想的事", now - 3 * year, 0, 0);
+        engine.add_event(3, "上周刚发生的事", now - 7 * 86400, 0, 0);
+        engine.build_temporal_backbone();
+        engine.compile();
+        for _ in 0..4 {
+            engine.record_access(2, now - 30 * 86400);
+        }
+
+        // exp(-3) ≈ 0.05 < 0.2 被遗忘；访问 4 次后 exp(-1/12 / 5) 与新近事件均保留
+        let unflushed = engine.unflushed_nodes();
+        assert_eq!(engine.forget(0.2, now), vec![1]);
+        assert_eq!(engine.unflushed_nodes(), unflushed + 1);
+        assert!(engine.tdb.get_payload(1).is_none());
+        assert!(engine.tdb.get_payload(2).is_some());
+        assert!(engine.tdb.get_payload(3).is_some());
+        assert!(engine.retrieve("很久以前", 0, 0.0).iter().all(|&(id, _)| id != 1));
+        // 时序脊梁跳过被删除的事件
+        let backbone: Vec<_> = [2, 3].iter().map(|&id| engine.tdb.get_payload(id).unwrap()).collect();
+        assert!(backbone[0].get("prev_event").is_none());
+        assert_eq!(backbone[0]["next_event"], 3);
+        assert_eq!(backbone[1]["prev_event"], 2);
+        assert!(engine.forget(0.2, now).is_empty());
+        // τ 跟随配置: 缩短到一天后一周前的事件也被遗忘
+        engine.config.resonance.decay_tau = 86400.0;
+        assert_eq!(engine.forget(0.2, now), vec![2, 3]);
+    }
+
+    #[test]
+    fn test_global_decay_and_background_maintenance() {
+        use std::time::{Duration, Instant};
+        use crate::core::types::{AsyncTaskInterface, ThreadedAsyncTask};
+
+        let mut engine = AdvancedEngine::open_temp("global_decay");
+        engine.add_event(1, "强关联的事件", 0, 0, 0);
+        engine.add_event(2, "弱关联的事件", 0, 0, 0);
+        let feature = engine.get_or_create_feature("关联") as u64;
+        engine.add_edge(feature as i64, 1, 0.8);
+        engine.add_edge(feature as i64, 2, 0.05);
+        engine.add_edge(1, 2, 0.8);
+        engine.tdb.link(1, 2, "representation", 0.6).unwrap();
+        let weight = |engine: &AdvancedEngine, src: u64, tgt: u64, label: &str| {
+            engine.tdb.get_edges(src).iter().find(|e| e.target_id == tgt && e.label == label).map(|e| e.weight)
+        };
+
+        assert_eq!(engine.apply_global_decay_and_pruning(0.5, 6554), 1);
+        assert_eq!(weight(&engine, feature, 1, "memory_edge"), Some(0.4));
+        assert_eq!(weight(&engine, feature, 2, "memory_edge"), None);
+        // 同一对节点间不同标签的边各自衰减
+        assert_eq!(weight(&engine, 1, 2, "memory_edge"), Some(0.4));
+        assert_eq!(weight(&engine, 1, 2, "representation"), Some(0.3));
+
+        // 后台线程只排期，剪枝在引擎线程上执行
+        assert_eq!(engine.run_pending_maintenance(), 0);
+        engine.async_task = Some(Box::new(ThreadedAsyncTask::spawn(Duration::from_secs(3600), 0.5, 13000)));
+        engine.schedule_maintenance("manual");
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let mut pruned = 0;
+        while pruned == 0 && Instant::now() < deadline {
+            pruned = engine.run_pending_maintenance();
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(pruned, 1);
+        assert_eq!(weight(&engine, 1, 2, "representation"), None);
+        assert_eq!(weight(&engine, feature, 1, "memory_edge"), Some(0.2));
+
+        let periodic = ThreadedAsyncTask::spawn(Duration::from_millis(5), 0.1, 0);
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let mut requests = Vec::new();
+        while requests.is_empty() && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(10));
+            requests = periodic.drain_requests();
+        }
+        assert!(requests.iter().all(|r| r.context == "periodic" && r.decay_rate == 0.1));
+        assert!(!requests.is_empty());
+    }
+
+    #[test]
+    fn test_weaken_ontology_until_pruned() {
+        let mut engine = AdvancedEngine::open_temp("weaken_ontology");
+        engine.maintain_ontology("rust", "编程语言", "representation", 0.9);
+        engine.maintain_ontology("rust", "铁锈", "equality", 0.9);
+        let src = engine.keyword_to_node["rust"] as u64;
+        let tgt = engine.keyword_to_node["编程语言"] as u64;
+        let strength = |engine: &AdvancedEngine| {
+            engine.tdb.get_edges(src).iter().find(|e| e.target_id == tgt).map(|e| e.weight)
+        };
+
+        let change = engine.weaken_ontology("rust", "编程语言", 0.5).unwrap();
+        assert_eq!((change.old_strength, change.pruned), (Some(0.9), false));
+        assert!((change.new_strength - 0.45).abs() < 1e-6);
+        assert!((strength(&engine).unwrap() - 0.45).abs() < 1e-6);
+
+        let mut rounds = 1;
+        while strength(&engine).is_some() {
+            let change = engine.weaken_ontology("rust", "编程语言", 0.5).unwrap();
+            assert_eq!(change.pruned, strength(&engine).is_none());
+            rounds += 1;
+            assert!(rounds < 10);
+        }
+        // 0.9 * 0.5^4 = 0.05625 < 阈值
+        assert_eq!(rounds, 4);
+        assert_eq!(engine.weaken_ontology("rust", "编程语言", 0.5), None);
+        assert_eq!(engine.weaken_ontology("rust", "未知", 0.5), None);
+        // 其它关联不受影响
+        let other = engine.keyword_to_node["铁锈"] as u64;
+        assert!(engine.tdb.get_edges(src).iter().any(|e| e.target_id == other));
+
+        // 同一对节点间的记忆边与反向的非对称边不随之削弱
+        engine.add_edge(src as i64, other as i64, 0.7);
+        engine.maintain_ontology("铁锈", "rust", "representation", 0.8);
+        let weight = |engine: &AdvancedEngine, from: u64, to: u64, label: &str| engine.tdb.get_edges(from).iter().find(|e| e.target_id == to && e.label == label).map(|e| e.weight);
+        let change = engine.weaken_ontology("rust", "铁锈", 0.5).unwrap();
+        assert!((change.new_strength - 0.45).abs() < 1e-6);
+        assert!((weight(&engine, other, src, "equality").unwrap() - 0.45).abs() < 1e-6);
+        assert_eq!(weight(&engine, other, src, "representation"), Some(0.8));
+        assert_eq!(weight(&engine, src, other, "memory_edge"), Some(0.7));
+
+        // 双向关系两侧同时削弱
+        assert!(engine.weaken_ontology("rust", "铁锈", 0.1).unwrap().pruned);
+        assert_eq!(weight(&engine, src, other, "equality"), None);
+        assert_eq!(weight(&engine, other, src, "equality"), None);
+        assert_eq!(weight(&engine, other, src, "representation"), Some(0.8));
+        assert_eq!(weight(&engine, src, other, "memory_edge"), Some(0.7));
+    }
+
+    #[test]
+    fn test_merge_similar_features() {
+        let mut engine = AdvancedEngine::open_temp("merge_features");
+        let one = engine.get_or_create_feature("feat_1");
+        let ell = engine.get_or_create_feature("feat_l");
+        let other = engine.get_or_create_feature("完全不同的特征");
+        assert!(((SimHash::compute("feat_1") ^ SimHash::compute("feat_l")) & SimHash::MASK_SEMANTIC).count_ones() <= 8);
+        engine.add_event(1, "第一件事", 0, 0, 0);
+        engine.add_event(2, "第二件事", 0, 0, 0);
+        engine.add_edge(one, 1, 0.8);
+        engine.add_edge(ell, 2, 0.6);
+        engine.maintain_ontology("完全不同的特征", "feat_l", "representation", 0.5);
+
+        assert_eq!(engine.merge_similar_features(8), 1);
+        let (canon, dup) = (one.min(ell), one.max(ell));
+        assert!(!engine.tdb.contains(dup as u64));
+        assert!(engine.tdb.contains(other as u64));
+        let mut targets: Vec<(u64, f32)> = engine.tdb.get_edges(canon as u64).iter().map(|e| (e.target_id, e.weight)).collect();
+        targets.sort_by_key(|&(t, _)| t);
+        assert_eq!(targets, vec![(1, 0.8), (2, 0.6)]);
+        assert_eq!(engine.tdb.get_edges(other as u64).iter().map(|e| e.target_id).collect::<Vec<_>>(), vec![canon as u64]);
+        assert_eq!(engine.keyword_to_node["feat_1"], canon);
+        assert_eq!(engine.keyword_to_node["feat_l"], canon);
+        assert_eq!(engine.keywords_for(canon), ["feat_1", "feat_l"]);
+        assert!(engine.keywords_for(dup).is_empty());
+        assert_eq!(engine.verify(), Ok(()));
+    }
+}
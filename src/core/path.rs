@@ -0,0 +1,176 @@
+use ahash::AHashMap;
+use crate::core::engine::{AdvancedEngine, MemoryEdgeType};
+use crate::core::ontology::ontology_edge_code;
+
+/// 一跳所在的图层
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GraphLayer {
+    /// 本体图: 特征之间的 representation / equality / inhibition 关系
+    Ontology,
+    /// 记忆图: memory_edge (特征 -> 事件、事件 -> 事件)
+    Memory,
+}
+
+/// 解释路径中的一跳
+#[derive(Debug, Clone, PartialEq)]
+pub struct PathHop {
+    pub from: i64,
+    pub to: i64,
+    pub strength: f32,
+    /// 边类型编码，按 layer 解读: 本体层为 ONTOLOGY_* (见 ontology_edge_code)，记忆层为 MemoryEdgeType 的编码
+    pub edge_type: u8,
+    pub layer: GraphLayer,
+}
+
+/// 边标签所属的图层与类型编码，两层都不认识的标签 (如直接写入 tdb 的自定义边) 为 None
+fn classify_edge(label: &str) -> Option<(GraphLayer, u8)> {
+    match MemoryEdgeType::from_label(label) {
+        Some(edge_type) => Some((GraphLayer::Memory, edge_type.code())),
+        None => ontology_edge_code(label).map(|code| (GraphLayer::Ontology, code)),
+    }
+}
+
+impl AdvancedEngine {
+    /// 带注释的最短关联路径: 跳数最少，同跳数时取各跳强度乘积最大的一条
+    /// 沿边的方向搜索，最多 max_hops 跳，不经过未知标签的边；src == tgt 时返回空路径。
+    pub fn explain_path(&self, src: i64, tgt: i64, max_hops: usize) -> Option<Vec<PathHop>> {
+        let (src, tgt) = (src as u64, tgt as u64);
+        if !self.tdb.contains(src) || !self.tdb.contains(tgt) { return None; }
+        if src == tgt { return Some(Vec::new()); }
+
+        // 节点 -> (到达时的强度乘积, 上一跳)
+        let mut visited: AHashMap<u64, (f32, Option<PathHop>)> = AHashMap::new();
+        visited.insert(src, (1.0, None));
+        let mut frontier = vec![src];
+        for _ in 0..max_hops {
+            let mut next: AHashMap<u64, (f32, PathHop)> = AHashMap::new();
+            for &node in &frontier {
+                let product = visited[&node].0;
+                for edge in self.tdb.get_edges(node) {
+                    if visited.contains_key(&edge.target_id) { continue; }
+                    let score = product * edge.weight;
+                    if next.get(&edge.target_id).is_some_and(|(best, _)| *best >= score) { continue; }
+                    let Some((layer, edge_type)) = classify_edge(&edge.label) else { continue; };
+                    let hop = PathHop { from: node as i64, to: edge.target_id as i64, strength: edge.weight, edge_type, layer };
+                    next.insert(edge.target_id, (score, hop));
+                }
+            }
+            if next.is_empty() { return None; }
+            frontier = next.keys().copied().collect();
+            for (node, (score, hop)) in next {
+                visited.insert(node, (score, Some(hop)));
+            }
+            if visited.contains_key(&tgt) { break; }
+        }
+
+        let mut path = Vec::new();
+        let mut node = tgt;
+        while let Some((_, Some(hop))) = visited.get(&node) {
+            node = hop.from as u64;
+            path.push(hop.clone());
+        }
+        if node != src { return None; }
+        path.reverse();
+        Some(path)
+    }
+
+    /// 加权最短路径: 每跳代价为 1 - 边强度 (强关联几乎不计代价)，至多 max_hops 跳
+    /// 沿边的方向搜索，抑制边不参与；返回途经节点 (含首尾)，src == tgt 时为 [src]，不可达时为 None。
+    pub fn find_path(&self, src: i64, tgt: i64, max_hops: usize) -> Option<Vec<i64>> {
+        if src < 0 || tgt < 0 { return None; }
+        let (src, tgt) = (src as u64, tgt as u64);
+        if !self.tdb.contains(src) || !self.tdb.contains(tgt) { return None; }
+        if src == tgt { return Some(vec![src as i64]); }
+
+        // 按跳数分层松弛 (限跳数的 Bellman-Ford): layers[h] 为恰好 h 跳时代价有所改进的节点 -> (代价, 上一跳节点)
+        let mut best: AHashMap<u64, f32> = AHashMap::from_iter([(src, 0.0)]);
+        let mut layers: Vec<AHashMap<u64, (f32, u64)>> = vec![AHashMap::from_iter([(src, (0.0, src))])];
+        for _ in 0..max_hops {
+            let mut next: AHashMap<u64, (f32, u64)> = AHashMap::new();
+            let mut frontier: Vec<(&u64, &(f32, u64))> = layers.last().unwrap().iter().collect();
+            // 哈希表迭代顺序不固定，按 id 处理保证等价路径的选择可复现
+            frontier.sort_unstable_by_key(|&(&node, _)| node);
+            for (&node, &(cost, _)) in frontier {
+                for edge in self.tdb.get_edges(node) {
+                    if edge.label == "inhibition" { continue; }
+                    let total = cost + (1.0 - edge.weight).max(0.0);
+                    let improves = best.get(&edge.target_id).is_none_or(|&b| total < b);
+                    if improves && next.get(&edge.target_id).is_none_or(|&(c, _)| total < c) {
+                        next.insert(edge.target_id, (total, node));
+                    }
+                }
+            }
+            if next.is_empty() { break; }
+            for (&node, &(cost, _)) in &next {
+                best.insert(node, cost);
+            }
+            layers.push(next);
+        }
+
+        // 取代价最低的一层作为终点，逐层回溯上一跳
+        let (mut hops, _) = layers.iter().enumerate()
+            .filter_map(|(h, layer)| layer.get(&tgt).map(|&(cost, _)| (h, cost)))
+            .min_by(|a, b| a.1.total_cmp(&b.1).then(a.0.cmp(&b.0)))?;
+        let mut path = vec![tgt as i64];
+        let mut node = tgt;
+        while hops > 0 {
+            node = layers[hops][&node].1;
+            path.push(node as i64);
+            hops -= 1;
+        }
+        path.reverse();
+        Some(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::ontology::{ONTOLOGY_INHIBITION, ONTOLOGY_REPRESENTATION};
+
+    #[test]
+    fn test_explain_path_across_layers() {
+        let mut engine = AdvancedEngine::open_temp("explain_path");
+        engine.load_standard_data();
+        let memory_safety = engine.keyword_to_node["内存安全"];
+        let rust = engine.keyword_to_node["rust"];
+
+        // 内存安全 -(representation)-> Rust -(memory_edge)-> 事件 100 (PeroCore 重构)
+        let path = engine.explain_path(memory_safety, 100, 3).unwrap();
+        assert_eq!(path, vec![
+            PathHop { from: memory_safety, to: rust, strength: 0.9, edge_type: ONTOLOGY_REPRESENTATION, layer: GraphLayer::Ontology },
+            PathHop { from: rust, to: 100, strength: 1.0, edge_type: MemoryEdgeType::Association.code(), layer: GraphLayer::Memory },
+        ]);
+        assert_eq!(engine.explain_path(memory_safety, 100, 1), None);
+        assert_eq!(engine.explain_path(100, 100, 3), Some(Vec::new()));
+        assert_eq!(engine.find_path(memory_safety, 100, 3), Some(vec![memory_safety, rust, 100]));
+    }
+
+    #[test]
+    fn test_find_path_prefers_strong_edges() {
+        let mut engine = AdvancedEngine::open_temp("find_path");
+        for id in 1..=4 {
+            engine.add_event(id, &format!("事件 {}", id), 0, 0, 0);
+        }
+        engine.add_edge(1, 4, 0.1);
+        engine.add_edge(1, 2, 0.9);
+        engine.add_edge(2, 3, 0.9);
+        engine.add_edge(3, 4, 0.9);
+        // 绕远的强关联 (代价 0.3) 胜过直连的弱关联 (代价 0.9)，但受跳数上限约束
+        assert_eq!(engine.find_path(1, 4, 3), Some(vec![1, 2, 3, 4]));
+        assert_eq!(engine.find_path(1, 4, 2), Some(vec![1, 4]));
+        assert_eq!(engine.find_path(4, 1, 3), None);
+        assert_eq!(engine.find_path(1, 1, 0), Some(vec![1]));
+        assert_eq!(engine.find_path(1, 404, 3), None);
+
+        // 抑制边不构成路径
+        engine.maintain_ontology("猫", "狗", "inhibition", 1.0);
+        let (cat, dog) = (engine.feature_id("猫").unwrap(), engine.feature_id("狗").unwrap());
+        assert_eq!(engine.find_path(cat, dog, 3), None);
+        assert_eq!(engine.explain_path(cat, dog, 3).unwrap()[0].edge_type, ONTOLOGY_INHIBITION);
+
+        // 未知标签的边不参与解释路径
+        engine.tdb.link(1, 3, "custom", 1.0).unwrap();
+        assert_eq!(engine.explain_path(1, 3, 1), None);
+    }
+}
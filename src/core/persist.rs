@@ -0,0 +1,222 @@
+use std::collections::BTreeMap;
+use std::hash::Hasher;
+use serde::{Deserialize, Serialize};
+use twox_hash::XxHash64;
+use crate::core::config::EngineConfig;
+use crate::core::engine::{AdvancedEngine, StorageError, DEFAULT_VECTOR_DIM};
+use crate::core::simhash::EmotionKeywords;
+use crate::core::stopwords::StopwordFilter;
+
+/// 会话清单的格式版本，布局变化时递增
+pub const MANIFEST_VERSION: u16 = 1;
+const MANIFEST_SUFFIX: &str = ".pedsa.toml";
+
+/// 与存储文件并列的会话清单: TriviumDB 之外、只存在于内存中的引擎状态
+/// 图、payload 与向量由 TriviumDB 自身持久化；关键词表、标签索引、文本索引与 IDF 表在 load 时由 compile 重建。
+#[derive(Debug, Serialize, Deserialize)]
+struct EngineManifest {
+    format_version: u16,
+    config: EngineConfig,
+    /// 情感位 -> 关键词 (TOML 的键只能是字符串)
+    emotion_keywords: BTreeMap<String, Vec<String>>,
+    /// 与内置表不同时记录全部停用词，None 表示使用内置表
+    #[serde(default)]
+    stopwords: Option<Vec<String>>,
+    /// 存储的向量维度 (旧清单没有该字段，取默认 512)
+    #[serde(default = "default_vector_dim")]
+    vector_dim: usize,
+    /// 存储文件名 -> XxHash64 (十六进制，TOML 整数放不下 u64)，只在 save 到新路径 (快照) 时记录，load 时校验
+    /// 原地保存的存储之后仍会被继续写入，不记录校验和。
+    #[serde(default)]
+    checksums: BTreeMap<String, String>,
+}
+
+fn default_vector_dim() -> usize {
+    DEFAULT_VECTOR_DIM
+}
+
+fn manifest_path(path: &str) -> String {
+    format!("{}{}", path, MANIFEST_SUFFIX)
+}
+
+/// 快照包含的存储文件 (.vec 不存在时跳过)
+fn store_files(path: &str) -> Vec<String> {
+    let vec_file = format!("{}.vec", path);
+    let mut files = vec![path.to_string()];
+    if std::path::Path::new(&vec_file).exists() { files.push(vec_file); }
+    files
+}
+
+fn file_checksum(path: &str) -> Result<String, StorageError> {
+    let bytes = std::fs::read(path).map_err(StorageError::Io)?;
+    let mut hasher = XxHash64::with_seed(0);
+    hasher.write(&bytes);
+    Ok(format!("{:016x}", hasher.finish()))
+}
+
+fn file_name(path: &str) -> String {
+    std::path::Path::new(path).file_name().map_or_else(|| path.to_string(), |n| n.to_string_lossy().into_owned())
+}
+
+impl AdvancedEngine {
+    /// 保存会话: 落盘存储并写出会话清单 (配置 + 情感关键词表)
+    /// path 与当前存储路径不同时，先把存储文件 (.tdb 与 .vec) 复制过去并记录校验和，当前引擎仍使用原路径。
+    pub fn save(&mut self, path: &str) -> Result<(), StorageError> {
+        self.tdb.flush().map_err(|e| StorageError::Storage(e.to_string()))?;
+        if path != self.path {
+            if let Some(parent) = std::path::Path::new(path).parent() {
+                std::fs::create_dir_all(parent).map_err(StorageError::Io)?;
+            }
+            std::fs::copy(&self.path, path).map_err(StorageError::Io)?;
+            let vec_file = format!("{}.vec", self.path);
+            if std::path::Path::new(&vec_file).exists() {
+                std::fs::copy(&vec_file, format!("{}.vec", path)).map_err(StorageError::Io)?;
+            }
+        }
+        let mut checksums = BTreeMap::new();
+        if path != self.path {
+            for file in store_files(path) {
+                checksums.insert(file_name(&file), file_checksum(&file)?);
+            }
+        }
+        let manifest = EngineManifest {
+            format_version: MANIFEST_VERSION,
+            config: self.config.clone(),
+            emotion_keywords: self.emotion_keywords.table.iter().map(|(flag, words)| (flag.to_string(), words.clone())).collect(),
+            stopwords: (self.stopwords != StopwordFilter::default())
+                .then(|| self.stopwords.words().into_iter().map(String::from).collect()),
+            vector_dim: self.vector_dim(),
+            checksums,
+        };
+        let text = toml::to_string(&manifest).map_err(|e| StorageError::Storage(e.to_string()))?;
+        std::fs::write(manifest_path(path), text).map_err(StorageError::Io)
+    }
+
+    /// 加载会话: 打开存储，按清单恢复配置与情感关键词表 (没有清单时取默认值)，再 compile 重建派生索引
+    /// 清单记录了校验和时先逐个校验存储文件，不一致返回 StorageError::Corrupt。
+    pub fn load(path: &str) -> Result<Self, StorageError> {
+        let manifest = match std::fs::read_to_string(manifest_path(path)) {
+            Ok(text) => Some(toml::from_str::<EngineManifest>(&text).map_err(|e| {
+                // 先取版本号，使布局变化报告为版本错误而不是解析错误
+                match toml::from_str::<toml::Table>(&text).ok().and_then(|t| t.get("format_version")?.as_integer()) {
+                    Some(v) if v != MANIFEST_VERSION as i64 => StorageError::UnsupportedVersion(v.clamp(0, u16::MAX as i64) as u16),
+                    _ => StorageError::Storage(format!("会话清单解析失败: {}", e)),
+                }
+            })?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => return Err(StorageError::Io(e)),
+        };
+        if let Some(m) = &manifest {
+            if m.format_version != MANIFEST_VERSION {
+                return Err(StorageError::UnsupportedVersion(m.format_version));
+            }
+            m.config.validate().map_err(|e| StorageError::Storage(e.to_string()))?;
+            if !m.checksums.is_empty() {
+                let files = store_files(path);
+                for (name, expected) in &m.checksums {
+                    let Some(file) = files.iter().find(|f| file_name(f) == *name) else {
+                        return Err(StorageError::Corrupt(name.clone()));
+                    };
+                    if file_checksum(file)? != *expected {
+                        return Err(StorageError::Corrupt(name.clone()));
+                    }
+                }
+            }
+        }
+
+        let mut engine = Self::try_open_with_dim(path, manifest.as_ref().map_or(DEFAULT_VECTOR_DIM, |m| m.vector_dim))?;
+        if let Some(m) = manifest {
+            engine.config = m.config;
+            let table = m.emotion_keywords.into_iter()
+                .filter_map(|(flag, words)| Some((flag.parse::<u8>().ok()?, words)))
+                .collect();
+            engine.emotion_keywords = EmotionKeywords { table };
+            if let Some(words) = m.stopwords {
+                engine.stopwords = StopwordFilter::empty();
+                for word in &words {
+                    engine.stopwords.insert(word);
+                }
+            }
+        }
+        engine.compile();
+        Ok(engine)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::simhash::SimHash;
+
+    #[test]
+    fn test_save_and_load_session() {
+        let dir = std::env::temp_dir().join(format!("pedsa_test_session_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let (src, dst) = (dir.join("src/db"), dir.join("saved/db"));
+        let (src, dst) = (src.to_str().unwrap(), dst.to_str().unwrap());
+
+        let expected = {
+            let mut engine = AdvancedEngine::open(src);
+            engine.config.english_stemming = true;
+            engine.config.min_score = 0.2;
+            engine.emotion_keywords.table.entry(SimHash::EMOTION_ANGER).or_default().push("红温".to_string());
+            engine.stopwords.insert("嗯嗯");
+            engine.add_event(1, "Rust optimization 笔记", 0, 0, 0);
+            engine.add_event(2, "打游戏又红温了", 0, 0, 0);
+            let feature = engine.get_or_create_feature("optimizing");
+            engine.add_edge(feature, 1, 1.0);
+            engine.compile();
+            engine.save(dst).unwrap();
+            (engine.config.clone(), engine.emotion_keywords.clone(), engine.retrieve("optimize", 0, 0.0), feature)
+        };
+
+        let engine = AdvancedEngine::load(dst).unwrap();
+        assert_eq!(engine.config, expected.0);
+        assert_eq!(engine.emotion_keywords, expected.1);
+        assert_eq!(engine.retrieve("optimize", 0, 0.0), expected.2);
+        assert_eq!(engine.feature_id("optimization"), Some(expected.3));
+        assert_eq!(engine.emotion_keywords.extract("红温"), SimHash::EMOTION_ANGER);
+        assert!(engine.stopwords.is_stopword("嗯嗯") && engine.stopwords.is_stopword("的"));
+        drop(engine);
+
+        let manifest = manifest_path(dst);
+        let text = std::fs::read_to_string(&manifest).unwrap();
+        std::fs::write(&manifest, text.replace("format_version = 1", "format_version = 9")).unwrap();
+        assert!(matches!(AdvancedEngine::load(dst), Err(StorageError::UnsupportedVersion(9))));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_load_rejects_corrupted_snapshot() {
+        let dir = std::env::temp_dir().join(format!("pedsa_test_checksum_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let (src, dst) = (dir.join("src/db"), dir.join("snapshot/db"));
+        let (src, dst) = (src.to_str().unwrap(), dst.to_str().unwrap());
+        {
+            let mut engine = AdvancedEngine::open(src);
+            engine.add_event(1, "快照里的事件", 0, 0, 0);
+            engine.compile();
+            engine.save(dst).unwrap();
+            // 原地保存不记录校验和，之后继续写入也能正常加载
+            engine.save(src).unwrap();
+            engine.add_event(2, "保存之后的新事件", 0, 0, 0);
+            engine.compile();
+        }
+        assert!(AdvancedEngine::load(src).unwrap().node_type(2).is_some());
+        let text = std::fs::read_to_string(manifest_path(dst)).unwrap();
+        assert!(text.contains("[checksums]"));
+
+        // 模拟写入中断: 快照文件尾部被改写
+        let original = std::fs::read(dst).unwrap();
+        let mut torn = original.clone();
+        *torn.last_mut().unwrap() ^= 0xff;
+        std::fs::write(dst, &torn).unwrap();
+        match AdvancedEngine::load(dst) {
+            Err(StorageError::Corrupt(file)) => assert_eq!(file, "db"),
+            other => panic!("expected Corrupt, got {:?}", other.map(|_| ())),
+        }
+        std::fs::write(dst, &original).unwrap();
+        assert_eq!(AdvancedEngine::load(dst).unwrap().node_type(1).as_deref(), Some("event"));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
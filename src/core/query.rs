@@ -0,0 +1,172 @@
+use crate::core::dates;
+use crate::core::simhash::{SimHash, SimHashLayout, SimHashRegion};
+
+/// 解析后的查询: 字段过滤条件 + 剩余自由文本
+/// 支持 `emotion:<名称>` `type:<类型>` `time:<年份>` `before:<日期>` `after:<日期>` 前缀,
+/// 日期格式为 `YYYY` / `YYYY-MM` / `YYYY-MM-DD`。无法识别的前缀原样保留在自由文本中。
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ParsedQuery {
+    pub text: String,
+    /// 情感位掩码 (与事件情感有任一位重叠即通过)
+    pub emotion_filter: Option<u8>,
+    /// 时间范围 [start, end) (time / before / after 合并后的结果)
+    pub time_filter: Option<(u64, u64)>,
+    pub type_filter: Option<u8>,
+}
+
+impl ParsedQuery {
+    pub fn has_filters(&self) -> bool {
+        self.emotion_filter.is_some() || self.time_filter.is_some() || self.type_filter.is_some()
+    }
+
+    /// 对检索命中的 payload 施加硬过滤 (指纹按默认布局解码)
+    pub fn matches(&self, payload: &serde_json::Value) -> bool {
+        self.matches_with_layout(payload, &SimHashLayout::DEFAULT)
+    }
+
+    /// 同 matches，类型过滤按给定布局从指纹中取类型区
+    pub fn matches_with_layout(&self, payload: &serde_json::Value, layout: &SimHashLayout) -> bool {
+        if let Some(mask) = self.emotion_filter {
+            let emotions = payload.get("emotions").and_then(|v| v.as_u64()).unwrap_or(0) as u8;
+            if emotions & mask == 0 { return false; }
+        }
+        if let Some((start, end)) = self.time_filter {
+            let timestamp = payload.get("timestamp").and_then(|v| v.as_u64()).unwrap_or(0);
+            if timestamp < start || timestamp >= end { return false; }
+        }
+        if let Some(type_val) = self.type_filter {
+            let fingerprint = payload.get("fingerprint").and_then(|v| v.as_u64()).unwrap_or(0);
+            if layout.extract(fingerprint, SimHashRegion::EntityType) != type_val as u64 { return false; }
+        }
+        true
+    }
+}
+
+pub fn parse_query(s: &str) -> ParsedQuery {
+    let mut parsed = ParsedQuery::default();
+    let mut words = Vec::new();
+    let (mut start, mut end) = (0u64, u64::MAX);
+
+    for token in s.split_whitespace() {
+        let Some((key, value)) = token.split_once(':') else {
+            words.push(token);
+            continue;
+        };
+        let value = value.to_lowercase();
+        let recognized = match key.to_lowercase().as_str() {
+            "emotion" => parse_emotion(&value).map(|flag| {
+                parsed.emotion_filter = Some(parsed.emotion_filter.unwrap_or(0) | flag);
+            }),
+            "type" => parse_type(&value).map(|type_val| parsed.type_filter = Some(type_val)),
+            "time" => value.parse::<i32>().ok().filter(|year| (1970..=9999).contains(year)).map(|year| {
+                start = start.max(date_timestamp(year, 1, 1));
+                end = end.min(date_timestamp(year + 1, 1, 1));
+            }),
+            "before" => parse_date(&value).map(|ts| end = end.min(ts)),
+            "after" => parse_date(&value).map(|ts| start = start.max(ts)),
+            _ => None,
+        };
+        if recognized.is_none() {
+            words.push(token);
+        }
+    }
+
+    if start > 0 || end < u64::MAX {
+        parsed.time_filter = Some((start, end));
+    }
+    parsed.text = words.join(" ");
+    parsed
+}
+
+pub(crate) fn parse_emotion(name: &str) -> Option<u8> {
+    Some(match name {
+        "joy" | "喜悦" | "开心" => SimHash::EMOTION_JOY,
+        "shy" | "trust" | "害羞" => SimHash::EMOTION_SHY,
+        "fear" | "害怕" => SimHash::EMOTION_FEAR,
+        "surprise" | "惊讶" => SimHash::EMOTION_SURPRISE,
+        "sadness" | "sad" | "难过" => SimHash::EMOTION_SADNESS,
+        "disgust" | "讨厌" => SimHash::EMOTION_DISGUST,
+        "anger" | "angry" | "生气" => SimHash::EMOTION_ANGER,
+        "anticipation" | "期待" => SimHash::EMOTION_ANTICIPATION,
+        _ => return None,
+    })
+}
+
+fn parse_type(name: &str) -> Option<u8> {
+    Some(match name {
+        "person" | "人物" => SimHash::TYPE_PERSON,
+        "tech" | "技术" => SimHash::TYPE_TECH,
+        "event" | "事件" => SimHash::TYPE_EVENT,
+        "location" | "地点" => SimHash::TYPE_LOCATION,
+        "object" | "物件" => SimHash::TYPE_OBJECT,
+        "values" | "价值观" => SimHash::TYPE_VALUES,
+        _ => return None,
+    })
+}
+
+/// `YYYY` / `YYYY-MM` / `YYYY-MM-DD`
+fn parse_date(value: &str) -> Option<u64> {
+    let mut parts = value.split('-');
+    let year = parts.next()?.parse::<i32>().ok()?;
+    let month = parts.next().map(|m| m.parse::<u32>().ok()).unwrap_or(Some(1))?;
+    let day = parts.next().map(|d| d.parse::<u32>().ok()).unwrap_or(Some(1))?;
+    if parts.next().is_some() || !(1970..=9999).contains(&year) {
+        return None;
+    }
+    dates::date_timestamp(year, month, day)
+}
+
+/// 与 `AdvancedEngine::extract_timestamp` 相同的公历换算, 保证过滤边界与入库时间戳一致
+fn date_timestamp(year: i32, month: u32, day: u32) -> u64 {
+    dates::date_timestamp(year, month, day).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_mixed_query() {
+        let parsed = parse_query("emotion:joy time:2024 rust 编程");
+        assert_eq!(parsed.text, "rust 编程");
+        assert_eq!(parsed.emotion_filter, Some(SimHash::EMOTION_JOY));
+        assert_eq!(parsed.time_filter, Some((date_timestamp(2024, 1, 1), date_timestamp(2025, 1, 1))));
+        assert_eq!(parsed.type_filter, None);
+
+        let parsed = parse_query("type:person after:2023-06 before:2024-01-15 Pero");
+        assert_eq!(parsed.text, "Pero");
+        assert_eq!(parsed.type_filter, Some(SimHash::TYPE_PERSON));
+        assert_eq!(parsed.time_filter, Some((date_timestamp(2023, 6, 1), date_timestamp(2024, 1, 15))));
+
+        // 未知前缀或非法取值保留为自由文本
+        let parsed = parse_query("http://example.com emotion:unknown 旧事");
+        assert_eq!(parsed.text, "http://example.com emotion:unknown 旧事");
+        assert!(!parsed.has_filters());
+
+        // 超出 1970..=9999 的年份不做过滤 (也不会溢出)
+        let parsed = parse_query("time:2147483647 time:1969 x");
+        assert_eq!(parsed.text, "time:2147483647 time:1969 x");
+        assert!(!parsed.has_filters());
+    }
+
+    #[test]
+    fn test_filters_applied_in_retrieve() {
+        let mut engine = crate::core::engine::AdvancedEngine::open_temp("query_filters");
+        engine.add_event(1, "2023年5月1日 和朋友一起去海边玩，非常开心", 0, 0, 0);
+        engine.add_event(2, "2024年5月1日 又去了海边，结果下大雨很难过", 0, 0, 0);
+        engine.add_event(3, "2024年8月3日 在海边开心地捡贝壳", 0, 0, 0);
+        engine.add_background_noise(100..120);
+        engine.compile();
+
+        let ids = |query: &str| {
+            let mut ids: Vec<i64> = engine.retrieve(query, 0, 0.0).iter().map(|&(id, _)| id).filter(|id| *id < 100).collect();
+            ids.sort();
+            ids
+        };
+        assert_eq!(ids("海边"), vec![1, 2, 3]);
+        assert_eq!(ids("time:2024 海边"), vec![2, 3]);
+        assert_eq!(ids("emotion:joy 海边"), vec![1, 3]);
+        assert_eq!(ids("emotion:joy time:2024 海边"), vec![3]);
+        assert_eq!(ids("before:2024-06 海边"), vec![1, 2]);
+    }
+}
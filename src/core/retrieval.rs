@@ -1,31 +1,631 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::hash::{Hash, Hasher};
+use ahash::{AHashMap, AHashSet};
+use twox_hash::XxHash64;
 use triviumdb::database::SearchConfig;
-use crate::core::simhash::SimHash;
-use crate::core::engine::AdvancedEngine;
+use triviumdb::node::SearchHit;
+use rayon::prelude::*;
+use crate::core::config::{BatchParallelism, MultiVectorPooling, ResonanceConfig, ScoreNormalization};
+use crate::core::query::parse_query;
+use crate::core::stemmer;
+use crate::core::simhash::{QueryAnalysis, RegionSimilarities, SimHash, SimHashLayout, SimHashRegion};
+use crate::core::engine::{AdvancedEngine, StorageError};
+
+/// scan_vectors_iter 的堆元素: 按得分排序，同分时 id 小的在前
+#[derive(Debug, Clone, Copy)]
+struct ScoredNode {
+    score: f32,
+    id: i64,
+}
+
+impl PartialEq for ScoredNode {
+    fn eq(&self, other: &Self) -> bool { self.cmp(other) == Ordering::Equal }
+}
+
+impl Eq for ScoredNode {}
+
+impl PartialOrd for ScoredNode {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> { Some(self.cmp(other)) }
+}
+
+impl Ord for ScoredNode {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.score.total_cmp(&other.score).then_with(|| other.id.cmp(&self.id))
+    }
+}
+
+/// 按余弦相似度降序逐个产出 (节点 id, 得分) 的惰性迭代器
+/// 全部得分一次算好后建堆 (O(n))，每次 next 弹出一个 (O(log n))，提前停止时省去完整排序。
+#[derive(Debug, Clone)]
+pub struct VectorScanIter {
+    heap: BinaryHeap<ScoredNode>,
+}
+
+impl Iterator for VectorScanIter {
+    type Item = (i64, f32);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.heap.pop().map(|n| (n.id, n.score))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.heap.len(), Some(self.heap.len()))
+    }
+}
+
+impl ExactSizeIterator for VectorScanIter {}
+
+fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    let (mut dot, mut norm_a, mut norm_b) = (0.0f32, 0.0f32, 0.0f32);
+    for (x, y) in a.iter().zip(b) {
+        dot += x * y;
+        norm_a += x * x;
+        norm_b += y * y;
+    }
+    if norm_a == 0.0 || norm_b == 0.0 { 0.0 } else { dot / (norm_a.sqrt() * norm_b.sqrt()) }
+}
+
+/// 激活的特征关键词及其扩散后的能量
+pub type FeatureActivations = Vec<(String, f32)>;
+
+/// 单个命中的得分构成: score = base_score × decay_factor + 各项共振加成 + serendipity_boost
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct ScoreBreakdown {
+    /// TriviumDB 混合检索得分 (文本 BM25 / 关键词扩散 / 向量相似度的综合，底层不再细分)
+    pub base_score: f32,
+    /// 时间衰减或 future_penalty 系数 (无时间戳时为 1)
+    pub decay_factor: f32,
+    pub semantic_boost: f32,
+    pub temporal_boost: f32,
+    pub location_boost: f32,
+    pub affective_boost: f32,
+    pub type_boost: f32,
+    /// 混沌检索的随机意外加成
+    pub serendipity_boost: f32,
+}
+
+/// 参与多模态共振的查询侧信息
+struct ResonanceQuery<'a> {
+    fp: u64,
+    /// 由查询文本检出的全部类型 (外部给定指纹时为空)
+    types: &'a [u8],
+    emotions: u8,
+}
+
+impl ScoreBreakdown {
+    /// 按分区相似度填入各项共振加成
+    fn resonate(&mut self, layout: &SimHashLayout, resonance: &ResonanceConfig, query: &ResonanceQuery, fp: u64, region: &RegionSimilarities, payload: &serde_json::Value) {
+        let query_fp = query.fp;
+        // 空分区 (相似度为 None) 不参与共振，而不是按 "完全不相似" 计
+        if let Some(sim) = region.semantic { self.semantic_boost = sim * resonance.semantic; }
+        if let Some(sim) = region.temporal && (query_fp & layout.mask(SimHashRegion::Temporal)) != 0 { self.temporal_boost = sim * resonance.temporal; }
+        if let Some(sim) = region.location && (query_fp & layout.mask(SimHashRegion::Location)) != 0 { self.location_boost = sim * resonance.location; }
+        if region.affective_overlap {
+            // 情感位图稀疏，按激活位的 Jaccard 重合度而不是汉明一致度计
+            let overlap = SimHash::similarity_jaccard(query_fp, fp, layout.mask(SimHashRegion::Affective)).unwrap_or(0.0);
+            self.affective_boost = resonance.affective * overlap * affective_affinity(query.emotions, payload);
+        }
+        let type_mask = layout.mask(SimHashRegion::EntityType);
+        if let Some(sim) = region.entity_type && (query_fp & type_mask) != 0 {
+            let sim = query.types.iter()
+                .filter_map(|&t| SimHash::similarity_weighted(layout.with_region(query_fp, SimHashRegion::EntityType, t as u64), fp, type_mask))
+                .fold(sim, f32::max);
+            self.type_boost = sim * resonance.entity_type;
+        }
+    }
+
+    /// 五项共振加成之和
+    fn resonance_total(&self) -> f32 {
+        self.semantic_boost + self.temporal_boost + self.location_boost + self.affective_boost + self.type_boost
+    }
+}
+
+/// retrieve_explained 的结果: 最终得分及其构成
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetrievalHit {
+    pub id: i64,
+    pub score: f32,
+    pub breakdown: ScoreBreakdown,
+}
+
+/// 标签过滤的匹配方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TagMatch {
+    /// 事件须携带全部给定标签
+    All,
+    /// 事件携带任一给定标签即可
+    Any,
+}
+
+/// retrieve 结果中保留哪些类型的节点
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ResultFilter {
+    /// 只返回事件 (默认)
+    #[default]
+    EventsOnly,
+    /// 只返回特征概念，按扩散后的能量排序 (标签云 / 实体抽取)
+    FeaturesOnly,
+    /// 事件与特征混合排序
+    Both,
+}
+
+impl ResultFilter {
+    fn accepts(self, payload: &serde_json::Value) -> bool {
+        let node_type = payload.get("type").and_then(|v| v.as_str());
+        match self {
+            ResultFilter::EventsOnly => node_type == Some("event"),
+            ResultFilter::FeaturesOnly => node_type == Some("feature"),
+            ResultFilter::Both => matches!(node_type, Some("event" | "feature")),
+        }
+    }
+}
+
+/// retrieve_outcome 的结果: 区分 "有相关记忆" 与 "没有任何结果达到相关性下限"
+#[derive(Debug, Clone, PartialEq)]
+pub enum RetrieveOutcome {
+    Results(Vec<(i64, f32)>),
+    NoRelevantResults,
+    /// 查询为空或仅含空白，未执行检索
+    EmptyQuery,
+}
+
+/// retrieve_diverse 中同一社群每多选中一条，后续候选得分乘以该系数
+const COMMUNITY_PENALTY: f32 = 0.5;
+
+/// retrieve_diverse 的分组键: 社群编号与不在社群表中的单节点分属不同变体，互不冲突
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum CommunityKey {
+    Cluster(usize),
+    Singleton(i64),
+}
+
+/// retrieve_with_seeds 中种子能量沿边扩散的每跳衰减
+const SEED_HOP_DECAY: f32 = 0.85;
+
+/// 文本引擎的 BM25 按哈希表顺序累加各词元得分，末位浮点误差随调用变化；量化到 1/65536 后再参与融合排序
+const TEXT_SCORE_QUANTUM: f32 = 65536.0;
+
+/// 混合检索的文本通道最多取 top_k 的这么多倍候选，再与向量得分融合后截断为锚点
+const TEXT_CANDIDATE_MULTIPLE: usize = 4;
+
+/// 情感共振系数 [0, 1]: 事件带有 emotion_intensity 时取与查询共有的情感轴上 1 - 0.4^命中次数 的最大值
+/// (命中 1 次 0.6、2 次 0.84 ...)，使强烈的情感比一笔带过的共振更强；否则按位图重叠记为 1
+fn affective_affinity(query_emotions: u8, payload: &serde_json::Value) -> f32 {
+    let Some(intensity) = payload.get("emotion_intensity").and_then(|v| v.as_array()) else { return 1.0; };
+    intensity.iter().enumerate()
+        .filter(|&(axis, _)| axis < 8 && query_emotions & (1 << axis) != 0)
+        .filter_map(|(_, hits)| hits.as_f64())
+        .map(|hits| 1.0 - 0.4f32.powf(hits as f32))
+        .fold(0.0, f32::max)
+}
+
+/// 结果排序: 得分降序，同分按节点 id 升序，保证多次检索顺序稳定
+fn rank_order(a: &SearchHit, b: &SearchHit) -> std::cmp::Ordering {
+    b.score.partial_cmp(&a.score).unwrap_or(std::cmp::Ordering::Equal).then(a.id.cmp(&b.id))
+}
 
 impl AdvancedEngine {
     pub fn retrieve(&self, query: &str, ref_time: u64, chaos_level: f32) -> Vec<(i64, f32)> {
+        self.retrieve_typed(query, ref_time, chaos_level, ResultFilter::EventsOnly)
+    }
+
+    /// 同 retrieve，但每个结果附带得分构成 (用于排查精度回退)，顺序与得分与 retrieve 一致
+    pub fn retrieve_explained(&self, query: &str, ref_time: u64, chaos_level: f32) -> Vec<RetrievalHit> {
+        let (mut hits, breakdowns) = self.scored_hits_explained(query, None, ref_time, chaos_level, None, &self.config.resonance);
+        hits.retain(|h| ResultFilter::EventsOnly.accepts(&h.payload));
+        self.rerank(&hits).into_iter()
+            .map(|(id, score)| RetrievalHit { id, score, breakdown: breakdowns.get(&(id as u64)).copied().unwrap_or_default() })
+            .collect()
+    }
+
+    /// 同 retrieve，但本次检索使用给定的共振权重 (不修改 config.resonance)，便于对比不同权重方案
+    pub fn retrieve_with_config(&self, query: &str, ref_time: u64, chaos_level: f32, resonance: &ResonanceConfig) -> Vec<(i64, f32)> {
+        let (mut hits, _) = self.scored_hits_explained(query, None, ref_time, chaos_level, None, resonance);
+        hits.retain(|h| ResultFilter::EventsOnly.accepts(&h.payload));
+        self.rerank(&hits)
+    }
+
+    /// 同 retrieve，但直接使用外部给定的多模态指纹 (如外部打标器产出)，跳过由查询文本推断指纹的步骤
+    /// query 仍用于关键词匹配 (AC 自动机)、BM25 与向量检索；只有关键词时以空格拼接传入即可。
+    pub fn retrieve_by_fingerprint(&self, query_fp: u64, query: &str, ref_time: u64, chaos_level: f32) -> Vec<(i64, f32)> {
+        let (mut hits, _) = self.scored_hits_explained(query, Some(query_fp), ref_time, chaos_level, None, &self.config.resonance);
+        hits.retain(|h| ResultFilter::EventsOnly.accepts(&h.payload));
+        self.rerank(&hits)
+    }
+
+    /// 纯指纹检索基线: 不跑文本 / 向量检索与图扩散，只按多模态指纹共振 (各项加成之和) 给全部事件排序
+    /// 查询文本仅用于推断指纹，不做时间衰减与 DPP 重排；返回得分为正的前 k 个事件，供精度评测对照。
+    pub fn retrieve_fingerprint_only(&self, query: &str, ref_time: u64, k: usize) -> Vec<(i64, f32)> {
+        if query.trim().is_empty() || k == 0 { return Vec::new(); }
         let query_lower = query.to_lowercase();
+        let query_types = SimHash::detect_types(&query_lower);
+        let query_fp = self.infer_query_fingerprint(&query_lower, ref_time);
+        let layout = &self.config.simhash_layout;
+        let query_signal = ResonanceQuery { fp: query_fp, types: &query_types, emotions: layout.extract(query_fp, SimHashRegion::Affective) as u8 };
+
+        let events: Vec<(u64, u64, serde_json::Value)> = self.tdb.all_node_ids().into_iter().filter_map(|id| {
+            let payload = self.tdb.get_payload(id)?;
+            if payload.get("type").and_then(|v| v.as_str()) != Some("event") || !self.passes_emotion_filter(&payload) { return None; }
+            let fp = payload.get("fingerprint")?.as_u64()?;
+            Some((id, fp, payload))
+        }).collect();
+        let fps: Vec<u64> = events.iter().map(|(_, fp, _)| *fp).collect();
+        let regions = layout.batch_region_similarity(query_fp, &fps);
+
+        let mut results: Vec<(i64, f32)> = events.iter().zip(&regions).filter_map(|((id, fp, payload), region)| {
+            let mut b = ScoreBreakdown::default();
+            b.resonate(layout, &self.config.resonance, &query_signal, *fp, region, payload);
+            let score = b.resonance_total();
+            (score > 0.0).then_some((*id as i64, score))
+        }).collect();
+        results.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+        results.truncate(k);
+        results
+    }
+
+    /// 同 retrieve，但可指定返回事件、特征概念或两者
+    pub fn retrieve_typed(&self, query: &str, ref_time: u64, chaos_level: f32, result_types: ResultFilter) -> Vec<(i64, f32)> {
+        self.retrieve_limited(query, ref_time, chaos_level, result_types, usize::MAX)
+    }
+
+    fn retrieve_limited(&self, query: &str, ref_time: u64, chaos_level: f32, result_types: ResultFilter, limit: usize) -> Vec<(i64, f32)> {
+        let mut hits = self.scored_hits(query, ref_time, chaos_level);
+        hits.retain(|h| result_types.accepts(&h.payload));
+        self.rerank_limited(&hits, limit)
+    }
+
+    /// 同 retrieve，但随机意外加成 (`config.serendipity`) 使用给定种子，相同种子的结果可复现
+    pub fn retrieve_seeded(&self, query: &str, ref_time: u64, chaos_level: f32, seed: u64) -> Vec<(i64, f32)> {
+        let mut hits = self.scored_hits_seeded(query, ref_time, chaos_level, Some(seed));
+        hits.retain(|h| ResultFilter::EventsOnly.accepts(&h.payload));
+        self.rerank(&hits)
+    }
 
+    /// 同 retrieve，但只返回携带指定标签的事件 (标签大小写不敏感)
+    pub fn retrieve_tagged(&self, query: &str, ref_time: u64, chaos_level: f32, tag_filter: &[String], mode: TagMatch) -> Vec<(i64, f32)> {
+        let mut hits = self.scored_hits(query, ref_time, chaos_level);
+        hits.retain(|h| ResultFilter::EventsOnly.accepts(&h.payload));
+        if !tag_filter.is_empty() {
+            let sets: Vec<ahash::AHashSet<i64>> = tag_filter.iter()
+                .map(|t| self.tag_index.get(&t.to_lowercase()).map(|ids| ids.iter().copied().collect()).unwrap_or_default())
+                .collect();
+            hits.retain(|h| {
+                let id = h.id as i64;
+                match mode {
+                    TagMatch::All => sets.iter().all(|s| s.contains(&id)),
+                    TagMatch::Any => sets.iter().any(|s| s.contains(&id)),
+                }
+            });
+        }
+        self.rerank(&hits)
+    }
+
+    /// 按社群分散的检索 (社群粒度的最大边际相关): 每次贪心选出调整后得分最高的事件，
+    /// 随后同社群的剩余候选得分乘以 COMMUNITY_PENALTY，避免结果集中在同一个话题簇。
+    /// 社群划分来自 detect_communities，每次调用都会在全图上重新计算。
+    pub fn retrieve_diverse(&self, query: &str, ref_time: u64, chaos_level: f32, limit: usize) -> Vec<(i64, f32)> {
+        let mut hits = self.scored_hits(query, ref_time, chaos_level);
+        hits.retain(|h| ResultFilter::EventsOnly.accepts(&h.payload));
+        let communities = self.detect_communities();
+        // 不在社群表中的节点各自成簇
+        let community_of = |id: i64| communities.get(&id).map_or(CommunityKey::Singleton(id), |&c| CommunityKey::Cluster(c));
+
+        let mut picked_per_community: AHashMap<CommunityKey, i32> = AHashMap::new();
+        let mut remaining: Vec<(i64, f32)> = hits.iter().map(|h| (h.id as i64, h.score)).collect();
+        let mut results = Vec::with_capacity(limit.min(remaining.len()));
+        while results.len() < limit && !remaining.is_empty() {
+            let adjusted = |&(id, score): &(i64, f32)| {
+                score * COMMUNITY_PENALTY.powi(picked_per_community.get(&community_of(id)).copied().unwrap_or(0))
+            };
+            let best = remaining.iter().enumerate()
+                .max_by(|(_, a), (_, b)| adjusted(a).total_cmp(&adjusted(b)).then(b.0.cmp(&a.0)))
+                .map(|(i, _)| i)
+                .unwrap();
+            let (id, score) = remaining.remove(best);
+            *picked_per_community.entry(community_of(id)).or_insert(0) += 1;
+            results.push((id, score));
+        }
+        results
+    }
+
+    /// 带上下文种子的检索 (多轮对话记忆): seeds 为上一轮结果等节点及其初始能量，
+    /// 能量沿出边扩散 `config.diffusion_depth` 跳 (每跳乘以边强度与 SEED_HOP_DECAY，抑制边取负)，
+    /// 叠加到本轮查询的得分上，使与上下文相连的记忆排名上升。最多返回 limit 条。
+    pub fn retrieve_with_seeds(&self, query: &str, ref_time: u64, chaos_level: f32, seeds: &[(i64, f32)], limit: usize) -> Vec<(i64, f32)> {
+        let mut hits = self.scored_hits(query, ref_time, chaos_level);
+        let now = if ref_time > 0 { ref_time } else { self.config.decay_reference_time };
+        let mut activation = self.seed_activation(seeds, now);
+        for hit in &mut hits {
+            if let Some(energy) = activation.remove(&hit.id) {
+                hit.score += energy;
+            }
+        }
+        for (id, energy) in activation {
+            if energy > 0.0 && let Some(payload) = self.tdb.get_payload(id) {
+                hits.push(SearchHit { id, score: energy, payload });
+            }
+        }
+        hits.retain(|h| ResultFilter::EventsOnly.accepts(&h.payload) && h.score >= self.config.min_score && self.passes_emotion_filter(&h.payload));
+        hits.sort_by(rank_order);
+        let mut results = self.rerank(&hits);
+        results.truncate(limit);
+        results
+    }
+
+    /// 种子能量扩散: 返回 节点 -> 累计能量 (含种子自身)，边强度按 now 时刻的有效强度计
+    /// 每一跳并行读取前沿节点的出边，按前沿顺序合并贡献后串行累加，结果与线程数无关。
+    /// 已激活过的节点不再接收扩散能量，等价边等双向关系不会把能量弹回种子。
+    fn seed_activation(&self, seeds: &[(i64, f32)], now: u64) -> AHashMap<u64, f32> {
+        let mut total: AHashMap<u64, f32> = AHashMap::new();
+        let mut frontier: Vec<(u64, f32)> = seeds.iter()
+            .filter(|&&(id, energy)| id >= 0 && energy > 0.0 && self.tdb.contains(id as u64))
+            .map(|&(id, energy)| (id as u64, energy))
+            .collect();
+        for &(id, energy) in &frontier {
+            *total.entry(id).or_insert(0.0) += energy;
+        }
+        let mut visited: AHashSet<u64> = frontier.iter().map(|&(id, _)| id).collect();
+        for _ in 0..self.config.diffusion_depth {
+            // (目标节点, 能量增量, 是否抑制边)；reduce 按前沿顺序拼接，不改变浮点累加次序
+            let contributions: Vec<(u64, f32, bool)> = frontier.par_iter()
+                .fold(Vec::new, |mut acc, &(node, energy)| {
+                    for (edge, strength) in self.edges_with_strength(node, now) {
+                        let gain = self.config.edge_type_gain.gain_for_label(&edge.label);
+                        let delta = energy * strength * gain * SEED_HOP_DECAY;
+                        acc.push((edge.target_id, delta, edge.label == "inhibition"));
+                    }
+                    acc
+                })
+                .reduce(Vec::new, |mut a, mut b| { a.append(&mut b); a });
+            let mut next: AHashMap<u64, f32> = AHashMap::new();
+            for (target, delta, inhibition) in contributions {
+                if inhibition {
+                    *total.entry(target).or_insert(0.0) -= delta;
+                } else if !visited.contains(&target) {
+                    *next.entry(target).or_insert(0.0) += delta;
+                }
+            }
+            visited.extend(next.keys().copied());
+            for (&id, &energy) in &next {
+                *total.entry(id).or_insert(0.0) += energy;
+            }
+            frontier = next.into_iter().collect();
+            // 哈希表迭代顺序不固定，按 id 排序保证下一跳的累加次序可复现
+            frontier.sort_unstable_by_key(|&(id, _)| id);
+        }
+        total
+    }
+
+    /// 混合检索: 文本 (AC + BM25) 与向量得分相加作为锚点，再沿出边做 PPR 扩散 (与 TriviumDB search_hybrid 同一算法)。
+    /// 锚点截断、扩散累加与侧向抑制都按 (得分, id) 定序，同一查询多次检索的结果与顺序完全一致。
+    /// now 为边时间衰减 (config.edge_decay) 的参照时刻。
+    fn hybrid_search(&self, query: &str, query_vec: Option<&[f32]>, config: &SearchConfig, now: u64) -> Vec<SearchHit> {
+        let mut anchors: AHashMap<u64, f32> = AHashMap::new();
+        if config.enable_text_hybrid_search {
+            // 文本得分不做下限过滤，与向量得分相加后再统一过滤；多取候选，让向量通道能把文本排名靠后的节点拉进锚点
+            let text_config = SearchConfig {
+                top_k: config.top_k.max(15) * TEXT_CANDIDATE_MULTIPLE, expand_depth: 0, min_score: f32::MIN, enable_bq_coarse_search: false, ..*config
+            };
+            for hit in self.tdb.search_hybrid(Some(query), None, &text_config).unwrap_or_default() {
+                anchors.insert(hit.id, (hit.score * TEXT_SCORE_QUANTUM).round() / TEXT_SCORE_QUANTUM);
+            }
+        }
+        if let Some(vec) = query_vec {
+            let vector_config = SearchConfig { expand_depth: 0, enable_text_hybrid_search: false, ..*config };
+            for hit in self.tdb.search_hybrid(None, Some(vec), &vector_config).unwrap_or_default() {
+                *anchors.entry(hit.id).or_insert(0.0) += hit.score;
+            }
+        }
+        let mut seeds: Vec<(u64, f32)> = anchors.into_iter().filter(|&(_, score)| score >= config.min_score).collect();
+        let by_rank = |a: &(u64, f32), b: &(u64, f32)| b.1.partial_cmp(&a.1).unwrap_or(Ordering::Equal).then(a.0.cmp(&b.0));
+        seeds.sort_by(by_rank);
+        seeds.truncate(config.top_k.max(15));
+
+        let activation = self.expand_activation(&seeds, config, now);
+        let mut hits: Vec<SearchHit> = activation.into_iter()
+            .filter_map(|(id, score)| self.tdb.get_payload(id).map(|payload| SearchHit { id, score, payload }))
+            .collect();
+        hits.sort_by(rank_order);
+        hits.truncate(config.top_k);
+        hits
+    }
+
+    /// hybrid_search 的 PPR 扩散: 每跳前沿能量留下 teleport_alpha，其余按边强度 × 边类型系数 (config.edge_type_gain，抑制边取负) 与目标入度惩罚
+    /// 1 / (1 + log10(入度)) 传给邻居；能量不为正的节点不再传播，前沿超过 lateral_inhibition_threshold 时只保留最强的。
+    /// 边强度取 now 时刻的有效强度 (见 effective_edge_strength)。前沿按 id 顺序展开，浮点累加次序固定。入度取引擎维护的 in_degrees。
+    fn expand_activation(&self, seeds: &[(u64, f32)], config: &SearchConfig, now: u64) -> AHashMap<u64, f32> {
+        let mut total: AHashMap<u64, f32> = seeds.iter().copied().collect();
+        if config.expand_depth == 0 || seeds.is_empty() { return total; }
+        let mut frontier: Vec<(u64, f32)> = seeds.to_vec();
+        frontier.sort_unstable_by_key(|&(id, _)| id);
+        for _ in 0..config.expand_depth {
+            let mut next: AHashMap<u64, f32> = AHashMap::new();
+            for &(node, energy) in &frontier {
+                let spread = energy * (1.0 - config.teleport_alpha).max(0.0);
+                if spread <= 0.0 { continue; }
+                for (edge, strength) in self.edges_with_strength(node, now) {
+                    let inhibition_factor = if config.enable_inverse_inhibition {
+                        let in_degree = self.in_degrees.get(&edge.target_id).copied().unwrap_or(0).max(1) as f32;
+                        1.0 / (1.0 + in_degree.log10())
+                    } else { 1.0 };
+                    let gain = self.config.edge_type_gain.gain_for_label(&edge.label);
+                    let transmitted = spread * strength * gain * inhibition_factor;
+                    let transmitted = if edge.label == "inhibition" { -transmitted } else { transmitted };
+                    *next.entry(edge.target_id).or_insert(0.0) += transmitted;
+                    *total.entry(edge.target_id).or_insert(0.0) += transmitted;
+                }
+            }
+            frontier = next.into_iter().filter(|&(_, energy)| energy > 0.0).collect();
+            let threshold = config.lateral_inhibition_threshold;
+            if threshold > 0 && frontier.len() > threshold {
+                frontier.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(Ordering::Equal).then(a.0.cmp(&b.0)));
+                frontier.truncate(threshold);
+            }
+            if frontier.is_empty() { break; }
+            frontier.sort_unstable_by_key(|&(id, _)| id);
+        }
+        total
+    }
+
+    /// 批量检索，每个查询最多返回 limit 条结果 (等于 retrieve 结果的前 limit 条，limit 在重排阶段生效)
+    /// 是否在查询之间并行由 `config.batch_parallelism` 决定 (默认并行)。
+    pub fn retrieve_batch(&self, queries: &[&str], ref_time: u64, chaos_level: f32, limit: usize) -> Vec<Vec<(i64, f32)>> {
+        let run = |query: &&str| self.retrieve_limited(query, ref_time, chaos_level, ResultFilter::EventsOnly, limit);
+        match self.config.batch_parallelism {
+            BatchParallelism::QueryLevel => queries.par_iter().map(run).collect(),
+            BatchParallelism::Sequential => queries.iter().map(run).collect(),
+        }
+    }
+
+    /// 同 retrieve，但在没有结果达到 `config.min_score` 时返回 NoRelevantResults (用于 "我不知道" 式回答)
+    pub fn retrieve_outcome(&self, query: &str, ref_time: u64, chaos_level: f32) -> RetrieveOutcome {
+        if query.trim().is_empty() { return RetrieveOutcome::EmptyQuery; }
+        let results = self.retrieve(query, ref_time, chaos_level);
+        if results.is_empty() { RetrieveOutcome::NoRelevantResults } else { RetrieveOutcome::Results(results) }
+    }
+
+    /// 同 retrieve，但得分按 `config.score_normalization` 映射到 [0, 1]，用于阈值式的相关性判断
+    /// 返回 (节点 id, 归一化得分, 原始得分)，顺序与 retrieve 相同。
+    pub fn retrieve_normalized(&self, query: &str, ref_time: u64, chaos_level: f32) -> Vec<(i64, f32, f32)> {
+        let results = self.retrieve(query, ref_time, chaos_level);
+        // 归一化 = (raw - offset) / range
+        let (offset, range) = match self.config.score_normalization {
+            ScoreNormalization::MaxBoost => {
+                let serendipity = if self.config.serendipity.enabled { self.config.serendipity.max_boost } else { 0.0 };
+                (0.0, 1.0 + self.config.resonance.max_boost() + serendipity)
+            }
+            ScoreNormalization::MinMax => {
+                let max = results.iter().map(|&(_, s)| s).fold(f32::MIN, f32::max);
+                let min = results.iter().map(|&(_, s)| s).fold(f32::MAX, f32::min);
+                // 只有一条或全部同分时都视为 1
+                if max - min > f32::EPSILON { (min, max - min) } else { (min - 1.0, 1.0) }
+            }
+        };
+        results.into_iter().map(|(id, raw)| (id, ((raw - offset) / range).clamp(0.0, 1.0), raw)).collect()
+    }
+
+    /// 同 retrieve，额外返回本次查询激活的特征关键词及其扩散后的能量 (用于命中解释 / 实体链接)
+    pub fn retrieve_with_activations(&self, query: &str, ref_time: u64, chaos_level: f32) -> (Vec<(i64, f32)>, FeatureActivations) {
+        let (mut events, features): (Vec<SearchHit>, Vec<SearchHit>) = self.scored_hits(query, ref_time, chaos_level)
+            .into_iter()
+            .partition(|h| !ResultFilter::FeaturesOnly.accepts(&h.payload));
+        events.retain(|h| ResultFilter::EventsOnly.accepts(&h.payload));
+        let activations = features.iter()
+            .filter_map(|h| Some((h.payload.get("content")?.as_str()?.to_string(), h.score)))
+            .collect();
+        (self.rerank(&events), activations)
+    }
+
+    /// "更多类似": 以已有节点为种子，返回语义最相近的其他事件 (最多 k 条，按得分降序)
+    /// 得分 = 向量余弦相似度 (种子有向量时) + 语义区 SimHash 相似度 × resonance.semantic。
+    /// 种子不存在时返回空，种子自身不出现在结果中。
+    pub fn similar_nodes(&self, id: i64, k: usize) -> Vec<(i64, f32)> {
+        if id < 0 || k == 0 { return Vec::new(); }
+        let Some(seed) = self.tdb.get(id as u64) else { return Vec::new(); };
+        let seed_fp = seed.payload.get("fingerprint").and_then(|v| v.as_u64());
+        // 未加载模型时写入的是零向量，此时只按指纹排序
+        let cosine: AHashMap<u64, f32> = if seed.vector.iter().any(|&x| x != 0.0) {
+            let config = SearchConfig { top_k: self.tdb.node_count(), expand_depth: 0, min_score: f32::MIN, ..Default::default() };
+            self.tdb.search_hybrid(None, Some(&seed.vector), &config).unwrap_or_default()
+                .into_iter()
+                .filter(|h| h.score.is_finite())
+                .map(|h| (h.id, h.score))
+                .collect()
+        } else { AHashMap::new() };
+
+        let (ids, fps): (Vec<u64>, Vec<u64>) = self.tdb.all_node_ids().into_iter()
+            .filter(|&other| other != seed.id)
+            .filter_map(|other| {
+                let payload = self.tdb.get_payload(other)?;
+                if !ResultFilter::EventsOnly.accepts(&payload) { return None; }
+                Some((other, payload.get("fingerprint").and_then(|v| v.as_u64()).unwrap_or(0)))
+            })
+            .unzip();
+        let regions = match seed_fp {
+            Some(fp) => self.config.simhash_layout.batch_region_similarity(fp, &fps),
+            None => Vec::new(),
+        };
+        let mut hits: Vec<(i64, f32)> = ids.iter().enumerate().map(|(i, &other)| {
+            let semantic = regions.get(i).and_then(|r| r.semantic).unwrap_or(0.0);
+            let score = cosine.get(&other).copied().unwrap_or(0.0) + semantic * self.config.resonance.semantic;
+            (other as i64, score)
+        }).collect();
+        hits.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+        hits.truncate(k);
+        hits
+    }
+
+    /// 混合检索 + 时间衰减 + 多模态共振，返回按得分降序排列的命中
+    fn scored_hits(&self, query: &str, ref_time: u64, chaos_level: f32) -> Vec<SearchHit> {
+        self.scored_hits_seeded(query, ref_time, chaos_level, None)
+    }
+
+    /// seed 为 None 时随机意外加成取时钟作种子 (仅在启用 serendipity 时有影响)
+    fn scored_hits_seeded(&self, query: &str, ref_time: u64, chaos_level: f32, seed: Option<u64>) -> Vec<SearchHit> {
+        self.scored_hits_explained(query, None, ref_time, chaos_level, seed, &self.config.resonance).0
+    }
+
+    /// Step 0: 由查询文本推断多模态指纹 (时间 / 情感 / 类型启发式，启用 gliner 时用 NER)
+    fn infer_query_fingerprint(&self, query_lower: &str, ref_time: u64) -> u64 {
         #[cfg(feature = "gliner")]
-        let query_fp = if let Some(gliner) = &self.gliner_engine {
-            let (type_e, time_e) = gliner.extract_all(&query_lower);
+        if let Some(gliner) = &self.gliner_engine {
+            let (type_e, time_e) = gliner.extract_all(query_lower);
             let type_val = crate::ml::gliner_ner::best_type_val(&type_e);
             let timestamp = crate::ml::gliner_ner::best_timestamp(&time_e, ref_time);
-            let emotion = SimHash::extract_emotion(&query_lower);
-            SimHash::compute_multimodal(&query_lower, timestamp, emotion, type_val)
-        } else { SimHash::compute_for_query(&query_lower, ref_time) };
-        
-        #[cfg(not(feature = "gliner"))]
-        let query_fp = SimHash::compute_for_query(&query_lower, ref_time);
+            let emotion = self.emotion_keywords.extract(query_lower);
+            return self.fingerprint(query_lower, timestamp, emotion, type_val);
+        }
+        self.query_fingerprint(query_lower, ref_time)
+    }
 
-        let query_vec_f32 = self.calculate_chaos(query);
+    /// 查询解析结果: 指纹与 retrieve 实际使用的一致 (引擎的布局、IDF 表、情感关键词表，GLiNER 开启时含其推断)，
+    /// 另列出启发式命中的全部实体类型
+    pub fn analyze_query(&self, query: &str, ref_time: u64) -> QueryAnalysis {
+        let layout = &self.config.simhash_layout;
+        let mut analysis = layout.analyze_query(query, ref_time);
+        if query.trim().is_empty() { return analysis; }
+        analysis.fingerprint = self.infer_query_fingerprint(&query.to_lowercase(), ref_time);
+        analysis.dominant_type = layout.extract(analysis.fingerprint, SimHashRegion::EntityType) as u8;
+        analysis.detected_emotions = layout.extract(analysis.fingerprint, SimHashRegion::Affective) as u8;
+        analysis
+    }
+
+    /// 同 scored_hits_seeded，额外返回每个命中的得分构成
+    /// query_fp 为 None 时由查询文本推断指纹 (infer_query_fingerprint)
+    fn scored_hits_explained(&self, query: &str, query_fp: Option<u64>, ref_time: u64, chaos_level: f32, seed: Option<u64>, resonance: &ResonanceConfig) -> (Vec<SearchHit>, AHashMap<u64, ScoreBreakdown>) {
+        // 空查询直接返回，不跑检索管线
+        if query.trim().is_empty() { return (Vec::new(), AHashMap::new()); }
+        if self.is_text_index_stale() && !self.stale_warned.swap(true, std::sync::atomic::Ordering::Relaxed) {
+            println!("⚠️ [Retrieve] 文本索引已过期，compile 或 refresh_text_index 之前新增的关键词 / 事件不会被文本检索命中");
+        }
+        // 字段过滤前缀 (emotion: / time: / type: ...) 作为硬过滤，剩余文本正常扩散
+        let parsed = parse_query(query);
+        let expanded;
+        let query = if self.config.english_stemming {
+            expanded = stemmer::expand_query(&parsed.text);
+            expanded.as_str()
+        } else { parsed.text.as_str() };
+        let query_lower = query.to_lowercase();
+
+        // 由文本推断指纹时记下命中的全部类型，类型加成取其中与节点最吻合的一个
+        let query_types = if query_fp.is_none() { SimHash::detect_types(&query_lower) } else { Vec::new() };
+        let query_fp = query_fp.unwrap_or_else(|| self.infer_query_fingerprint(&query_lower, ref_time));
+
+        // 词干扩展只服务于关键词 / BM25 匹配，向量仍对用户原文编码，避免拼接的词干稀释语义
+        let query_vec_f32 = self.calculate_chaos(&parsed.text);
+        let bq_candidate_ratio = match &query_vec_f32 {
+            Some(vec) if chaos_level > 0.0 && self.config.hybrid_scan.adaptive => self.adaptive_l1_ratio(vec, 200).0,
+            _ => self.config.hybrid_scan.l1_candidate_ratio,
+        };
         let config = SearchConfig {
             top_k: 200, 
-            expand_depth: 2,
+            expand_depth: self.config.diffusion_depth,
             min_score: 0.1,
             teleport_alpha: 0.15,
             enable_advanced_pipeline: true,
             enable_bq_coarse_search: chaos_level > 0.0,
+            bq_candidate_ratio,
             text_boost: 1.5,
             enable_text_hybrid_search: true, // Native fast search
             enable_inverse_inhibition: true, // Native inverse inhibition
@@ -34,52 +634,212 @@ impl AdvancedEngine {
             ..Default::default()
         };
 
-        let mut hits = self.tdb.search_hybrid(
-            Some(query), 
-            query_vec_f32.as_deref(), 
-            &config
-        ).unwrap_or_default();
+        let current_decay_time = if ref_time > 0 { ref_time } else { self.config.decay_reference_time };
+        let mut hits = self.hybrid_search(query, query_vec_f32.as_deref(), &config, current_decay_time);
 
-        let current_decay_time = if ref_time > 0 { ref_time } else { 1777593600 }; 
-        let tau = 31536000.0;
+        if self.config.multi_vector {
+            hits = self.fold_chunk_hits(hits);
+        }
+        if parsed.has_filters() {
+            hits.retain(|h| parsed.matches_with_layout(&h.payload, &self.config.simhash_layout));
+        }
 
         // V2 Temporal Decay & Multimodal Resonance
-        for hit in &mut hits {
-            if let Some(timestamp) = hit.payload.get("timestamp").and_then(|v| v.as_u64()) {
-                if timestamp > 0 && timestamp < current_decay_time {
+        let fingerprints: Vec<Option<u64>> = hits.iter().map(|h| h.payload.get("fingerprint").and_then(|v| v.as_u64())).collect();
+        let flat_fps: Vec<u64> = fingerprints.iter().map(|fp| fp.unwrap_or(0)).collect();
+        let layout = &self.config.simhash_layout;
+        let regions = layout.batch_region_similarity(query_fp, &flat_fps);
+        let query_signal = ResonanceQuery { fp: query_fp, types: &query_types, emotions: layout.extract(query_fp, SimHashRegion::Affective) as u8 };
+
+        let mut breakdowns: AHashMap<u64, ScoreBreakdown> = AHashMap::with_capacity(hits.len());
+        for ((hit, fp), region) in hits.iter_mut().zip(&fingerprints).zip(&regions) {
+            let mut b = ScoreBreakdown { base_score: hit.score, decay_factor: 1.0, ..Default::default() };
+            if let Some(timestamp) = hit.payload.get("timestamp").and_then(|v| v.as_u64())
+                && timestamp > 0 {
+                if timestamp < current_decay_time {
                     let delta_t = (current_decay_time - timestamp) as f32;
-                    let decay_factor = (-delta_t / tau).exp();
-                    hit.score *= decay_factor.max(0.8);
+                    let decay_factor = (-delta_t / resonance.decay_tau).exp();
+                    b.decay_factor = decay_factor.max(resonance.decay_floor);
+                } else if timestamp > current_decay_time {
+                    // 尚未发生的事件: 不衰减，按 future_penalty 折算
+                    b.decay_factor = self.config.future_penalty;
                 }
+                hit.score *= b.decay_factor;
             }
-            if let Some(fp) = hit.payload.get("fingerprint").and_then(|v| v.as_u64()) {
-                let semantic_sim = SimHash::similarity_weighted(query_fp, fp, SimHash::MASK_SEMANTIC);
-                let mut boost = semantic_sim * 0.6;
-                if (query_fp & SimHash::MASK_TEMPORAL) != 0 { boost += SimHash::similarity_weighted(query_fp, fp, SimHash::MASK_TEMPORAL) * 0.5; }
-                if (query_fp & SimHash::MASK_AFFECTIVE) != 0 {
-                    if ((query_fp & SimHash::MASK_AFFECTIVE) >> 48) & ((fp & SimHash::MASK_AFFECTIVE) >> 48) != 0 { boost += 0.6; }
-                }
-                if (query_fp & SimHash::MASK_TYPE) != 0 { boost += SimHash::similarity_weighted(query_fp, fp, SimHash::MASK_TYPE) * 0.8; }
-                
-                hit.score += boost;
+            if let Some(fp) = *fp {
+                b.resonate(layout, resonance, &query_signal, fp, region, &hit.payload);
+                hit.score += b.resonance_total();
             }
+            breakdowns.insert(hit.id, b);
         }
         
-        hits.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap());
+        if self.config.serendipity.enabled && chaos_level > 0.0 {
+            let seed = seed.unwrap_or_else(|| {
+                std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map(|d| d.as_nanos() as u64).unwrap_or(0)
+            });
+            let before: AHashMap<u64, f32> = hits.iter().map(|h| (h.id, h.score)).collect();
+            for id in self.inject_serendipity(&mut hits, chaos_level, seed) {
+                if let (Some(b), Some(hit)) = (breakdowns.get_mut(&id), hits.iter().find(|h| h.id == id)) {
+                    b.serendipity_boost = hit.score - before[&id];
+                }
+            }
+        }
+
+        hits.retain(|h| h.score >= self.config.min_score && self.passes_emotion_filter(&h.payload));
+        hits.sort_by(rank_order);
+        (hits, breakdowns)
+    }
+
+    /// `config.require_emotions` 情感硬过滤: 非事件节点与掩码为 0 时总是通过
+    fn passes_emotion_filter(&self, payload: &serde_json::Value) -> bool {
+        let required = self.config.require_emotions as u64;
+        if required == 0 || payload.get("type").and_then(|v| v.as_str()) != Some("event") { return true; }
+        payload.get("fingerprint").and_then(|v| v.as_u64())
+            .is_some_and(|fp| self.config.simhash_layout.extract(fp, SimHashRegion::Affective) & required != 0)
+    }
+
+    /// 随机意外加成: 以 chaos_level 的概率触发，给若干随机候选加一个小的正向得分
+    /// 随机数由 (seed, 节点 id) 哈希得出，与候选顺序无关，相同种子结果相同。
+    fn inject_serendipity(&self, hits: &mut [SearchHit], chaos_level: f32, seed: u64) -> Vec<u64> {
+        let unit = |id: u64, salt: u64| {
+            let mut hasher = XxHash64::with_seed(seed);
+            (id, salt).hash(&mut hasher);
+            (hasher.finish() >> 40) as f32 / (1u64 << 24) as f32
+        };
+        let chaos = chaos_level.clamp(0.0, 1.0);
+        if unit(u64::MAX, 0) >= chaos {
+            return Vec::new();
+        }
+        let mut picks: Vec<usize> = (0..hits.len()).collect();
+        picks.sort_by(|&a, &b| unit(hits[a].id, 1).total_cmp(&unit(hits[b].id, 1)));
+        picks.truncate(self.config.serendipity.picks);
+        picks.iter().map(|&i| {
+            let hit = &mut hits[i];
+            hit.score += self.config.serendipity.max_boost * chaos * unit(hit.id, 2);
+            hit.id
+        }).collect()
+    }
 
+    /// DPP 多样性重排，重排后的完整列表再按 `config.max_results` 截断 (截断结果总是不限条数时的前缀)
+    fn rerank(&self, hits: &[SearchHit]) -> Vec<(i64, f32)> {
+        self.rerank_limited(hits, usize::MAX)
+    }
+
+    /// 同 rerank，但最多保留 limit 条: 贪心 DPP 的前 k 个选择与 k 无关，只需选出 min(limit, 10) 个，结果仍是 rerank 的前缀
+    fn rerank_limited(&self, hits: &[SearchHit], limit: usize) -> Vec<(i64, f32)> {
+        let limit = limit.min(self.config.max_results.unwrap_or(usize::MAX));
         // Native PEDSA DPP Greedy
-        let results: Vec<(i64, f32)> = hits.iter().map(|h| (h.id as i64, h.score)).collect();
+        let mut results: Vec<(i64, f32)> = hits.iter().map(|h| (h.id as i64, h.score)).collect();
         if results.len() > 10 {
-            let dpp_candidates = results.len().min(50);
-            let selected = self.dpp_greedy_select(&hits[..dpp_candidates], 10);
+            let dpp_candidates = results.len().min(self.config.refinement_top_n);
+            // 窗口不超过 10 时 dpp_greedy_select 原样返回，不能缩小 k
+            let k = if dpp_candidates > 10 { limit.clamp(1, 10) } else { 10 };
+            let selected = self.dpp_greedy_select(&hits[..dpp_candidates], k);
             let mut dpp_results: Vec<(i64, f32)> = selected.iter().map(|&i| results[i]).collect();
-            for item in results.iter().skip(dpp_candidates) { dpp_results.push(*item); }
+            // 窗口内未被 DPP 选中的候选与窗口外的候选都按原有顺序接在后面
+            dpp_results.extend((0..dpp_candidates).filter(|i| !selected.contains(i)).map(|i| results[i]));
+            dpp_results.extend_from_slice(&results[dpp_candidates..]);
+            dpp_results.truncate(limit);
             return dpp_results;
         }
 
+        results.truncate(limit);
         results
     }
 
+    /// 纯向量的 BQ 两段式扫描 (L1 汉明粗筛 + L2 余弦精排)，遵循 `config.hybrid_scan`
+    /// 返回命中与实际使用的 L1 候选数，便于调参。查询向量维度与存储不一致时返回 DimensionMismatch。
+    pub fn scan_vectors(&self, query_vec: &[f32], top_k: usize) -> Result<(Vec<SearchHit>, usize), StorageError> {
+        if query_vec.len() != self.vector_dim() {
+            return Err(StorageError::DimensionMismatch { expected: self.vector_dim(), found: query_vec.len() });
+        }
+        if self.config.hybrid_scan.adaptive {
+            let (ratio, hits) = self.adaptive_l1_ratio(query_vec, top_k);
+            return Ok((hits, self.l1_candidate_count(ratio, top_k)));
+        }
+        let ratio = self.config.hybrid_scan.l1_candidate_ratio;
+        Ok((self.bq_scan(query_vec, top_k, ratio), self.l1_candidate_count(ratio, top_k)))
+    }
+
+    /// 精确扫描全部节点向量 (不做 BQ 粗筛)，按余弦相似度降序惰性产出 (节点 id, 得分)
+    /// 适合 K 很大或按阈值截断的场景: `.take_while(|&(_, s)| s > 0.7)`。查询向量维度与存储不一致时返回 DimensionMismatch。
+    pub fn scan_vectors_iter(&self, query_vec: &[f32]) -> Result<VectorScanIter, StorageError> {
+        if query_vec.len() != self.vector_dim() {
+            return Err(StorageError::DimensionMismatch { expected: self.vector_dim(), found: query_vec.len() });
+        }
+        let heap = self.tdb.all_node_ids().into_iter()
+            .filter_map(|id| {
+                let node = self.tdb.get(id)?;
+                Some(ScoredNode { score: cosine_similarity(query_vec, &node.vector), id: id as i64 })
+            })
+            .collect();
+        Ok(VectorScanIter { heap })
+    }
+
+    /// 自适应 L1: 候选比例逐轮翻倍，直到第 k 名得分稳定或已扫描全量，返回 (比例, 该比例下的命中)
+    fn adaptive_l1_ratio(&self, query_vec: &[f32], top_k: usize) -> (f32, Vec<SearchHit>) {
+        let kth = |hits: &[SearchHit]| hits.get(top_k.saturating_sub(1)).or(hits.last()).map(|h| h.score);
+        let mut ratio = self.config.hybrid_scan.l1_candidate_ratio.clamp(1e-4, 1.0);
+        let mut hits = self.bq_scan(query_vec, top_k, ratio);
+        while ratio < 1.0 {
+            let next_ratio = (ratio * 2.0).min(1.0);
+            let next_hits = self.bq_scan(query_vec, top_k, next_ratio);
+            let stable = kth(&hits) == kth(&next_hits);
+            ratio = next_ratio;
+            hits = next_hits;
+            if stable { break; }
+        }
+        (ratio, hits)
+    }
+
+    fn bq_scan(&self, query_vec: &[f32], top_k: usize, ratio: f32) -> Vec<SearchHit> {
+        let config = SearchConfig {
+            top_k,
+            min_score: f32::MIN,
+            enable_bq_coarse_search: true,
+            bq_candidate_ratio: ratio,
+            ..Default::default()
+        };
+        self.tdb.search_hybrid(None, Some(query_vec), &config).unwrap_or_default()
+    }
+
+    fn l1_candidate_count(&self, ratio: f32, top_k: usize) -> usize {
+        ((self.tdb.node_count() as f32 * ratio).ceil() as usize).max(top_k)
+    }
+
+    /// 多向量模式: 把 chunk 命中折叠回父事件
+    /// 事件得分 = max(事件自身得分, 分块汇总得分)，分块按 Max / Sum 汇总。
+    fn fold_chunk_hits(&self, hits: Vec<SearchHit>) -> Vec<SearchHit> {
+        let mut pooled: AHashMap<u64, f32> = AHashMap::new();
+        let mut folded = Vec::with_capacity(hits.len());
+        for hit in hits {
+            if hit.payload.get("type").and_then(|v| v.as_str()) == Some("chunk") {
+                if let Some(parent) = hit.payload.get("parent").and_then(|v| v.as_u64()) {
+                    let entry = pooled.entry(parent).or_insert(0.0);
+                    match self.config.multi_vector_pooling {
+                        MultiVectorPooling::Max => *entry = entry.max(hit.score),
+                        MultiVectorPooling::Sum => *entry += hit.score,
+                    }
+                }
+            } else {
+                folded.push(hit);
+            }
+        }
+        for hit in &mut folded {
+            if let Some(chunk_score) = pooled.remove(&hit.id) {
+                hit.score = hit.score.max(chunk_score);
+            }
+        }
+        // 仅靠分块命中的父事件
+        for (parent, score) in pooled {
+            if let Some(payload) = self.tdb.get_payload(parent) {
+                folded.push(SearchHit { id: parent, score, payload });
+            }
+        }
+        folded.sort_by(rank_order);
+        folded
+    }
+
     fn dpp_greedy_select(&self, candidates: &[triviumdb::node::SearchHit], k: usize) -> Vec<usize> {
         let n = candidates.len();
         if n <= k { return (0..n).collect(); }
@@ -92,23 +852,801 @@ impl AdvancedEngine {
 
         for j in 0..k {
             let mut best = 0; let mut best_val = f32::NEG_INFINITY;
-            for i in 0..n {
-                if !selected.contains(&i) && diag[i] > best_val { best_val = diag[i]; best = i; }
+            for (i, &d) in diag.iter().enumerate() {
+                if !selected.contains(&i) && d > best_val { best_val = d; best = i; }
             }
             selected.push(best);
             if j == k - 1 || diag[best] < 1e-10 { break; }
 
             let fp_best = fingerprints[best];
+            let semantic_mask = self.config.simhash_layout.mask(SimHashRegion::Semantic);
             let q_best = quality[best];
+            // 先取出主元范数: 下方循环会在 i == best 时把 diag[best] 清零
+            let norm_best = diag[best].sqrt();
 
             for i in 0..n {
-                let sim = 1.0 - ((fp_best & SimHash::MASK_SEMANTIC) ^ (fingerprints[i] & SimHash::MASK_SEMANTIC)).count_ones() as f32 / 32.0;
+                let sim = SimHash::similarity_weighted(fp_best, fingerprints[i], semantic_mask).unwrap_or(0.0);
                 let mut c_j_i = q_best * sim * quality[i];
-                for p in 0..j { c_j_i -= c[p][best] * c[p][i]; }
-                c[j][i] = c_j_i / diag[best].sqrt();
+                for row in c.iter().take(j) { c_j_i -= row[best] * row[i]; }
+                c[j][i] = c_j_i / norm_best;
                 diag[i] = (diag[i] - c[j][i] * c[j][i]).max(0.0);
             }
         }
         selected
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::config::EngineConfig;
+
+    #[test]
+    fn test_refinement_top_n_widens_rerank_window() {
+        let mut engine = AdvancedEngine::open_temp("refinement_top_n");
+        // 60 条内容完全相同的旧事 (指纹一致，DPP 视为冗余)
+        for id in 1..=60 {
+            engine.add_event(id, "一件旧事", 0, 0, 0);
+        }
+        // 无关的背景事件，保证 BM25 的 IDF 有区分度
+        engine.add_background_noise(1000..1200);
+        // 一条语义不同但原始得分靠后的事件 (更长的文本 + 更久远的时间)
+        engine.add_event(100, "很多年前那个夏天在海边发生的另一件完全不同的旧事", 1000, 0, 0);
+        engine.compile();
+
+        let position = |results: &[(i64, f32)]| results.iter().position(|&(id, _)| id == 100).unwrap();
+
+        engine.config.refinement_top_n = 50;
+        let narrow = engine.retrieve("旧事", 0, 0.0);
+        engine.config.refinement_top_n = 80;
+        let wide = engine.retrieve("旧事", 0, 0.0);
+
+        // 窗口大小只影响头部的重排，不会丢弃候选
+        let ids = |results: &[(i64, f32)]| {
+            let mut ids: Vec<i64> = results.iter().map(|&(id, _)| id).collect();
+            ids.sort_unstable();
+            ids
+        };
+        assert_eq!(ids(&narrow), ids(&wide));
+        // 窄窗口下事件 100 排在窗口之外，只能留在原位；宽窗口把它纳入 DPP，被选进前 10
+        let (narrow_rank, wide_rank) = (position(&narrow), position(&wide));
+        assert!(narrow_rank >= 50, "narrow {} wide {}", narrow_rank, wide_rank);
+        assert!(wide_rank < 10, "narrow {} wide {}", narrow_rank, wide_rank);
+    }
+
+    #[test]
+    fn test_retrieve_with_activations_reports_features() {
+        let mut engine = AdvancedEngine::open_temp("activations");
+        engine.maintain_ontology("女孩", "Pero", "equality", 1.0);
+        let pero = engine.get_or_create_feature("Pero");
+        engine.add_event(1, "Pero 今天换了一条蓝色的裙子", 0, 0, 0);
+        engine.add_event(2, "和 Pero 一起去看了烟花", 0, 0, 0);
+        engine.add_edge(pero, 1, 1.0);
+        engine.add_edge(pero, 2, 1.0);
+        engine.add_background_noise(100..120);
+        engine.compile();
+
+        let (results, activations) = engine.retrieve_with_activations("女孩", 0, 0.0);
+        assert!(results.iter().any(|&(id, _)| id == 1), "results: {:?}", results);
+        assert!(results.iter().any(|&(id, _)| id == 2), "results: {:?}", results);
+        assert!(activations.iter().any(|(kw, e)| kw == "女孩" && *e > 0.0), "activations: {:?}", activations);
+        assert!(activations.iter().any(|(kw, e)| kw == "pero" && *e > 0.0), "activations: {:?}", activations);
+        assert_eq!(results, engine.retrieve("女孩", 0, 0.0));
+    }
+
+    #[test]
+    fn test_emotion_intensity_scales_affective_boost() {
+        let counts = SimHash::extract_emotion_weighted("开心开心，结果很满意，但也有点担心");
+        assert_eq!(counts[0], 3.0);
+        assert_eq!(counts[2], 1.0);
+        assert_eq!(counts.iter().sum::<f32>(), 4.0);
+        assert_eq!(SimHash::extract_emotion_weighted_normalized("开心")[0], 50.0);
+
+        let mut engine = AdvancedEngine::open_temp("emotion_intensity");
+        engine.add_event(1, "周末去公园散步，挺开心的", 0, 0, 0);
+        engine.add_event(2, "拿到offer了，开心到飞起，快乐幸福，哈哈哈", 0, 0, 0);
+        engine.add_event(3, "手动标注的喜悦", 0, SimHash::EMOTION_JOY, 0);
+        let affinity = |id: u64| affective_affinity(SimHash::EMOTION_JOY, &engine.tdb.get_payload(id).unwrap());
+        assert!((affinity(1) - 0.6).abs() < 1e-6);
+        assert!(affinity(2) > affinity(1));
+        assert_eq!(affinity(3), 1.0);
+        assert_eq!(affective_affinity(SimHash::EMOTION_FEAR, &engine.tdb.get_payload(2).unwrap()), 0.0);
+    }
+
+    #[test]
+    fn test_affective_boost_uses_jaccard_overlap() {
+        let mut engine = AdvancedEngine::open_temp("affective_jaccard");
+        engine.add_event(1, "海边的日记", 0, SimHash::EMOTION_JOY, 0);
+        engine.add_event(2, "海边的随笔", 0, SimHash::EMOTION_JOY | SimHash::EMOTION_SURPRISE, 0);
+        engine.add_event(3, "海边的记录", 0, SimHash::EMOTION_FEAR, 0);
+        engine.add_background_noise(100..120);
+        engine.compile();
+
+        let hits = engine.retrieve_explained("开心的海边", 0, 0.0);
+        let boost = |id: i64| hits.iter().find(|h| h.id == id).map(|h| h.breakdown.affective_boost).unwrap();
+        let full = engine.config.resonance.affective;
+        assert_eq!(boost(1), full);
+        assert_eq!(boost(2), full * 0.5);
+        assert_eq!(boost(3), 0.0);
+    }
+
+    #[test]
+    fn test_retrieve_fingerprint_only_ranks_by_resonance() {
+        let mut engine = AdvancedEngine::open_temp("fingerprint_only");
+        engine.add_event(1, "海边的日记", 0, SimHash::EMOTION_JOY, 0);
+        engine.add_event(2, "海边的日记", 0, SimHash::EMOTION_FEAR, 0);
+        engine.add_event(3, "在家写 Rust 代码", 0, 0, 0);
+        // 图扩散不参与: 强边不会把事件 3 拉到前面
+        engine.add_edge(1, 3, 1.0);
+        engine.add_edge(2, 3, 1.0);
+        engine.compile();
+
+        let hits = engine.retrieve_fingerprint_only("开心的海边日记", 0, 10);
+        assert_eq!(hits.iter().map(|h| h.0).take(2).collect::<Vec<_>>(), vec![1, 2]);
+        assert!(hits.iter().all(|&(id, _)| engine.node_type(id).as_deref() == Some("event")));
+        assert!(hits.windows(2).all(|w| w[0].1 >= w[1].1));
+        assert_eq!(engine.retrieve_fingerprint_only("开心的海边日记", 0, 1), hits[..1].to_vec());
+        assert!(engine.retrieve_fingerprint_only("  ", 0, 10).is_empty());
+    }
+
+    #[test]
+    fn test_retrieve_explained_matches_retrieve() {
+        let mut engine = AdvancedEngine::open_temp("explained");
+        engine.load_standard_data();
+        engine.compile();
+        for query in ["Rust 内存安全", "上海 开心的事情", "去年 Pero"] {
+            let plain = engine.retrieve(query, 1777593600, 0.0);
+            let explained = engine.retrieve_explained(query, 1777593600, 0.0);
+            assert!(!explained.is_empty(), "{}", query);
+            assert_eq!(explained.iter().map(|h| (h.id, h.score)).collect::<Vec<_>>(), plain, "{}", query);
+            for hit in &explained {
+                let b = hit.breakdown;
+                let total = b.base_score * b.decay_factor + b.semantic_boost + b.temporal_boost + b.location_boost
+                    + b.affective_boost + b.type_boost + b.serendipity_boost;
+                assert!((total - hit.score).abs() < 1e-4, "{} {:?}", query, hit);
+                assert!(b.decay_factor > 0.0 && b.decay_factor <= 1.0);
+            }
+        }
+    }
+
+    #[test]
+    fn test_multi_type_query_boosts_each_type() {
+        let mut engine = AdvancedEngine::open_temp("multi_type");
+        engine.add_event(1, "海边的用户日记", 0, 0, SimHash::TYPE_PERSON);
+        engine.add_event(2, "海边的代码日记", 0, 0, SimHash::TYPE_TECH);
+        engine.add_event(3, "海边的日记", 0, 0, SimHash::TYPE_OBJECT);
+        engine.add_background_noise(100..120);
+        engine.compile();
+
+        let analysis = engine.analyze_query("海边 用户 代码", 0);
+        assert_eq!(analysis.detected_types, vec![SimHash::TYPE_PERSON, SimHash::TYPE_TECH]);
+        assert_eq!(analysis.dominant_type, SimHash::TYPE_PERSON);
+
+        let hits = engine.retrieve_explained("海边 用户 代码", 0, 0.0);
+        let type_boost = |id: i64| hits.iter().find(|h| h.id == id).map(|h| h.breakdown.type_boost).unwrap();
+        let full = engine.config.resonance.entity_type;
+        // 人物与技术两类事件都拿到完整的类型加成，其他类型不受影响
+        assert_eq!(type_boost(1), full);
+        assert_eq!(type_boost(2), full);
+        assert!(type_boost(3) < full);
+    }
+
+    #[test]
+    fn test_retrieve_by_fingerprint_skips_inference() {
+        let mut engine = AdvancedEngine::open_temp("by_fingerprint");
+        engine.add_event(1, "和朋友吃火锅", 0, SimHash::EMOTION_JOY, 0);
+        engine.add_event(2, "和朋友吃火锅", 0, SimHash::EMOTION_FEAR, 0);
+        engine.add_background_noise(100..120);
+        engine.compile();
+
+        let layout = engine.config.simhash_layout;
+        let inferred = engine.query_fingerprint("火锅", 0);
+        assert_eq!(layout.extract(inferred, SimHashRegion::Affective), 0);
+        let with_emotion = |emotion: u8| layout.with_region(inferred, SimHashRegion::Affective, emotion as u64);
+        // 查询文本本身不带情感，外部指纹决定情感共振
+        assert_eq!(engine.retrieve_by_fingerprint(with_emotion(SimHash::EMOTION_JOY), "火锅", 0, 0.0)[0].0, 1);
+        assert_eq!(engine.retrieve_by_fingerprint(with_emotion(SimHash::EMOTION_FEAR), "火锅", 0, 0.0)[0].0, 2);
+
+        let plain = engine.retrieve("火锅", 0, 0.0);
+        let by_fp = engine.retrieve_by_fingerprint(inferred, "火锅", 0, 0.0);
+        assert_eq!(by_fp.iter().map(|&(id, _)| id).collect::<AHashSet<_>>(), plain.iter().map(|&(id, _)| id).collect());
+        assert!(engine.retrieve_by_fingerprint(inferred, "  ", 0, 0.0).is_empty());
+    }
+
+    #[test]
+    fn test_retrieve_with_config_overrides_weights() {
+        let mut engine = AdvancedEngine::open_temp("resonance_config");
+        engine.load_standard_data();
+        engine.compile();
+        let query = "Rust 内存安全";
+        assert_eq!(engine.retrieve_with_config(query, 1777593600, 0.0, &ResonanceConfig::default()), engine.retrieve(query, 1777593600, 0.0));
+
+        // 去掉全部加成且不衰减: 得分退化为底层混合检索得分
+        let flat = ResonanceConfig { semantic: 0.0, temporal: 0.0, location: 0.0, affective: 0.0, entity_type: 0.0, decay_floor: 1.0, ..Default::default() };
+        let base: AHashMap<i64, f32> = engine.retrieve_explained(query, 1777593600, 0.0).iter().map(|h| (h.id, h.breakdown.base_score)).collect();
+        let results = engine.retrieve_with_config(query, 1777593600, 0.0, &flat);
+        assert!(!results.is_empty());
+        for (id, score) in results {
+            if let Some(&b) = base.get(&id) { assert!((score - b).abs() < 1e-5, "{} {} {}", id, score, b); }
+        }
+    }
+
+    #[test]
+    fn test_result_filter_returns_features() {
+        let mut engine = AdvancedEngine::open_temp("result_filter");
+        let rust = engine.get_or_create_feature("Rust");
+        let distributed = engine.get_or_create_feature("分布式");
+        let cooking = engine.get_or_create_feature("烹饪");
+        engine.add_event(1, "用 Rust 写了一个分布式存储", 0, 0, 0);
+        engine.add_event(2, "周末学做红烧肉", 0, 0, 0);
+        engine.add_edge(rust, 1, 1.0);
+        engine.add_edge(distributed, 1, 1.0);
+        engine.add_edge(cooking, 2, 1.0);
+        engine.add_background_noise(100..120);
+        engine.compile();
+
+        let query = "Rust 分布式";
+        let features = engine.retrieve_typed(query, 0, 0.0, ResultFilter::FeaturesOnly);
+        let ids: Vec<i64> = features.iter().map(|&(id, _)| id).collect();
+        assert!(ids.contains(&rust) && ids.contains(&distributed), "features: {:?}", features);
+        assert!(!ids.contains(&cooking), "features: {:?}", features);
+
+        let events = engine.retrieve(query, 0, 0.0);
+        assert!(events.iter().all(|&(id, _)| id != rust && id != distributed));
+        assert_eq!(events.first().map(|&(id, _)| id), Some(1));
+        assert_eq!(events, engine.retrieve_typed(query, 0, 0.0, ResultFilter::EventsOnly));
+
+        let both = engine.retrieve_typed(query, 0, 0.0, ResultFilter::Both);
+        assert!(both.iter().any(|&(id, _)| id == rust) && both.iter().any(|&(id, _)| id == 1));
+    }
+
+    #[test]
+    fn test_serendipity_is_seeded_and_gated_by_chaos() {
+        let mut engine = AdvancedEngine::open_temp("serendipity");
+        engine.add_event(1, "用 Rust 写了一个分布式存储", 0, 0, 0);
+        engine.add_event(2, "分布式系统的一致性协议", 0, 0, 0);
+        engine.add_background_noise(100..120);
+        engine.compile();
+        let query = "分布式";
+
+        let plain_chaos = engine.retrieve(query, 0, 1.0);
+        let plain_calm = engine.retrieve(query, 0, 0.0);
+        engine.config.serendipity.enabled = true;
+
+        // chaos_level = 0 时不注入
+        assert_eq!(engine.retrieve_seeded(query, 0, 0.0, 42), plain_calm);
+
+        // 固定种子可复现，且 chaos_level = 1 时必然触发
+        let seeded = engine.retrieve_seeded(query, 0, 1.0, 42);
+        assert_eq!(engine.retrieve_seeded(query, 0, 1.0, 42), seeded);
+        assert_ne!(seeded, plain_chaos);
+
+        let mut hits = engine.scored_hits(query, 0, 0.0);
+        let picks = engine.inject_serendipity(&mut hits, 1.0, 7);
+        assert_eq!(picks.len(), hits.len().min(engine.config.serendipity.picks));
+        assert_eq!(engine.inject_serendipity(&mut engine.scored_hits(query, 0, 0.0), 1.0, 7), picks);
+        assert!(engine.inject_serendipity(&mut hits, 0.0, 7).is_empty());
+    }
+
+    #[test]
+    fn test_english_stemming_merges_feature_forms() {
+        let build = |name: &str, stemming: bool| {
+            let mut engine = AdvancedEngine::open_temp(name);
+            engine.config.english_stemming = stemming;
+            let feature = engine.get_or_create_feature("optimization");
+            engine.add_event(1, "重写了检索内核的热路径", 0, 0, 0);
+            engine.add_edge(feature, 1, 1.0);
+            engine.add_background_noise(100..120);
+            engine.compile();
+            engine
+        };
+        let recalls = |engine: &AdvancedEngine| engine.retrieve("optimizing memory", 0, 0.0).iter().any(|&(id, _)| id == 1);
+
+        assert!(!recalls(&build("stemming_off", false)));
+        let mut engine = build("stemming_on", true);
+        assert!(recalls(&engine));
+        // 归并到同一特征，原词形保留用于展示
+        let optim = engine.keyword_to_node["optim"];
+        assert_eq!(engine.get_or_create_feature("Optimize"), optim);
+        assert_eq!(engine.keyword_to_node.len(), 1);
+        assert_eq!(engine.tdb.get_payload(optim as u64).unwrap()["display"], "optimization");
+    }
+
+    #[test]
+    fn test_retrieve_diverse_interleaves_communities() {
+        let mut engine = AdvancedEngine::open_temp("retrieve_diverse");
+        let rust = engine.get_or_create_feature("Rust");
+        let cooking = engine.get_or_create_feature("烹饪");
+        for id in 1..=4 {
+            engine.add_event(id, &format!("Rust 学习笔记 第{}篇", id), 0, 0, 0);
+            engine.add_edge(rust, id, 1.0);
+        }
+        for id in 11..=14 {
+            engine.add_event(id, &format!("关于烹饪的学习笔记 第{}篇", id), 0, 0, 0);
+            engine.add_edge(cooking, id, 1.0);
+        }
+        engine.add_background_noise(100..120);
+        engine.compile();
+
+        let cluster = |results: &[(i64, f32)]| results.iter().map(|&(id, _)| id > 10).collect::<Vec<_>>();
+        let normal = engine.retrieve("学习笔记", 0, 0.0);
+        let diverse = engine.retrieve_diverse("学习笔记", 0, 0.0, 4);
+        assert_eq!(cluster(&normal[..4]), vec![false; 4], "normal: {:?}", normal);
+        assert_eq!(cluster(&diverse), vec![false, true, false, true], "diverse: {:?}", diverse);
+    }
+
+    #[test]
+    fn test_decay_tau_and_floor_old_vs_new() {
+        let mut engine = AdvancedEngine::open_temp("decay_tau_floor");
+        let now = 1_700_000_000;
+        let year = 31_536_000;
+        engine.add_event(1, "学习 Rust 的生命周期", now - 2 * year, 0, 0);
+        engine.add_event(2, "复习 Rust 的生命周期", now - 86400, 0, 0);
+        engine.add_background_noise(100..120);
+        engine.compile();
+        let decay = |engine: &AdvancedEngine, id: i64| {
+            engine.retrieve_explained("Rust 生命周期", now, 0.0).iter().find(|h| h.id == id).unwrap().breakdown.decay_factor
+        };
+
+        // 默认 tau 为一年、下限 0.8: 两年前的旧事被托底
+        assert_eq!(decay(&engine, 1), 0.8);
+        assert!(decay(&engine, 2) > 0.99);
+        engine.config.resonance.decay_floor = 0.0;
+        assert!((decay(&engine, 1) - (-2.0f32).exp()).abs() < 1e-4);
+        engine.config.resonance.decay_tau = 10.0 * year as f32;
+        assert!((decay(&engine, 1) - (-0.2f32).exp()).abs() < 1e-4);
+        assert!(decay(&engine, 2) > decay(&engine, 1));
+    }
+
+    #[test]
+    fn test_future_events_penalty() {
+        let mut engine = AdvancedEngine::open_temp("future_penalty");
+        let now = 1_700_000_000;
+        engine.add_event(1, "故事里的一场雨", now - 86400, 0, 0);
+        engine.add_event(2, "故事里的一场雪", now + 86400, 0, 0);
+        engine.add_background_noise(100..120);
+        engine.compile();
+        let score = |engine: &AdvancedEngine, id: i64| {
+            engine.retrieve("故事里的一场", now, 0.0).iter().find(|&&(i, _)| i == id).map(|&(_, s)| s)
+        };
+
+        // 默认: 未来事件不衰减也不惩罚; 调低 future_penalty 后得分单调下降
+        let future = score(&engine, 2).unwrap();
+        engine.config.future_penalty = 0.5;
+        let halved = score(&engine, 2).unwrap();
+        engine.config.future_penalty = 0.0;
+        let zeroed = score(&engine, 2).unwrap_or(0.0);
+        assert!(future > halved && halved > zeroed);
+        // 过去的事件不受影响
+        let past = score(&engine, 1);
+        engine.config.future_penalty = 1.0;
+        assert_eq!(score(&engine, 1), past);
+
+        // ref_time = 0 时以 decay_reference_time 为参照
+        engine.config.decay_reference_time = now;
+        assert_eq!(engine.retrieve("故事里的一场", 0, 0.0), engine.retrieve("故事里的一场", now, 0.0));
+    }
+
+    #[test]
+    fn test_seeds_boost_connected_events() {
+        let mut engine = AdvancedEngine::open_temp("seeds");
+        engine.load_standard_data();
+        engine.compile();
+
+        let rank = |results: &[(i64, f32)], id: i64| results.iter().position(|&(i, _)| i == id).unwrap_or(usize::MAX);
+        let plain = engine.retrieve_with_seeds("优化", 0, 0.0, &[], 50);
+        let seeded = engine.retrieve_with_seeds("优化", 0, 0.0, &[(100, 1.0)], 50);
+        assert!(rank(&seeded, 104) < rank(&plain, 104), "104: {} -> {}", rank(&plain, 104), rank(&seeded, 104));
+        assert!(rank(&seeded, 106) < rank(&plain, 106), "106: {} -> {}", rank(&plain, 106), rank(&seeded, 106));
+        assert!(seeded.len() <= 50);
+    }
+
+    #[test]
+    fn test_seed_activation_independent_of_thread_count() {
+        let mut engine = AdvancedEngine::open_temp("seed_parallel");
+        engine.load_standard_data();
+        engine.maintain_ontology("rust", "c++", "inhibition", 0.5);
+        let seeds: Vec<(i64, f32)> = ["rust", "上海", "pero", "分布式"].iter()
+            .filter_map(|k| Some((engine.feature_id(k)?, 1.0)))
+            .collect();
+        assert!(seeds.len() >= 2);
+        let run = |threads: usize| {
+            let pool = rayon::ThreadPoolBuilder::new().num_threads(threads).build().unwrap();
+            pool.install(|| engine.seed_activation(&seeds, 0))
+        };
+        let serial = run(1);
+        assert!(serial.len() > seeds.len());
+        for threads in [2, 8] {
+            assert_eq!(run(threads), serial, "{} threads", threads);
+        }
+    }
+
+    #[test]
+    fn test_typed_memory_edges_scale_diffusion() {
+        use crate::core::engine::MemoryEdgeType;
+        let mut engine = AdvancedEngine::open_temp("typed_edges");
+        for id in 1..=4 {
+            engine.add_event(id, &format!("事件 {}", id), 0, 0, 0);
+        }
+        engine.add_edge(1, 2, 0.5);
+        engine.add_edge_typed(1, 3, 0.5, MemoryEdgeType::Causal);
+        engine.add_edge_typed(1, 4, 0.5, MemoryEdgeType::Contrast);
+        // 不同类型的边互不合并
+        engine.add_edge_typed(1, 2, 0.3, MemoryEdgeType::Sequential);
+        assert_eq!(engine.tdb.get_edges(1).len(), 4);
+
+        engine.config.diffusion_depth = 1;
+        let energy = engine.seed_activation(&[(1, 1.0)], 0);
+        let expected = |gain: f32| 0.5 * gain * SEED_HOP_DECAY;
+        assert!((energy[&2] - (expected(1.0) + 0.3 * 1.2 * SEED_HOP_DECAY)).abs() < 1e-6);
+        assert!((energy[&3] - expected(1.5)).abs() < 1e-6);
+        assert!((energy[&4] - expected(0.5)).abs() < 1e-6);
+
+        // 检索主路径的 PPR 扩散同样按边类型加权
+        let ppr = SearchConfig { expand_depth: 1, teleport_alpha: 0.0, enable_inverse_inhibition: false, ..Default::default() };
+        let spread = engine.expand_activation(&[(1, 1.0)], &ppr, 0);
+        assert!((spread[&2] - (0.5 + 0.3 * 1.2)).abs() < 1e-6);
+        assert!((spread[&3] - 0.5 * 1.5).abs() < 1e-6);
+        assert!((spread[&4] - 0.5 * 0.5).abs() < 1e-6);
+
+        engine.config.edge_type_gain.contrast = 0.0;
+        assert_eq!(engine.seed_activation(&[(1, 1.0)], 0)[&4], 0.0);
+        assert_eq!(engine.expand_activation(&[(1, 1.0)], &ppr, 0)[&4], 0.0);
+        assert_eq!(MemoryEdgeType::from_code(1), Some(MemoryEdgeType::Causal));
+        assert_eq!(MemoryEdgeType::from_code(4), None);
+        let hops = engine.explain_path(1, 3, 1).unwrap();
+        assert_eq!(hops[0].edge_type, MemoryEdgeType::Causal.code());
+        assert_eq!(hops[0].layer, crate::core::path::GraphLayer::Memory);
+    }
+
+    #[test]
+    fn test_diffusion_depth_reaches_transitive_concepts() {
+        let mut engine = AdvancedEngine::open_temp("diffusion_depth");
+        engine.load_standard_data();
+        engine.maintain_ontology("Pero", "猫", "representation", 0.8);
+        let girl = engine.feature_id("女孩").unwrap();
+        let (pero, cat) = (engine.feature_id("pero").unwrap() as u64, engine.feature_id("猫").unwrap() as u64);
+
+        engine.config.diffusion_depth = 1;
+        let one_hop = engine.seed_activation(&[(girl, 1.0)], 0);
+        assert!(one_hop.get(&pero).is_some_and(|&e| e > 0.0));
+        assert!(!one_hop.contains_key(&cat));
+
+        engine.config.diffusion_depth = 2;
+        let two_hops = engine.seed_activation(&[(girl, 1.0)], 0);
+        assert_eq!(two_hops[&pero], one_hop[&pero]);
+        assert!(two_hops[&cat] > 0.0 && two_hops[&cat] < two_hops[&pero]);
+
+        // Pero 与 pero / 佩罗 互为等价边，能量不会沿环路回流到种子
+        engine.config.diffusion_depth = 4;
+        assert_eq!(engine.seed_activation(&[(pero as i64, 1.0)], 0)[&pero], 1.0);
+        assert!(EngineConfig::from_toml_str("diffusion_depth = 3").is_ok_and(|c| c.diffusion_depth == 3));
+    }
+
+    #[test]
+    fn test_unreinforced_edge_propagates_less() {
+        let mut engine = AdvancedEngine::open_temp("edge_decay");
+        let now = 1_700_000_000;
+        engine.add_event(1, "上下文事件", 0, 0, 0);
+        engine.add_event(2, "最近强化过的联想", 0, 0, 0);
+        engine.add_event(3, "多年未强化的联想", 0, 0, 0);
+        engine.add_edge(1, 2, 0.8);
+        engine.add_edge(1, 3, 0.8);
+        // 写入时已自动记录一次强化
+        assert!(engine.tdb.get_payload(1).unwrap()["edge_reinforced"]["3"].as_u64().is_some_and(|ts| ts > now));
+        engine.reinforce_edge(1, 2, now - 86400);
+        engine.reinforce_edge(1, 3, now - 5 * 31536000);
+
+        let energy = |engine: &AdvancedEngine, id: u64| engine.seed_activation(&[(1, 1.0)], now)[&id];
+        let ppr = SearchConfig { expand_depth: 1, teleport_alpha: 0.0, enable_inverse_inhibition: false, ..Default::default() };
+        let spread = |engine: &AdvancedEngine, id: u64| engine.expand_activation(&[(1, 1.0)], &ppr, now)[&id];
+        assert_eq!(energy(&engine, 2), energy(&engine, 3));
+        assert_eq!(spread(&engine, 2), spread(&engine, 3));
+        engine.config.edge_decay = true;
+        let (recent, old) = (energy(&engine, 2), energy(&engine, 3));
+        assert!(old < recent * 0.1, "recent {} old {}", recent, old);
+        // 检索主路径的扩散同样衰减
+        let (recent, old) = (spread(&engine, 2), spread(&engine, 3));
+        assert!(old < recent * 0.1, "recent {} old {}", recent, old);
+        // 时间常数取 resonance.decay_tau
+        engine.config.resonance.decay_tau *= 100.0;
+        assert!(spread(&engine, 3) > recent * 0.9);
+        // 存储的强度不变
+        assert!(engine.tdb.get_edges(1).iter().all(|e| e.weight == 0.8));
+    }
+
+    #[test]
+    fn test_retrieve_normalized_in_unit_range() {
+        let mut engine = AdvancedEngine::open_temp("normalized");
+        engine.load_standard_data();
+        engine.compile();
+        for mode in [ScoreNormalization::MaxBoost, ScoreNormalization::MinMax] {
+            engine.config.score_normalization = mode;
+            for query in ["Rust 内存安全", "分布式系统", "开心的一天", "Pero"] {
+                let raw = engine.retrieve(query, 0, 0.0);
+                let normalized = engine.retrieve_normalized(query, 0, 0.0);
+                assert!(!normalized.is_empty(), "{}", query);
+                assert_eq!(normalized.len(), raw.len(), "{}", query);
+                for (&(id, score, raw_score), &(raw_id, expected)) in normalized.iter().zip(&raw) {
+                    assert_eq!((id, raw_score), (raw_id, expected));
+                    assert!((0.0..=1.0).contains(&score), "{:?} {} -> {}", mode, query, score);
+                }
+                if mode == ScoreNormalization::MinMax && !normalized.is_empty() {
+                    assert_eq!(normalized.iter().map(|r| r.1).fold(0.0, f32::max), 1.0);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_min_score_drops_noise() {
+        let mut engine = AdvancedEngine::open_temp("min_score");
+        engine.add_event(1, "在海边捡到了一枚很漂亮的贝壳", 0, 0, 0);
+        engine.add_event(2, "海边的风很大", 0, 0, 0);
+        engine.add_event(3, "这个周末哪里也没去", 0, 0, 0);
+        engine.add_background_noise(100..120);
+        engine.compile();
+
+        let all = engine.retrieve("海边的贝壳", 0, 0.0);
+        let top = all[0].1;
+        engine.config.min_score = top - 1e-3;
+        let filtered = engine.retrieve("海边的贝壳", 0, 0.0);
+        assert!(filtered.len() < all.len(), "unfiltered: {:?}", all);
+        assert!(filtered.iter().all(|&(_, s)| s >= engine.config.min_score));
+        assert_eq!(engine.retrieve_outcome("海边的贝壳", 0, 0.0), RetrieveOutcome::Results(filtered));
+
+        engine.config.min_score = top + 1.0;
+        assert_eq!(engine.retrieve_outcome("海边的贝壳", 0, 0.0), RetrieveOutcome::NoRelevantResults);
+
+        for query in ["", "  \t "] {
+            assert!(engine.retrieve(query, 0, 0.5).is_empty());
+            assert_eq!(engine.retrieve_outcome(query, 0, 0.0), RetrieveOutcome::EmptyQuery);
+        }
+    }
+
+    #[test]
+    fn test_require_emotions_filters_events() {
+        let mut engine = AdvancedEngine::open_temp("require_emotions");
+        engine.add_event(1, "下雨天一个人看海", 0, SimHash::EMOTION_SADNESS, 0);
+        engine.add_event(2, "和朋友一起看海", 0, SimHash::EMOTION_JOY, 0);
+        engine.add_event(3, "周末去看海", 0, 0, 0);
+        engine.add_event(4, "看海时想起离别", 0, SimHash::EMOTION_SADNESS | SimHash::EMOTION_FEAR, 0);
+        engine.add_background_noise(100..120);
+        engine.compile();
+        let ids = |engine: &AdvancedEngine, query: &str| {
+            let mut ids: Vec<i64> = engine.retrieve(query, 0, 0.0).into_iter().map(|(id, _)| id).collect();
+            ids.sort();
+            ids
+        };
+        assert_eq!(ids(&engine, "看海"), vec![1, 2, 3, 4]);
+
+        engine.config.require_emotions = SimHash::EMOTION_SADNESS;
+        assert_eq!(ids(&engine, "看海"), vec![1, 4]);
+        // 保留下来的事件仍享有情感共振加成
+        let boosted = engine.retrieve_explained("难过 看海", 0, 0.0);
+        assert!(!boosted.is_empty());
+        assert!(boosted.iter().all(|h| h.breakdown.affective_boost > 0.0));
+
+        engine.config.require_emotions = SimHash::EMOTION_JOY | SimHash::EMOTION_FEAR;
+        assert_eq!(ids(&engine, "看海"), vec![2, 4]);
+    }
+
+    #[test]
+    fn test_similar_nodes_ranks_other_events() {
+        let mut engine = AdvancedEngine::open_temp("similar_nodes");
+        engine.add_event(1, "我家的猫喜欢在阳台上晒太阳", 0, 0, 0);
+        engine.add_event(2, "猫咪整个下午都趴在阳台晒太阳", 0, 0, 0);
+        engine.add_event(3, "用 Rust 重写了服务端的内存分配器", 0, 0, 0);
+        engine.add_event(4, "周五的项目评审会议推迟到下周", 0, 0, 0);
+        let feature = engine.get_or_create_feature("猫");
+        engine.compile();
+
+        let similar = engine.similar_nodes(1, 10);
+        assert_eq!(similar.len(), 3);
+        assert_eq!(similar[0].0, 2);
+        assert!(similar.iter().all(|&(id, _)| id != 1 && id != feature));
+        assert!(similar.windows(2).all(|w| w[0].1 >= w[1].1));
+        assert_eq!(engine.similar_nodes(1, 1), similar[..1]);
+        assert!(engine.similar_nodes(404, 5).is_empty());
+        assert!(engine.similar_nodes(-1, 5).is_empty());
+    }
+
+    #[test]
+    fn test_max_results_truncates_after_ranking() {
+        let mut engine = AdvancedEngine::open_temp("max_results");
+        engine.load_standard_data();
+        engine.compile();
+        let query = "Rust 内存安全";
+        let all = engine.retrieve(query, 0, 0.0);
+        assert!(all.len() > 20);
+
+        for limit in [3, 12] {
+            engine.config.max_results = Some(limit);
+            // 截断发生在 DPP 重排之后，与不限条数的结果逐项一致
+            assert_eq!(engine.retrieve(query, 0, 0.0), all[..limit]);
+            assert_eq!(engine.retrieve_explained(query, 0, 0.0).len(), limit);
+            assert!(engine.retrieve_seeded(query, 0, 0.8, 7).len() <= limit);
+        }
+
+        // min_score 先过滤，limit 再截断
+        engine.config.max_results = Some(3);
+        engine.config.min_score = all[0].1 - 1e-3;
+        let limited = engine.retrieve(query, 0, 0.0);
+        assert!(!limited.is_empty() && limited.len() <= 3);
+        assert!(limited.iter().all(|&(_, s)| s >= engine.config.min_score));
+        assert!(EngineConfig::from_toml_str("max_results = 0").is_err());
+    }
+
+    #[test]
+    fn test_location_region_resonance() {
+        let mut engine = AdvancedEngine::open_temp("location_resonance");
+        engine.add_event(1, "在深圳发生的事情", 0, 0, 0);
+        engine.add_event(2, "在徐家汇发生的事情", 0, 0, 0);
+        engine.add_background_noise(100..120);
+        engine.compile();
+
+        // "徐家汇" 与 "上海" 没有共同字面词，只能经由地点区共振
+        let results = engine.retrieve("上海的事情", 0, 0.0);
+        assert_eq!(results.first().map(|&(id, _)| id), Some(2), "results: {:?}", results);
+    }
+
+    #[test]
+    fn test_equal_scores_are_ordered_by_id() {
+        let mut engine = AdvancedEngine::open_temp("tie_break");
+        for id in [7, 3, 5] {
+            engine.add_event(id, "一模一样的记录", 1000, 0, 0);
+        }
+        engine.add_background_noise(100..120);
+        engine.compile();
+
+        let first = engine.retrieve("一模一样", 0, 0.0);
+        assert_eq!(first.iter().map(|&(id, _)| id).collect::<Vec<_>>(), vec![3, 5, 7]);
+        for _ in 0..5 {
+            assert_eq!(engine.retrieve("一模一样", 0, 0.0), first);
+        }
+    }
+
+    #[test]
+    fn test_retrieve_is_deterministic_on_standard_data() {
+        let mut engine = AdvancedEngine::open_temp("deterministic_order");
+        engine.load_standard_data();
+        engine.compile();
+        // 这些查询的锚点在截断处有同分项，扩散也会汇入大量节点
+        for query in ["去年 Pero", "上海 开心的事情", "Rust 内存安全", "分布式系统"] {
+            let first = engine.retrieve(query, 1777593600, 0.0);
+            assert!(first.len() > 5, "{}", query);
+            for _ in 0..10 {
+                assert_eq!(engine.retrieve(query, 1777593600, 0.0), first, "{}", query);
+            }
+        }
+    }
+
+    #[test]
+    fn test_adaptive_l1_finds_quantization_adversarial_vector() {
+        let mut engine = AdvancedEngine::open_temp("adaptive_l1");
+        // 查询: 前半段强信号，后半段微弱正值
+        let query: Vec<f32> = (0..512).map(|i| if i < 256 { 1.0 } else { 0.01 }).collect();
+        // 目标: 余弦约 0.9998，但后半段符号全反，汉明距离 256
+        let target: Vec<f32> = (0..512).map(|i| if i < 256 { 1.0 } else { -0.01 }).collect();
+        engine.tdb.insert_with_id(1, &target, serde_json::json!({"type": "event"})).unwrap();
+        // 诱饵: 汉明距离 i 逐个递增、余弦也逐个递增，但都低于目标
+        for i in 1..=200usize {
+            let decoy: Vec<f32> = (0..512).map(|d| match d {
+                d if d < 201 - i => 0.2,
+                d if d < 256 => 1.0,
+                d if d < 256 + i => -0.01,
+                _ => 0.01,
+            }).collect();
+            engine.tdb.insert_with_id(1000 + i as u64, &decoy, serde_json::json!({"type": "event"})).unwrap();
+        }
+        // 背景: 符号全反 (汉明距离 512)
+        for i in 0..800u64 {
+            let filler: Vec<f32> = (0..512).map(|d| -1.0 - ((d as u64 + i) % 7) as f32).collect();
+            engine.tdb.insert_with_id(10_000 + i, &filler, serde_json::json!({"type": "event"})).unwrap();
+        }
+
+        engine.config.hybrid_scan.l1_candidate_ratio = 0.01;
+        let (fixed, fixed_l1) = engine.scan_vectors(&query, 1).unwrap();
+        assert_ne!(fixed[0].id, 1);
+
+        engine.config.hybrid_scan.adaptive = true;
+        let (adaptive, adaptive_l1) = engine.scan_vectors(&query, 1).unwrap();
+        assert_eq!(adaptive[0].id, 1);
+        assert!(adaptive_l1 > fixed_l1, "fixed l1 = {}, adaptive l1 = {}", fixed_l1, adaptive_l1);
+        assert!(matches!(engine.scan_vectors(&query[..256], 1), Err(StorageError::DimensionMismatch { expected: 512, found: 256 })));
+    }
+
+    #[test]
+    fn test_scan_vectors_iter_descending() {
+        let mut engine = AdvancedEngine::open_temp("scan_vectors_iter");
+        let axis = |i: usize, j: usize| -> Vec<f32> { (0..512).map(|d| if d == i || d == j { 1.0 } else { 0.0 }).collect() };
+        let query = axis(0, 0);
+        engine.tdb.insert_with_id(1, &axis(0, 0), serde_json::json!({"type": "event"})).unwrap();
+        engine.tdb.insert_with_id(2, &axis(0, 1), serde_json::json!({"type": "event"})).unwrap();
+        engine.tdb.insert_with_id(3, &axis(1, 1), serde_json::json!({"type": "event"})).unwrap();
+        engine.tdb.insert_with_id(4, &axis(0, 2), serde_json::json!({"type": "event"})).unwrap();
+
+        let iter = engine.scan_vectors_iter(&query).unwrap();
+        assert_eq!(iter.len(), 4);
+        let all: Vec<(i64, f32)> = iter.collect();
+        assert_eq!(all.iter().map(|&(id, _)| id).collect::<Vec<_>>(), vec![1, 2, 4, 3]);
+        assert!((all[0].1 - 1.0).abs() < 1e-6);
+        assert!((all[1].1 - std::f32::consts::FRAC_1_SQRT_2).abs() < 1e-6);
+        assert_eq!(all[3].1, 0.0);
+
+        let above: Vec<i64> = engine.scan_vectors_iter(&query).unwrap().take_while(|&(_, s)| s > 0.7).map(|(id, _)| id).collect();
+        assert_eq!(above, vec![1, 2, 4]);
+        assert!(matches!(engine.scan_vectors_iter(&query[..10]), Err(StorageError::DimensionMismatch { .. })));
+    }
+
+    #[test]
+    fn test_retrieve_batch_matches_sequential() {
+        let mut engine = AdvancedEngine::open_temp("retrieve_batch");
+        engine.add_event(1, "用 Rust 和 PyO3 重构了检索内核", 0, 0, 0);
+        engine.add_event(2, "LSM-Tree 存储引擎的压缩策略", 0, 0, 0);
+        engine.add_event(3, "在上海徐家汇的咖啡馆偶遇老朋友", 0, 0, 0);
+        engine.add_background_noise(100..120);
+        engine.compile();
+        let queries = ["PyO3 重构", "LSM-Tree 存储", "徐家汇的咖啡馆", "老朋友"];
+
+        let expected: Vec<Vec<(i64, f32)>> = queries.iter().map(|q| {
+            let mut r = engine.retrieve(q, 0, 0.0);
+            r.truncate(5);
+            r
+        }).collect();
+        assert!(expected.iter().all(|r| !r.is_empty() && r.len() <= 5));
+        assert_eq!(engine.retrieve_batch(&queries, 0, 0.0, 5), expected);
+        engine.config.batch_parallelism = BatchParallelism::Sequential;
+        assert_eq!(engine.retrieve_batch(&queries, 0, 0.0, 5), expected);
+        let first: Vec<Vec<(i64, f32)>> = expected.iter().map(|r| r[..1].to_vec()).collect();
+        assert_eq!(engine.retrieve_batch(&queries, 0, 0.0, 1), first);
+    }
+
+    #[test]
+    fn test_retrieve_tagged_filters_by_tag() {
+        let mut engine = AdvancedEngine::open_temp("tags");
+        engine.add_event_with_tags(1, "PEDSA 检索内核的重构会议", 0, 0, 0, &["PEDSA", "工作"]);
+        engine.add_event_with_tags(2, "周末和 Pero 讨论 PEDSA 的名字", 0, 0, 0, &["Pero", "PEDSA"]);
+        engine.add_event(3, "PEDSA 的论文草稿", 0, 0, 0);
+        engine.add_background_noise(100..120);
+        engine.compile();
+
+        let ids = |tags: &[&str], mode: TagMatch| {
+            let tags: Vec<String> = tags.iter().map(|t| t.to_string()).collect();
+            let mut ids: Vec<i64> = engine.retrieve_tagged("PEDSA", 0, 0.0, &tags, mode).iter().map(|&(id, _)| id).collect();
+            ids.sort();
+            ids
+        };
+        assert_eq!(ids(&["pedsa"], TagMatch::All), vec![1, 2]);
+        assert_eq!(ids(&["PEDSA", "pero"], TagMatch::All), vec![2]);
+        assert_eq!(ids(&["工作", "Pero"], TagMatch::Any), vec![1, 2]);
+        assert!(ids(&["不存在"], TagMatch::Any).is_empty());
+        assert!(engine.retrieve_tagged("PEDSA", 0, 0.0, &[], TagMatch::All).iter().any(|&(id, _)| id == 3));
+    }
+
+    #[test]
+    fn test_multi_vector_recalls_second_sentence() {
+        let build = |name: &str, multi_vector: bool| {
+            let mut engine = AdvancedEngine::open_temp(name);
+            engine.embedding_model = Some(crate::ml::embedding::CandleModel::new().ok()?);
+            engine.config.multi_vector = multi_vector;
+            engine.add_event(1, "清晨的公园里雾气很重，湖边的柳树静静垂着，几只白鹭贴着水面飞过，远处传来晨练老人的太极音乐。傍晚我在厨房烤了一炉松软的蔓越莓面包。", 0, 0, 0);
+            engine.add_event(2, "周末去郊外爬山，山顶的风景非常开阔。", 0, 0, 0);
+            engine.add_event(3, "地铁里人很多，通勤花了一个小时。", 0, 0, 0);
+            engine.compile();
+            Some(engine)
+        };
+        let (Some(single), Some(multi)) = (build("single_vector", false), build("multi_vector", true)) else {
+            eprintln!("Model failed to load, skipping multi-vector test");
+            return;
+        };
+
+        let score_of = |engine: &AdvancedEngine| {
+            engine.retrieve("烘焙甜点", 0, 0.0).iter().find(|&&(id, _)| id == 1).map(|&(_, s)| s).unwrap_or(0.0)
+        };
+        let (single_score, multi_score) = (score_of(&single), score_of(&multi));
+        assert!(multi_score > single_score, "single-vector {} vs multi-vector {}", single_score, multi_score);
+        // chunk 节点不会作为结果暴露
+        assert!(multi.retrieve("烘焙甜点", 0, 0.0).iter().all(|&(id, _)| [1, 2, 3].contains(&id)));
+    }
+}
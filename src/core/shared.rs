@@ -0,0 +1,131 @@
+//! 多线程共享的引擎句柄: 检索并发进行，写入先进缓冲区
+//! 引擎本体放在 RwLock 中，retrieve 只取读锁；add_event / add_edge 只锁写缓冲区，不会阻塞检索。
+//! 缓冲的写入在 flush_writes 时一次性取写锁落到引擎并按需 compile，此前对检索不可见。
+
+use std::sync::{Mutex, MutexGuard, PoisonError, RwLock, RwLockReadGuard};
+use crate::core::engine::AdvancedEngine;
+
+/// 缓冲中的一次写入
+#[derive(Debug, Clone, PartialEq)]
+enum PendingWrite {
+    Event { id: i64, summary: String, timestamp: u64, emotion: u8, event_type: u8 },
+    Edge { src: i64, tgt: i64, weight: f32 },
+}
+
+/// 可放进 Arc 在线程间共享的引擎
+pub struct SharedEngine {
+    engine: RwLock<AdvancedEngine>,
+    buffer: Mutex<Vec<PendingWrite>>,
+}
+
+impl SharedEngine {
+    pub fn new(engine: AdvancedEngine) -> Self {
+        Self { engine: RwLock::new(engine), buffer: Mutex::new(Vec::new()) }
+    }
+
+    /// 引擎的读视图，可并发调用 retrieve_* 等 &self 接口；持有期间 flush_writes 会等待
+    pub fn read(&self) -> RwLockReadGuard<'_, AdvancedEngine> {
+        self.engine.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn retrieve(&self, query: &str, ref_time: u64, chaos_level: f32) -> Vec<(i64, f32)> {
+        self.read().retrieve(query, ref_time, chaos_level)
+    }
+
+    fn buffer(&self) -> MutexGuard<'_, Vec<PendingWrite>> {
+        self.buffer.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// 缓冲一条事件，flush_writes 时按 add_event 写入
+    pub fn add_event(&self, id: i64, summary: &str, timestamp: u64, emotion: u8, event_type: u8) {
+        self.buffer().push(PendingWrite::Event { id, summary: summary.to_string(), timestamp, emotion, event_type });
+    }
+
+    /// 缓冲一条记忆边，flush_writes 时按 add_edge 写入
+    pub fn add_edge(&self, src: i64, tgt: i64, weight: f32) {
+        self.buffer().push(PendingWrite::Edge { src, tgt, weight });
+    }
+
+    /// 尚未落到引擎的写入数
+    pub fn pending_writes(&self) -> usize {
+        self.buffer().len()
+    }
+
+    /// 按提交顺序把缓冲的写入落到引擎，并在图谱有改动时 compile。返回落地的写入数。
+    pub fn flush_writes(&self) -> usize {
+        let writes = std::mem::take(&mut *self.buffer());
+        if writes.is_empty() { return 0; }
+        let mut engine = self.engine.write().unwrap_or_else(PoisonError::into_inner);
+        for write in &writes {
+            match write {
+                PendingWrite::Event { id, summary, timestamp, emotion, event_type } => {
+                    engine.add_event(*id, summary, *timestamp, *emotion, *event_type);
+                }
+                PendingWrite::Edge { src, tgt, weight } => engine.add_edge(*src, *tgt, *weight),
+            }
+        }
+        engine.compile_if_dirty();
+        writes.len()
+    }
+
+    /// 落地剩余写入后取回引擎
+    pub fn into_inner(self) -> AdvancedEngine {
+        self.flush_writes();
+        self.engine.into_inner().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    #[test]
+    fn test_concurrent_retrieve_while_writing() {
+        let mut engine = AdvancedEngine::open_temp("shared_engine");
+        engine.add_event(1, "周末去海边散步", 0, 0, 0);
+        engine.add_background_noise(2..21);
+        engine.compile();
+        let shared = Arc::new(SharedEngine::new(engine));
+        let done = Arc::new(AtomicBool::new(false));
+
+        let readers: Vec<_> = (0..4).map(|_| {
+            let (shared, done) = (Arc::clone(&shared), Arc::clone(&done));
+            std::thread::spawn(move || {
+                let mut rounds = 0;
+                while !done.load(Ordering::Relaxed) || rounds == 0 {
+                    // 已落地的事件始终可见
+                    assert!(!shared.retrieve("海边", 0, 0.0).is_empty());
+                    rounds += 1;
+                }
+                rounds
+            })
+        }).collect();
+
+        let writer = {
+            let shared = Arc::clone(&shared);
+            std::thread::spawn(move || {
+                for id in 100..140 {
+                    shared.add_event(id, &format!("新写入的登山记录 {}", id), 0, 0, 0);
+                    if id > 100 { shared.add_edge(id - 1, id, 0.5); }
+                    if id % 10 == 9 { shared.flush_writes(); }
+                }
+            })
+        };
+        writer.join().unwrap();
+        done.store(true, Ordering::Relaxed);
+        for reader in readers {
+            assert!(reader.join().unwrap() > 0);
+        }
+
+        assert_eq!(shared.pending_writes(), 0);
+        shared.add_event(200, "最后一条缓冲的事件", 0, 0, 0);
+        assert_eq!(shared.read().node_type(200), None);
+        let engine = Arc::into_inner(shared).unwrap().into_inner();
+        assert_eq!(engine.node_type(200).as_deref(), Some("event"));
+        assert!((100..140).all(|id| engine.node_type(id).as_deref() == Some("event")));
+        assert!(engine.tdb.get_edges(138).iter().any(|e| e.target_id == 139));
+        assert!(!engine.is_dirty());
+    }
+}
@@ -1,5 +1,10 @@
 use std::hash::{Hash, Hasher};
+use ahash::{AHashMap, AHashSet};
+use serde::{Deserialize, Serialize};
 use twox_hash::XxHash64;
+use crate::core::config::ConfigError;
+use crate::core::dates;
+use crate::core::query::parse_emotion;
 
 // ============================================================================
 // 语义指纹 (SimHash V2: Partitioned Multimodal)
@@ -7,9 +12,318 @@ use twox_hash::XxHash64;
 
 pub struct SimHash;
 
+/// 查询解析的中间结果，便于检查启发式推断 (analyze_query)
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct QueryAnalysis {
+    /// 检索实际使用的查询指纹
+    pub fingerprint: u64,
+    /// 命中的全部实体类型，主导类型在前 (见 SimHash::detect_types)
+    pub detected_types: Vec<u8>,
+    /// 写入指纹类型区的类型，未识别时为 TYPE_UNKNOWN
+    pub dominant_type: u8,
+    /// 情感位图
+    pub detected_emotions: u8,
+    /// 解析出的时间戳 (相对时间或年份)，未识别时为 0
+    pub detected_timestamp: u64,
+}
+
+/// 单个节点指纹相对查询指纹的分区相似度 (批量精排使用)
+/// 布局中位数为 0 的分区无从比较，相似度为 None (而不是表示 "完全不相似" 的 0.0)。
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RegionSimilarities {
+    /// 语义区 [0-23] 汉明相似度
+    pub semantic: Option<f32>,
+    /// 时间区 [32-47] 汉明相似度
+    pub temporal: Option<f32>,
+    /// 地点区 [24-31] 汉明相似度
+    pub location: Option<f32>,
+    /// 类型区 [56-63] 汉明相似度
+    pub entity_type: Option<f32>,
+    /// 情感区 [48-55] 是否存在共同激活的情感位
+    pub affective_overlap: bool,
+}
+
+/// 指纹分区 (由低位到高位排列)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SimHashRegion {
+    Semantic,
+    Location,
+    Temporal,
+    Affective,
+    EntityType,
+}
+
+impl SimHashRegion {
+    pub const ALL: [SimHashRegion; 5] = [Self::Semantic, Self::Location, Self::Temporal, Self::Affective, Self::EntityType];
+}
+
+/// 指纹的位布局: 各分区占用的位数，由低位到高位依次为 语义 / 地点 / 时间 / 情感 / 类型，总和必须为 64
+///
+/// 默认 24/8/16/8/8 与 MASK_* 常量一致: 地点区取自语义区的高 8 位，时间 / 情感 / 类型区与引入地点区之前的存储位置相同，
+/// 旧指纹的语义低 24 位 (语义哈希逐位独立) 也可直接比较，只有地点区需 rewrite_fingerprints 重算后才有意义。
+/// 写入的值超出分区宽度时只保留低位，
+/// 因此情感区少于 8 位会丢弃高位情感，类型区宽度需容纳所用的类型编号。
+/// 更换布局后已有节点的指纹需用 rewrite_fingerprints 重新计算。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SimHashLayout {
+    pub semantic_bits: u32,
+    pub temporal_bits: u32,
+    pub location_bits: u32,
+    pub affective_bits: u32,
+    pub type_bits: u32,
+}
+
+impl Default for SimHashLayout {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// 宽度为 bits 的低位掩码
+fn low_bits(bits: u32) -> u64 {
+    if bits >= 64 { u64::MAX } else { (1u64 << bits) - 1 }
+}
+
+impl SimHashLayout {
+    pub const DEFAULT: SimHashLayout = SimHashLayout { semantic_bits: 24, temporal_bits: 16, location_bits: 8, affective_bits: 8, type_bits: 8 };
+
+    pub fn new(semantic_bits: u32, temporal_bits: u32, location_bits: u32, affective_bits: u32, type_bits: u32) -> Result<Self, String> {
+        let layout = Self { semantic_bits, temporal_bits, location_bits, affective_bits, type_bits };
+        layout.validate()?;
+        Ok(layout)
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        let total: u64 = SimHashRegion::ALL.iter().map(|&r| self.bits(r) as u64).sum();
+        if total != 64 {
+            return Err(format!("SimHash 布局各分区位数之和必须为 64, 当前为 {}", total));
+        }
+        Ok(())
+    }
+
+    pub fn bits(&self, region: SimHashRegion) -> u32 {
+        match region {
+            SimHashRegion::Semantic => self.semantic_bits,
+            SimHashRegion::Temporal => self.temporal_bits,
+            SimHashRegion::Location => self.location_bits,
+            SimHashRegion::Affective => self.affective_bits,
+            SimHashRegion::EntityType => self.type_bits,
+        }
+    }
+
+    /// 分区最低位在指纹中的位置
+    pub fn shift(&self, region: SimHashRegion) -> u32 {
+        SimHashRegion::ALL.iter().take_while(|&&r| r != region).map(|&r| self.bits(r)).sum()
+    }
+
+    pub fn mask(&self, region: SimHashRegion) -> u64 {
+        let shift = self.shift(region);
+        if shift >= 64 { 0 } else { low_bits(self.bits(region)) << shift }
+    }
+
+    /// 取出分区的值 (已右移到低位)
+    pub fn extract(&self, fp: u64, region: SimHashRegion) -> u64 {
+        (fp & self.mask(region)).checked_shr(self.shift(region)).unwrap_or(0)
+    }
+
+    /// 按布局拼装指纹，各分区的值超出宽度的高位被截断
+    pub fn pack(&self, semantic: u64, temporal: u64, location: u64, affective: u64, entity_type: u64) -> u64 {
+        [(SimHashRegion::Semantic, semantic), (SimHashRegion::Temporal, temporal), (SimHashRegion::Location, location),
+         (SimHashRegion::Affective, affective), (SimHashRegion::EntityType, entity_type)]
+            .iter()
+            .fold(0, |fp, &(region, value)| self.with_region(fp, region, value))
+    }
+
+    /// 按本布局计算多模态指纹，地点从文本中自动识别
+    pub fn compute_multimodal(&self, text: &str, timestamp: u64, emotion_val: u8, type_val: u8) -> u64 {
+        self.compute_multimodal_with_location(text, timestamp, emotion_val, type_val, SimHash::extract_location(text))
+    }
+
+    /// 同 compute_multimodal，但显式指定地点
+    pub fn compute_multimodal_with_location(&self, text: &str, timestamp: u64, emotion_val: u8, type_val: u8, location: Option<&str>) -> u64 {
+        self.multimodal(text, timestamp, emotion_val, type_val, location, None)
+    }
+
+    /// 同 compute_multimodal，语义区按词元权重 (如 IDF) 加权
+    pub fn compute_multimodal_weighted(&self, text: &str, timestamp: u64, emotion_val: u8, type_val: u8, weights: &AHashMap<String, f32>) -> u64 {
+        self.multimodal(text, timestamp, emotion_val, type_val, SimHash::extract_location(text), Some(weights))
+    }
+
+    /// 把指纹按本布局拆回各分区
+    pub fn regions(&self, fp: u64) -> FingerprintRegions {
+        FingerprintRegions {
+            semantic: self.extract(fp, SimHashRegion::Semantic),
+            temporal: self.extract(fp, SimHashRegion::Temporal),
+            location: self.extract(fp, SimHashRegion::Location),
+            affective: self.extract(fp, SimHashRegion::Affective),
+            entity_type: self.extract(fp, SimHashRegion::EntityType),
+        }
+    }
+
+    /// regions 的逆操作: 按本布局拼装指纹
+    pub fn assemble(&self, regions: &FingerprintRegions) -> u64 {
+        self.pack(regions.semantic, regions.temporal, regions.location, regions.affective, regions.entity_type)
+    }
+
+    /// 替换指纹中某个分区的值 (其余分区不变)
+    pub fn with_region(&self, fp: u64, region: SimHashRegion, value: u64) -> u64 {
+        let part = (value & low_bits(self.bits(region))).checked_shl(self.shift(region)).unwrap_or(0);
+        (fp & !self.mask(region)) | part
+    }
+
+    /// 替换指纹的语义区 (其余分区不变)
+    pub fn with_semantic(&self, fp: u64, text: &str, weights: Option<&AHashMap<String, f32>>) -> u64 {
+        (fp & !self.mask(SimHashRegion::Semantic)) | SimHash::compute_text_hash_weighted(text, self.semantic_bits, weights)
+    }
+
+    fn multimodal(&self, text: &str, timestamp: u64, emotion_val: u8, type_val: u8, location: Option<&str>, weights: Option<&AHashMap<String, f32>>) -> u64 {
+        let temporal = if timestamp > 0 { SimHash::temporal_hash(timestamp) } else { 0 };
+        let location = match location {
+            // 保证非零，以区分 "无地点"
+            Some(location) if self.location_bits > 0 => (SimHash::location_hash(location) & low_bits(self.location_bits)).max(1),
+            _ => 0,
+        };
+        self.pack(SimHash::compute_text_hash_weighted(text, self.semantic_bits, weights), temporal, location, emotion_val as u64, type_val as u64)
+    }
+
+    /// 仅文本的指纹 (其他分区为 0)
+    pub fn compute(&self, text: &str) -> u64 {
+        self.compute_multimodal(text, 0, 0, 0)
+    }
+
+    /// 按本布局生成查询指纹，解析规则同 SimHash::compute_for_query
+    pub fn compute_for_query(&self, query: &str, ref_time: u64) -> u64 {
+        self.query_fingerprint(query, ref_time, None)
+    }
+
+    /// 解析查询并返回指纹与各项推断结果 (指纹同 compute_for_query)，空查询返回全 0
+    pub fn analyze_query(&self, query: &str, ref_time: u64) -> QueryAnalysis {
+        match SimHash::query_signals(query, ref_time) {
+            Some((query_lower, timestamp, emotion, type_val)) => QueryAnalysis {
+                fingerprint: self.multimodal(&query_lower, timestamp, emotion, type_val, SimHash::extract_location(&query_lower), None),
+                detected_types: SimHash::detect_types(&query_lower),
+                dominant_type: type_val,
+                detected_emotions: emotion,
+                detected_timestamp: timestamp,
+            },
+            None => QueryAnalysis::default(),
+        }
+    }
+
+    /// 同 compute_for_query，语义区按词元权重加权
+    pub fn compute_for_query_weighted(&self, query: &str, ref_time: u64, weights: &AHashMap<String, f32>) -> u64 {
+        self.query_fingerprint(query, ref_time, Some(weights))
+    }
+
+    fn query_fingerprint(&self, query: &str, ref_time: u64, weights: Option<&AHashMap<String, f32>>) -> u64 {
+        match SimHash::query_signals(query, ref_time) {
+            Some((query_lower, timestamp, emotion, type_val)) => {
+                self.multimodal(&query_lower, timestamp, emotion, type_val, SimHash::extract_location(&query_lower), weights)
+            }
+            None => 0,
+        }
+    }
+
+    /// 单个指纹的分区相似度 (标量版本)
+    pub fn region_similarity(&self, query_fp: u64, fp: u64) -> RegionSimilarities {
+        RegionSimilarities {
+            semantic: SimHash::similarity_weighted(query_fp, fp, self.mask(SimHashRegion::Semantic)),
+            temporal: SimHash::similarity_weighted(query_fp, fp, self.mask(SimHashRegion::Temporal)),
+            location: SimHash::similarity_weighted(query_fp, fp, self.mask(SimHashRegion::Location)),
+            entity_type: SimHash::similarity_weighted(query_fp, fp, self.mask(SimHashRegion::EntityType)),
+            affective_overlap: (query_fp & fp & self.mask(SimHashRegion::Affective)) != 0,
+        }
+    }
+
+    /// 批量计算分区相似度，支持 AVX2 时每次处理 4 个指纹
+    pub fn batch_region_similarity(&self, query_fp: u64, node_fps: &[u64]) -> Vec<RegionSimilarities> {
+        #[cfg(target_arch = "x86_64")]
+        {
+            if is_x86_feature_detected!("avx2") {
+                // SAFETY: 已在运行时确认 CPU 支持 AVX2
+                return unsafe { SimHash::batch_region_similarity_avx2(self, query_fp, node_fps) };
+            }
+        }
+        node_fps.iter().map(|&fp| self.region_similarity(query_fp, fp)).collect()
+    }
+}
+
+/// 情感关键词表: 情感位 -> 关键词 (小写子串匹配)
+/// 默认即内置的 Plutchik 表，可用 SimHash::load_emotion_keywords 从文件追加领域词汇。
+#[derive(Debug, Clone, PartialEq)]
+pub struct EmotionKeywords {
+    pub table: AHashMap<u8, Vec<String>>,
+}
+
+impl Default for EmotionKeywords {
+    fn default() -> Self {
+        let table = SimHash::get_emotion_keywords().iter()
+            .map(|&(flag, keywords)| (flag, keywords.iter().map(|k| k.to_string()).collect()))
+            .collect();
+        Self { table }
+    }
+}
+
+impl EmotionKeywords {
+    /// 并入另一张表 (同一情感位的关键词追加并去重)
+    pub fn merge(&mut self, other: EmotionKeywords) {
+        for (flag, keywords) in other.table {
+            let entry = self.table.entry(flag).or_default();
+            for keyword in keywords {
+                if !entry.contains(&keyword) { entry.push(keyword); }
+            }
+        }
+    }
+
+    /// 各情感轴 (按位序 0..8) 的关键词命中次数，同一关键词多次出现分别计数
+    pub fn extract_weighted(&self, text: &str) -> [f32; 8] {
+        let text_lower = text.to_lowercase();
+        let mut intensity = [0.0f32; 8];
+        for (&flag, keywords) in &self.table {
+            let hits = keywords.iter().filter(|k| !k.is_empty()).map(|k| text_lower.matches(k.as_str()).count()).sum::<usize>();
+            for (axis, slot) in intensity.iter_mut().enumerate() {
+                if flag & (1 << axis) != 0 { *slot += hits as f32; }
+            }
+        }
+        intensity
+    }
+
+    pub fn extract(&self, text: &str) -> u8 {
+        let text_lower = text.to_lowercase();
+        self.table.iter()
+            .filter(|(_, keywords)| keywords.iter().any(|k| text_lower.contains(k.as_str())))
+            .fold(0, |emotion, (&flag, _)| emotion | flag)
+    }
+}
+
+/// 多模态指纹按分区解码后的各部分 (均已右移到低位)，由 SimHashLayout::regions 拆出、SimHashLayout::assemble 拼回
+/// 各分区的有效位数取决于布局，超出分区宽度的高位在拼装时被截断。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct FingerprintRegions {
+    /// 语义区 (默认 [0-23])
+    pub semantic: u64,
+    /// 时间区 (默认 [32-47])
+    pub temporal: u64,
+    /// 地点区 (默认 [24-31])
+    pub location: u64,
+    /// 情感区 (情感位图，默认 [48-55])
+    pub affective: u64,
+    /// 类型区 (实体类型，默认 [56-63])
+    pub entity_type: u64,
+}
+
+impl FingerprintRegions {
+    /// 按默认布局拼装，自定义布局使用 SimHashLayout::assemble
+    pub fn assemble(&self) -> u64 {
+        SimHashLayout::DEFAULT.assemble(self)
+    }
+}
+
 impl SimHash {
-    pub const MASK_SEMANTIC: u64 = 0xFFFFFFFF;
-    pub const MASK_TEMPORAL: u64 = 0xFFFF00000000; // [32-47]: 时间区 (仅时间 - V2 中已移除位置)
+    pub const MASK_SEMANTIC: u64 = 0x00FFFFFF; // [0-23]: 语义区
+    pub const MASK_LOCATION: u64 = 0xFF000000; // [24-31]: 地点区
+    pub const MASK_TEMPORAL: u64 = 0xFFFF00000000; // [32-47]: 时间区
     pub const MASK_AFFECTIVE: u64 = 0x00FF000000000000;
     pub const MASK_TYPE: u64 = 0xFF00000000000000;
 
@@ -38,36 +352,37 @@ impl SimHash {
     pub const EMOTION_ANGER: u8        = 1 << 6; // 生气
     pub const EMOTION_ANTICIPATION: u8 = 1 << 7; // 期待
 
-    /// 计算多模态分区指纹 (64位)
-    /// [0-31]: 语义区 (文本)
+    /// 计算多模态分区指纹 (64位)，地点从文本中自动识别
+    /// [0-23]: 语义区 (文本)
+    /// [24-31]: 地点区 (地点)
     /// [32-47]: 时间区 (时间)
     /// [48-55]: 情感区 (情感)
     /// [56-63]: 类型区 (实体类型)
     pub fn compute_multimodal(text: &str, timestamp: u64, emotion_val: u8, type_val: u8) -> u64 {
-        let mut fp = 0u64;
-
-        // 1. 语义区 [0-31] (32位)
-        let semantic_hash = Self::compute_text_hash_32(text);
-        fp |= (semantic_hash as u64) & Self::MASK_SEMANTIC;
-
-        // 2. 时间区 [32-47] (16位) - 仅保留时间
-        if timestamp > 0 {
-            let t_hash = Self::compute_temporal_hash(timestamp);
-            fp |= ((t_hash as u64) << 32) & Self::MASK_TEMPORAL;
-        }
-
-        // 3. 情感区 [48-55] (8位)
-        fp |= ((emotion_val as u64) << 48) & Self::MASK_AFFECTIVE;
+        Self::compute_multimodal_with_location(text, timestamp, emotion_val, type_val, Self::extract_location(text))
+    }
 
-        // 4. 类型区 [56-63] (8位)
-        fp |= ((type_val as u64) << 56) & Self::MASK_TYPE;
+    /// 同 compute_multimodal，但显式指定地点 (None 表示无地点，地点区全 0)
+    pub fn compute_multimodal_with_location(text: &str, timestamp: u64, emotion_val: u8, type_val: u8, location: Option<&str>) -> u64 {
+        SimHashLayout::DEFAULT.compute_multimodal_with_location(text, timestamp, emotion_val, type_val, location)
+    }
 
-        fp
+    /// 把指纹按默认布局拆回各分区，自定义布局使用 SimHashLayout::regions
+    pub fn regions(fp: u64) -> FingerprintRegions {
+        SimHashLayout::DEFAULT.regions(fp)
     }
 
     /// 针对查询字符串的智能指纹生成 (增强的时间感知)
     /// ref_time: 外部传入的参考时间戳（现实时间或叙事时间），用于解析相对时间
+    /// 空查询 (空串或仅含空白) 返回 0: 所有分区均为空，不与任何节点产生共振
     pub fn compute_for_query(query: &str, ref_time: u64) -> u64 {
+        SimHashLayout::DEFAULT.compute_for_query(query, ref_time)
+    }
+
+    /// 从查询中解析 (小写查询, 时间戳, 情感位图, 实体类型)，空查询返回 None
+    #[allow(clippy::if_same_then_else)]
+    fn query_signals(query: &str, ref_time: u64) -> Option<(String, u64, u8, u8)> {
+        if query.trim().is_empty() { return None; }
         let mut timestamp = 0u64;
         let mut type_val = Self::TYPE_UNKNOWN;
 
@@ -76,8 +391,12 @@ impl SimHash {
         // --- 1. 相对时间解析 (相对时间分辨率) ---
         // 只有当 ref_time 有效 (>0) 时才启用相对时间解析
         if ref_time > 0 {
+            // 数字相对时间 ("三天前" / "5 days ago") 比下面的固定短语更具体，优先匹配
+            if let Some(offset) = dates::parse_relative_offset(&query_lower) {
+                timestamp = ref_time.saturating_sub(offset);
+            }
             // 0. 今天/今日/此刻 (当前)
-            if query_lower.contains("今天") || query_lower.contains("今日") || query_lower.contains("today") || 
+            else if query_lower.contains("今天") || query_lower.contains("今日") || query_lower.contains("today") || 
                query_lower.contains("now") || query_lower.contains("此刻") || query_lower.contains("当前") {
                 timestamp = ref_time;
             }
@@ -134,19 +453,34 @@ impl SimHash {
         // 模拟情感提取 (Plutchik 情感轮)
         let emotion = Self::extract_emotion(&query_lower);
 
-        // 模拟类型推断
-
-        if query_lower.contains("pero") || query_lower.contains("用户") || query_lower.contains("女孩") {
-            type_val = Self::TYPE_PERSON;
-        } else if query_lower.contains("rust") || query_lower.contains("代码") || query_lower.contains("算法") {
-            type_val = Self::TYPE_TECH;
-        } else if query_lower.contains("事情") || query_lower.contains("发生") {
-            type_val = Self::TYPE_EVENT;
-        } else if query_lower.contains("蝴蝶结") || query_lower.contains("键盘") {
-            type_val = Self::TYPE_OBJECT;
+        // 模拟类型推断: 取命中关键词最多的类型
+        if let Some(&dominant) = Self::detect_types(&query_lower).first() {
+            type_val = dominant;
         }
 
-        Self::compute_multimodal(&query_lower, timestamp, emotion, type_val)
+        Some((query_lower, timestamp, emotion, type_val))
+    }
+
+    /// 查询类型推断的关键词表 (小写子串匹配)，顺序即命中数相同时的优先级
+    fn get_type_keywords() -> &'static [(u8, &'static [&'static str])] {
+        &[
+            (Self::TYPE_PERSON, &["pero", "用户", "女孩"]),
+            (Self::TYPE_TECH, &["rust", "代码", "算法"]),
+            (Self::TYPE_EVENT, &["事情", "发生"]),
+            (Self::TYPE_OBJECT, &["蝴蝶结", "键盘"]),
+        ]
+    }
+
+    /// 文本 (已小写) 命中的全部实体类型，按命中关键词数降序，同数时按关键词表顺序；首个即主导类型
+    pub fn detect_types(text_lower: &str) -> Vec<u8> {
+        let mut hits: Vec<(usize, usize, u8)> = Self::get_type_keywords().iter().enumerate()
+            .filter_map(|(rank, &(type_val, keywords))| {
+                let count = keywords.iter().filter(|k| text_lower.contains(*k)).count();
+                (count > 0).then_some((count, rank, type_val))
+            })
+            .collect();
+        hits.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+        hits.into_iter().map(|(_, _, type_val)| type_val).collect()
     }
 
     fn get_emotion_keywords() -> &'static [(u8, &'static [&'static str])] {
@@ -209,6 +543,49 @@ impl SimHash {
         ]
     }
 
+    /// 从 JSON (.json) 或 TOML 文件加载情感关键词表
+    /// 键为情感名 (joy / 开心 / anger ...，同查询语法 emotion:) 或情感位的数值，值为关键词列表。
+    /// replace 为 false 时与内置 Plutchik 表合并，为 true 时只使用文件中的词。
+    pub fn load_emotion_keywords(path: impl AsRef<std::path::Path>, replace: bool) -> Result<EmotionKeywords, ConfigError> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path).map_err(ConfigError::Io)?;
+        let raw: std::collections::HashMap<String, Vec<String>> = if path.extension().is_some_and(|e| e == "json") {
+            serde_json::from_str(&text).map_err(|e| ConfigError::Parse(e.to_string()))?
+        } else {
+            toml::from_str(&text).map_err(|e| ConfigError::Parse(e.to_string()))?
+        };
+        let mut loaded = EmotionKeywords { table: AHashMap::new() };
+        for (key, keywords) in raw {
+            let flag = match key.parse::<u8>() {
+                Ok(flag) if flag != 0 => flag,
+                Ok(_) => return Err(ConfigError::Invalid("情感位不能为 0".into())),
+                Err(_) => parse_emotion(&key.to_lowercase())
+                    .ok_or_else(|| ConfigError::Invalid(format!("未知的情感: {}", key)))?,
+            };
+            loaded.table.entry(flag).or_default().extend(keywords.into_iter().map(|k| k.to_lowercase()));
+        }
+        if replace { return Ok(loaded); }
+        let mut table = EmotionKeywords::default();
+        table.merge(loaded);
+        Ok(table)
+    }
+
+    /// 按给定关键词表提取情感
+    pub fn extract_emotion_with(table: &EmotionKeywords, text: &str) -> u8 {
+        table.extract(text)
+    }
+
+    /// 情感强度: 内置表在 Plutchik 八个轴上的关键词命中次数 (按情感位序)
+    pub fn extract_emotion_weighted(text: &str) -> [f32; 8] {
+        EmotionKeywords::default().extract_weighted(text)
+    }
+
+    /// 同 extract_emotion_weighted，按文本长度归一化为每百字的命中次数
+    pub fn extract_emotion_weighted_normalized(text: &str) -> [f32; 8] {
+        let chars = text.chars().count().max(1) as f32;
+        Self::extract_emotion_weighted(text).map(|hits| hits * 100.0 / chars)
+    }
+
     /// 从文本中提取情感 (Plutchik's Wheel)
     pub fn extract_emotion(text: &str) -> u8 {
         let mut emotion = 0u8;
@@ -228,22 +605,50 @@ impl SimHash {
 
     /// 传统的 SimHash 计算 (仅用于语义区，压缩到 32 位)
     pub fn compute_text_hash_32(text: &str) -> u32 {
+        Self::compute_text_hash(text, 32) as u32
+    }
+
+    /// 语义区 SimHash，取 bits 位 (至多 64)
+    pub fn compute_text_hash(text: &str, bits: u32) -> u64 {
+        Self::compute_text_hash_weighted(text, bits, None)
+    }
+
+    /// 加权的语义区 SimHash (32 位): 每个词元的 +1/-1 贡献乘以其权重 (如 IDF)，表中没有的词元权重为 1
+    pub fn compute_text_hash_32_weighted(text: &str, weights: &AHashMap<String, f32>) -> u32 {
+        Self::compute_text_hash_weighted(text, 32, Some(weights)) as u32
+    }
+
+    pub fn compute_text_hash_weighted(text: &str, bits: u32, weights: Option<&AHashMap<String, f32>>) -> u64 {
         let text_lower = text.to_lowercase();
-        let mut v = [0i32; 32];
-        
-        for word in text_lower.split_whitespace() {
-            Self::update_v_32(&mut v, word);
-        }
-        // 处理中文等无空格字符
-        for c in text_lower.chars() {
-            let mut buf = [0u8; 4];
-            let s = c.encode_utf8(&mut buf);
-            Self::update_v_32(&mut v, s);
+        Self::hash_tokens(Self::tokens(&text_lower), bits, weights)
+    }
+
+    /// 带字符 n-gram (shingle) 的语义区 SimHash (32 位): 在单字词元之外，
+    /// 再加入长度 2..=n 的连续字符片段，使字序不同的文本 ("机器学习" / "学习机器") 可区分。
+    /// n <= 1 时与 compute_text_hash_32 相同。
+    pub fn compute_text_hash_32_ngram(text: &str, n: usize) -> u32 {
+        Self::compute_text_hash_ngram(text, 32, n) as u32
+    }
+
+    /// 带字符 n-gram 的语义区 SimHash，取 bits 位 (至多 64)
+    pub fn compute_text_hash_ngram(text: &str, bits: u32, n: usize) -> u64 {
+        let text_lower = text.to_lowercase();
+        let shingles = Self::shingles(&text_lower, n);
+        Self::hash_tokens(Self::tokens(&text_lower).chain(shingles.iter().map(String::as_str)), bits, None)
+    }
+
+    fn hash_tokens<'a>(tokens: impl Iterator<Item = &'a str>, bits: u32, weights: Option<&AHashMap<String, f32>>) -> u64 {
+        let mut v = [0f32; 64];
+        let v = &mut v[..bits.min(64) as usize];
+
+        for token in tokens {
+            let weight = weights.and_then(|w| w.get(token)).copied().unwrap_or(1.0);
+            Self::update_v(v, token, weight);
         }
 
-        let mut finger_print = 0u32;
-        for i in 0..32 {
-            if v[i] > 0 {
+        let mut finger_print = 0u64;
+        for (i, &weight) in v.iter().enumerate() {
+            if weight > 0.0 {
                 finger_print |= 1 << i;
             }
         }
@@ -255,43 +660,445 @@ impl SimHash {
         Self::compute_multimodal(text, 0, 0, 0)
     }
 
-    fn update_v_32(v: &mut [i32; 32], token: &str) {
+    /// SimHash 的词元: 空白分隔的词 + 逐个字符 (处理中文等无空格文本)
+    fn tokens(text_lower: &str) -> impl Iterator<Item = &str> {
+        text_lower.split_whitespace()
+            .chain(text_lower.char_indices().map(|(i, c)| &text_lower[i..i + c.len_utf8()]))
+    }
+
+    /// 长度 2..=n 的连续字符片段，连续空白折叠为一个空格，片段可跨越词边界以保留词序
+    fn shingles(text_lower: &str, n: usize) -> Vec<String> {
+        let chars: Vec<char> = text_lower.split_whitespace().collect::<Vec<_>>().join(" ").chars().collect();
+        (2..=n.min(chars.len()))
+            .flat_map(|len| chars.windows(len).map(|w| w.iter().collect()).collect::<Vec<_>>())
+            .collect()
+    }
+
+    /// 由语料构建词元 IDF 表: ln((1 + N) / (1 + df)) + 1，词元切分与语义区 SimHash 一致
+    pub fn build_idf<'a>(docs: impl IntoIterator<Item = &'a str>) -> AHashMap<String, f32> {
+        let mut df: AHashMap<String, u32> = AHashMap::new();
+        let mut n = 0u32;
+        for doc in docs {
+            n += 1;
+            let doc_lower = doc.to_lowercase();
+            let unique: AHashSet<&str> = Self::tokens(&doc_lower).collect();
+            for token in unique {
+                *df.entry(token.to_string()).or_insert(0) += 1;
+            }
+        }
+        df.into_iter()
+            .map(|(token, count)| (token, ((1.0 + n as f32) / (1.0 + count as f32)).ln() + 1.0))
+            .collect()
+    }
+
+    fn update_v(v: &mut [f32], token: &str, weight: f32) {
         let mut hasher = XxHash64::with_seed(0);
         token.hash(&mut hasher);
         let hash = hasher.finish();
         
-        for i in 0..32 {
+        for (i, slot) in v.iter_mut().enumerate() {
             let bit = (hash >> i) & 1;
             if bit == 1 {
-                v[i] += 1;
+                *slot += weight;
             } else {
-                v[i] -= 1;
+                *slot -= weight;
             }
         }
     }
 
-    fn compute_temporal_hash(timestamp: u64) -> u16 {
-        // 纯时间戳哈希
+    /// 纯时间戳哈希 (按布局截取低位)
+    fn temporal_hash(timestamp: u64) -> u64 {
         let mut hasher = XxHash64::with_seed(12345); // 独立种子
         timestamp.hash(&mut hasher);
-        let h = hasher.finish();
-        (h & 0xFFFF) as u16
+        hasher.finish()
+    }
+
+    fn location_hash(location: &str) -> u64 {
+        let mut hasher = XxHash64::with_seed(54321); // 独立种子
+        location.to_lowercase().hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// 地点哈希 (8位)，保证非零，以区分 "无地点"
+    pub fn compute_location_hash(location: &str) -> u8 {
+        ((Self::location_hash(location) & 0xFF) as u8).max(1)
+    }
+
+    /// 地点词典: (关键词, 归一后的地点)。区/地标归一到所属城市，使 "上海" 能与 "徐家汇" 共振
+    fn get_location_keywords() -> &'static [(&'static str, &'static str)] {
+        &[
+            ("徐家汇", "上海"), ("张江", "上海"), ("滨江大道", "上海"), ("陆家嘴", "上海"),
+            ("外滩", "上海"), ("浦东", "上海"), ("上海", "上海"), ("shanghai", "上海"),
+            ("南山", "深圳"), ("福田", "深圳"), ("深圳", "深圳"), ("shenzhen", "深圳"),
+            ("中关村", "北京"), ("海淀", "北京"), ("北京", "北京"), ("beijing", "北京"),
+            ("西湖", "杭州"), ("杭州", "杭州"), ("广州", "广州"), ("成都", "成都"),
+            ("东京", "东京"), ("tokyo", "东京"),
+        ]
+    }
+
+    /// 从文本中识别地点 (取最先出现的地点关键词)
+    pub fn extract_location(text: &str) -> Option<&'static str> {
+        let text_lower = text.to_lowercase();
+        Self::get_location_keywords().iter()
+            .filter_map(|&(kw, location)| text_lower.find(kw).map(|pos| (pos, location)))
+            .min_by_key(|&(pos, _)| pos)
+            .map(|(_, location)| location)
     }
 
     /// 计算加权汉明距离相似度 (V2: 支持分区权重掩码)
-    /// mask: 用于指定只关注哪些区域 (例如只关注时空区)
-    pub fn similarity_weighted(a: u64, b: u64, mask: u64) -> f32 {
+    /// mask: 用于指定只关注哪些区域 (例如只关注时空区)；mask 为 0 时没有可比较的位，返回 None
+    pub fn similarity_weighted(a: u64, b: u64, mask: u64) -> Option<f32> {
         let xor = (a ^ b) & mask;
         let dist = xor.count_ones();
         let total_bits = mask.count_ones();
-        if total_bits == 0 { return 0.0; }
-        1.0 - (dist as f32 / total_bits as f32)
+        if total_bits == 0 { return None; }
+        Some(1.0 - (dist as f32 / total_bits as f32))
     }
     
+    /// 掩码内置位集合的 Jaccard 相似度 |a∧b| / |a∨b|；两侧掩码内都没有置位时返回 None
+    ///
+    /// 汉明相似度 (similarity_weighted) 把 "都为 0" 也算作一致，适合每一位都携带信息的哈希区 (语义 / 时间 / 地点 / 类型)；
+    /// 情感区是稀疏的位图，大多数位为 0，按汉明计算会被共同的 0 抬高，应改用 Jaccard 只看激活位的重合程度。
+    pub fn similarity_jaccard(a: u64, b: u64, mask: u64) -> Option<f32> {
+        let union = ((a | b) & mask).count_ones();
+        if union == 0 { return None; }
+        Some((a & b & mask).count_ones() as f32 / union as f32)
+    }
+
     /// 原始相似度接口
     #[allow(dead_code)]
     pub fn similarity(a: u64, b: u64) -> f32 {
         // 默认全区匹配
-        Self::similarity_weighted(a, b, 0xFFFFFFFFFFFFFFFF)
+        1.0 - ((a ^ b).count_ones() as f32 / 64.0)
+    }
+
+    /// 批量计算分区相似度 (精排循环使用)
+    /// 一次遍历同时得到语义/时间/类型三区的汉明相似度与情感位交集，
+    /// 支持 AVX2 时每次处理 4 个指纹 (向量化 popcount)，否则回退到标量实现。
+    pub fn batch_region_similarity(query_fp: u64, node_fps: &[u64]) -> Vec<RegionSimilarities> {
+        SimHashLayout::DEFAULT.batch_region_similarity(query_fp, node_fps)
+    }
+
+    /// 单个指纹的分区相似度 (标量版本，亦作为 SIMD 路径的尾部处理)
+    pub fn region_similarity(query_fp: u64, fp: u64) -> RegionSimilarities {
+        SimHashLayout::DEFAULT.region_similarity(query_fp, fp)
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[target_feature(enable = "avx2")]
+    fn batch_region_similarity_avx2(layout: &SimHashLayout, query_fp: u64, node_fps: &[u64]) -> Vec<RegionSimilarities> {
+        use std::arch::x86_64::*;
+
+        let mut out = Vec::with_capacity(node_fps.len());
+        let q = _mm256_set1_epi64x(query_fp as i64);
+        let m_sem = _mm256_set1_epi64x(layout.mask(SimHashRegion::Semantic) as i64);
+        let m_tmp = _mm256_set1_epi64x(layout.mask(SimHashRegion::Temporal) as i64);
+        let m_loc = _mm256_set1_epi64x(layout.mask(SimHashRegion::Location) as i64);
+        let m_typ = _mm256_set1_epi64x(layout.mask(SimHashRegion::EntityType) as i64);
+        let m_aff = _mm256_set1_epi64x(layout.mask(SimHashRegion::Affective) as i64);
+
+        // 空分区的相似度与标量版本一致记为 None
+        let similarity = |dist: u64, bits: u32| (bits > 0).then(|| 1.0 - (dist as f32 / bits as f32));
+        let sem_bits = layout.semantic_bits;
+        let tmp_bits = layout.temporal_bits;
+        let loc_bits = layout.location_bits;
+        let typ_bits = layout.type_bits;
+
+        let chunks = node_fps.chunks_exact(4);
+        let tail = chunks.remainder();
+        for chunk in chunks {
+            // SAFETY: chunk 恰好 4 个 u64 (32 字节)，loadu 不要求对齐
+            let v = unsafe { _mm256_loadu_si256(chunk.as_ptr() as *const __m256i) };
+            let x = _mm256_xor_si256(v, q);
+            let sem = Self::popcount_epi64(_mm256_and_si256(x, m_sem));
+            let tmp = Self::popcount_epi64(_mm256_and_si256(x, m_tmp));
+            let loc = Self::popcount_epi64(_mm256_and_si256(x, m_loc));
+            let typ = Self::popcount_epi64(_mm256_and_si256(x, m_typ));
+            let aff = _mm256_and_si256(_mm256_and_si256(v, q), m_aff);
+
+            let (mut sem_d, mut tmp_d, mut loc_d, mut typ_d, mut aff_v) = ([0u64; 4], [0u64; 4], [0u64; 4], [0u64; 4], [0u64; 4]);
+            // SAFETY: 目标数组均为 32 字节，storeu 不要求对齐
+            unsafe {
+                _mm256_storeu_si256(sem_d.as_mut_ptr() as *mut __m256i, sem);
+                _mm256_storeu_si256(tmp_d.as_mut_ptr() as *mut __m256i, tmp);
+                _mm256_storeu_si256(loc_d.as_mut_ptr() as *mut __m256i, loc);
+                _mm256_storeu_si256(typ_d.as_mut_ptr() as *mut __m256i, typ);
+                _mm256_storeu_si256(aff_v.as_mut_ptr() as *mut __m256i, aff);
+            }
+            for lane in 0..4 {
+                out.push(RegionSimilarities {
+                    semantic: similarity(sem_d[lane], sem_bits),
+                    temporal: similarity(tmp_d[lane], tmp_bits),
+                    location: similarity(loc_d[lane], loc_bits),
+                    entity_type: similarity(typ_d[lane], typ_bits),
+                    affective_overlap: aff_v[lane] != 0,
+                });
+            }
+        }
+        out.extend(tail.iter().map(|&fp| layout.region_similarity(query_fp, fp)));
+        out
+    }
+
+    /// AVX2 每 64 位通道 popcount (半字节查表 + SAD 横向求和)
+    #[cfg(target_arch = "x86_64")]
+    #[target_feature(enable = "avx2")]
+    fn popcount_epi64(v: std::arch::x86_64::__m256i) -> std::arch::x86_64::__m256i {
+        use std::arch::x86_64::*;
+        let lookup = _mm256_setr_epi8(
+            0, 1, 1, 2, 1, 2, 2, 3, 1, 2, 2, 3, 2, 3, 3, 4,
+            0, 1, 1, 2, 1, 2, 2, 3, 1, 2, 2, 3, 2, 3, 3, 4,
+        );
+        let low_mask = _mm256_set1_epi8(0x0f);
+        let lo = _mm256_and_si256(v, low_mask);
+        let hi = _mm256_and_si256(_mm256_srli_epi16(v, 4), low_mask);
+        let cnt = _mm256_add_epi8(_mm256_shuffle_epi8(lookup, lo), _mm256_shuffle_epi8(lookup, hi));
+        _mm256_sad_epu8(cnt, _mm256_setzero_si256())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_batch_region_similarity_matches_scalar() {
+        // 简易 xorshift 伪随机，保证测试可复现
+        let mut state = 0x9E3779B97F4A7C15u64;
+        let mut next = || { state ^= state << 13; state ^= state >> 7; state ^= state << 17; state };
+        let query_fp = next();
+        // 非 4 的倍数，覆盖 SIMD 主循环与尾部标量路径
+        let node_fps: Vec<u64> = (0..1027).map(|_| next()).chain([query_fp, 0, u64::MAX]).collect();
+
+        let batch = SimHash::batch_region_similarity(query_fp, &node_fps);
+        assert_eq!(batch.len(), node_fps.len());
+        for (r, &fp) in batch.iter().zip(&node_fps) {
+            assert_eq!(r.semantic, SimHash::similarity_weighted(query_fp, fp, SimHash::MASK_SEMANTIC));
+            assert_eq!(r.temporal, SimHash::similarity_weighted(query_fp, fp, SimHash::MASK_TEMPORAL));
+            assert_eq!(r.location, SimHash::similarity_weighted(query_fp, fp, SimHash::MASK_LOCATION));
+            assert_eq!(r.entity_type, SimHash::similarity_weighted(query_fp, fp, SimHash::MASK_TYPE));
+            assert_eq!(r.affective_overlap, SimHash::regions(query_fp).affective & SimHash::regions(fp).affective != 0);
+        }
+    }
+
+    #[test]
+    fn test_location_region() {
+        assert_eq!(SimHash::extract_location("周末去了徐家汇"), Some("上海"));
+        assert_eq!(SimHash::extract_location("没有地点"), None);
+
+        let query = SimHash::compute_for_query("上海的事情", 0);
+        let shanghai = SimHash::compute_multimodal("在徐家汇吃了一顿火锅", 0, 0, 0);
+        let shenzhen = SimHash::compute_multimodal("在深圳吃了一顿火锅", 0, 0, 0);
+        assert_ne!(query & SimHash::MASK_LOCATION, 0);
+        assert_eq!(SimHash::similarity_weighted(query, shanghai, SimHash::MASK_LOCATION), Some(1.0));
+        assert!(SimHash::similarity_weighted(query, shenzhen, SimHash::MASK_LOCATION).is_some_and(|s| s < 1.0));
+        assert_eq!(SimHash::compute("火锅") & SimHash::MASK_LOCATION, 0);
+    }
+
+    #[test]
+    fn test_empty_query_fingerprint() {
+        for query in ["", "   ", "\t\n"] {
+            assert_eq!(SimHash::compute_for_query(query, 0), 0);
+            assert_eq!(SimHash::compute_for_query(query, 1777593600), 0);
+        }
+        assert_eq!(SimHash::compute_text_hash_32(""), 0);
+        assert_ne!(SimHash::compute_for_query("昨天", 1777593600), 0);
+    }
+
+    #[test]
+    fn test_regions_round_trip() {
+        let r = FingerprintRegions { semantic: 0xADBEEF, temporal: 0x1234, location: 0x56, affective: (SimHash::EMOTION_JOY | SimHash::EMOTION_FEAR) as u64, entity_type: SimHash::TYPE_PERSON as u64 };
+        assert_eq!(SimHash::regions(r.assemble()), r);
+        for fp in [0u64, u64::MAX, 0x0123456789ABCDEF] {
+            assert_eq!(SimHash::regions(fp).assemble(), fp);
+        }
+        // 各分区与掩码一致
+        let fp = r.assemble();
+        assert_eq!(fp & SimHash::MASK_SEMANTIC, 0xADBEEF);
+        assert_eq!(fp & SimHash::MASK_LOCATION, 0x56 << 24);
+        assert_eq!(fp & SimHash::MASK_TEMPORAL, 0x1234 << 32);
+        assert_eq!(fp & SimHash::MASK_AFFECTIVE, ((SimHash::EMOTION_JOY | SimHash::EMOTION_FEAR) as u64) << 48);
+        assert_eq!(fp & SimHash::MASK_TYPE, (SimHash::TYPE_PERSON as u64) << 56);
+
+        let event = SimHash::compute_multimodal("在徐家汇很开心", 1000, SimHash::EMOTION_JOY, SimHash::TYPE_EVENT);
+        let regions = SimHash::regions(event);
+        assert_eq!(regions.entity_type, SimHash::TYPE_EVENT as u64);
+        assert_eq!(regions.affective, SimHash::EMOTION_JOY as u64);
+        assert_eq!(regions.location, SimHash::compute_location_hash("上海") as u64);
+    }
+
+    #[test]
+    fn test_default_layout_keeps_legacy_regions() {
+        // 引入地点区之前的格式: 语义 [0-31] / 时间 [32-47] / 情感 [48-55] / 类型 [56-63]
+        let text = "在徐家汇很开心";
+        let legacy = SimHash::compute_text_hash(text, 32)
+            | (SimHash::temporal_hash(1000) & 0xFFFF) << 32
+            | (SimHash::EMOTION_JOY as u64) << 48
+            | (SimHash::TYPE_EVENT as u64) << 56;
+        let fp = SimHash::compute_multimodal(text, 1000, SimHash::EMOTION_JOY, SimHash::TYPE_EVENT);
+        assert_eq!(fp & !SimHash::MASK_LOCATION, legacy & !SimHash::MASK_LOCATION);
+        let r = SimHash::region_similarity(fp, legacy);
+        assert_eq!((r.semantic, r.temporal, r.entity_type), (Some(1.0), Some(1.0), Some(1.0)));
+    }
+
+    #[test]
+    fn test_layout_default_and_custom() {
+        let default = SimHashLayout::default();
+        assert_eq!(default.mask(SimHashRegion::Semantic), SimHash::MASK_SEMANTIC);
+        assert_eq!(default.mask(SimHashRegion::Temporal), SimHash::MASK_TEMPORAL);
+        assert_eq!(default.mask(SimHashRegion::Location), SimHash::MASK_LOCATION);
+        assert_eq!(default.mask(SimHashRegion::Affective), SimHash::MASK_AFFECTIVE);
+        assert_eq!(default.mask(SimHashRegion::EntityType), SimHash::MASK_TYPE);
+        let text = "在徐家汇很开心";
+        assert_eq!(default.compute_multimodal(text, 1000, SimHash::EMOTION_JOY, SimHash::TYPE_EVENT),
+                   FingerprintRegions {
+                       semantic: SimHash::compute_text_hash(text, 24),
+                       temporal: SimHash::temporal_hash(1000) & 0xFFFF,
+                       location: SimHash::compute_location_hash("上海") as u64,
+                       affective: SimHash::EMOTION_JOY as u64,
+                       entity_type: SimHash::TYPE_EVENT as u64,
+                   }.assemble());
+
+        assert!(SimHashLayout::new(32, 16, 8, 8, 8).is_err());
+        assert!(SimHashLayout::new(0, 0, 0, 0, 63).is_err());
+        let layout = SimHashLayout::new(24, 20, 8, 8, 4).unwrap();
+        let masks: Vec<u64> = SimHashRegion::ALL.iter().map(|&r| layout.mask(r)).collect();
+        assert_eq!(masks.iter().fold(0, |a, m| a | m), u64::MAX);
+        assert_eq!(masks.iter().map(|m| m.count_ones()).sum::<u32>(), 64);
+
+        let fp = layout.compute_multimodal(text, 1000, SimHash::EMOTION_JOY, SimHash::TYPE_EVENT);
+        assert_eq!(layout.extract(fp, SimHashRegion::Temporal), SimHash::temporal_hash(1000) & 0xFFFFF);
+        assert_eq!(layout.extract(fp, SimHashRegion::Affective), SimHash::EMOTION_JOY as u64);
+        assert_eq!(layout.extract(fp, SimHashRegion::EntityType), SimHash::TYPE_EVENT as u64);
+        assert_eq!(layout.extract(fp, SimHashRegion::Semantic), SimHash::compute_text_hash(text, 24));
+        // 分区拆装按布局进行，与默认布局的结果不同
+        let regions = layout.regions(fp);
+        assert_eq!(regions.temporal, SimHash::temporal_hash(1000) & 0xFFFFF);
+        assert_eq!(layout.assemble(&regions), fp);
+        assert_ne!(SimHash::regions(fp), regions);
+        // 时间区更宽: 不同时间戳的时间区相似度按 20 位计算
+        let other = layout.compute_multimodal(text, 2000, SimHash::EMOTION_JOY, SimHash::TYPE_EVENT);
+        let r = layout.region_similarity(fp, other);
+        assert_eq!(r.semantic, Some(1.0));
+        assert_eq!(r.temporal, SimHash::similarity_weighted(fp, other, 0xFFFFF << 32));
+        assert_eq!(layout.batch_region_similarity(fp, &[other; 5]), vec![r; 5]);
+    }
+
+    #[test]
+    fn test_empty_region_has_no_similarity() {
+        assert_eq!(SimHash::similarity_weighted(0, u64::MAX, 0), None);
+        assert_eq!(SimHash::similarity(0, u64::MAX), 0.0);
+
+        // 不划分地点区的布局: 地点相似度为 None，其余分区照常计算
+        let layout = SimHashLayout::new(40, 8, 0, 8, 8).unwrap();
+        let fp = layout.compute_multimodal("上海的海边散步", 1000, SimHash::EMOTION_JOY, SimHash::TYPE_EVENT);
+        let other = layout.compute_multimodal("深圳的海边散步", 1000, SimHash::EMOTION_JOY, SimHash::TYPE_EVENT);
+        let r = layout.region_similarity(fp, other);
+        assert_eq!(r.location, None);
+        assert_eq!(r.temporal, Some(1.0));
+        assert!(r.semantic.is_some() && r.entity_type.is_some());
+        assert_eq!(layout.batch_region_similarity(fp, &[other; 6]), vec![r; 6]);
+    }
+
+    #[test]
+    fn test_idf_weighted_text_hash() {
+        let ones: AHashMap<String, f32> = ["的", "猫"].iter().map(|t| (t.to_string(), 1.0)).collect();
+        let text = "的的的的猫";
+        assert_eq!(SimHash::compute_text_hash_32_weighted(text, &ones), SimHash::compute_text_hash_32(text));
+        // 填充字与整词权重为 0 时只剩 "猫" 的贡献
+        let weights: AHashMap<String, f32> = [("的", 0.0), (text, 0.0)].iter().map(|&(t, w)| (t.to_string(), w)).collect();
+        assert_eq!(SimHash::compute_text_hash_32_weighted(text, &weights), SimHash::compute_text_hash_32("猫"));
+
+        let idf = SimHash::build_idf(["我的猫", "我的狗", "你的书"]);
+        assert!(idf["的"] < idf["我"]);
+        assert!(idf["我"] < idf["猫"]);
+        assert_eq!(idf["的"], 1.0);
+    }
+
+    #[test]
+    fn test_similarity_jaccard() {
+        let mask = SimHash::MASK_AFFECTIVE;
+        let fp = |emotions: u8| (emotions as u64) << 48;
+        let joy = fp(SimHash::EMOTION_JOY);
+        let joy_surprise = fp(SimHash::EMOTION_JOY | SimHash::EMOTION_SURPRISE);
+        assert_eq!(SimHash::similarity_jaccard(joy, joy, mask), Some(1.0));
+        assert_eq!(SimHash::similarity_jaccard(joy, joy_surprise, mask), Some(0.5));
+        assert_eq!(SimHash::similarity_jaccard(joy, fp(SimHash::EMOTION_FEAR), mask), Some(0.0));
+        // 两侧都没有激活位: 无从比较
+        assert_eq!(SimHash::similarity_jaccard(0, !mask, mask), None);
+        // 同一对位图按汉明计算会被共同的 0 抬高
+        assert_eq!(SimHash::similarity_weighted(joy, fp(SimHash::EMOTION_FEAR), mask), Some(0.75));
+    }
+
+    #[test]
+    fn test_analyze_query_reports_all_types() {
+        let layout = SimHashLayout::DEFAULT;
+        let analysis = layout.analyze_query("用户写的代码和算法", 0);
+        // 技术类命中两个关键词，压过只命中一个的人物类
+        assert_eq!(analysis.detected_types, vec![SimHash::TYPE_TECH, SimHash::TYPE_PERSON]);
+        assert_eq!(analysis.dominant_type, SimHash::TYPE_TECH);
+        assert_eq!(analysis.fingerprint, layout.compute_for_query("用户写的代码和算法", 0));
+        assert_eq!(layout.extract(analysis.fingerprint, SimHashRegion::EntityType), SimHash::TYPE_TECH as u64);
+
+        // 命中数相同时按关键词表顺序
+        let tie = layout.analyze_query("用户的代码 昨天很开心", 1_700_000_000);
+        assert_eq!(tie.detected_types, vec![SimHash::TYPE_PERSON, SimHash::TYPE_TECH]);
+        assert_eq!(tie.dominant_type, SimHash::TYPE_PERSON);
+        assert_eq!(tie.detected_timestamp, 1_700_000_000 - 86400);
+        assert_ne!(tie.detected_emotions & SimHash::EMOTION_JOY, 0);
+
+        assert!(layout.analyze_query("随便看看", 0).detected_types.is_empty());
+        assert_eq!(layout.analyze_query("  ", 0), QueryAnalysis::default());
+    }
+
+    #[test]
+    fn test_numeric_relative_time_in_query() {
+        let now = 1_700_000_000;
+        let timestamp = |query: &str| SimHash::query_signals(query, now).unwrap().1;
+        assert_eq!(timestamp("三天前吃了什么"), now - 3 * 86400);
+        assert_eq!(timestamp("2 weeks ago"), now - 2 * 604800);
+        // 数字相对时间优先于笼统的 "最近"
+        assert_eq!(timestamp("最近两个月前的事"), now - 2 * 2592000);
+        assert_eq!(timestamp("最近的事"), now - 259200);
+        assert_eq!(timestamp("昨天"), now - 86400);
+        // 没有参照时刻时不解析相对时间
+        assert_eq!(SimHash::query_signals("三天前", 0).unwrap().1, 0);
+    }
+
+    #[test]
+    fn test_ngram_text_hash_keeps_order() {
+        // 同一组词、不同词序: 单字 + 整词的词元完全相同
+        let (a, b) = ("机器 学习", "学习 机器");
+        assert_eq!(SimHash::compute_text_hash_32(a), SimHash::compute_text_hash_32(b));
+        assert_ne!(SimHash::compute_text_hash_32_ngram(a, 2), SimHash::compute_text_hash_32_ngram(b, 2));
+        assert_ne!(SimHash::compute_text_hash_32_ngram("机器学习", 3), SimHash::compute_text_hash_32_ngram("学习机器", 3));
+        // n <= 1 退化为单字
+        assert_eq!(SimHash::compute_text_hash_32_ngram(a, 1), SimHash::compute_text_hash_32(a));
+        assert_eq!(SimHash::compute_text_hash_32_ngram("", 3), 0);
+        assert_eq!(SimHash::shingles("机器  学习", 2), vec!["机器", "器 ", " 学", "学习"]);
+    }
+
+    #[test]
+    fn test_load_emotion_keywords() {
+        let dir = std::env::temp_dir().join(format!("pedsa_emotions_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let toml_path = dir.join("emotions.toml");
+        std::fs::write(&toml_path, "joy = [\"赢麻了\"]\n64 = [\"红温\"]\n").unwrap();
+
+        let merged = SimHash::load_emotion_keywords(&toml_path, false).unwrap();
+        assert_eq!(SimHash::extract_emotion_with(&merged, "这把赢麻了"), SimHash::EMOTION_JOY);
+        assert_eq!(SimHash::extract_emotion_with(&merged, "直接红温"), SimHash::EMOTION_ANGER);
+        assert_eq!(SimHash::extract_emotion_with(&merged, "今天很开心"), SimHash::EMOTION_JOY);
+        assert_eq!(EmotionKeywords::default().extract("今天很开心，但有点担心"), SimHash::extract_emotion("今天很开心，但有点担心"));
+
+        let replaced = SimHash::load_emotion_keywords(&toml_path, true).unwrap();
+        assert_eq!(replaced.extract("今天很开心"), 0);
+        assert_eq!(replaced.extract("赢麻了又红温"), SimHash::EMOTION_JOY | SimHash::EMOTION_ANGER);
+
+        let json_path = dir.join("emotions.json");
+        std::fs::write(&json_path, r#"{"期待": ["蹲一个"]}"#).unwrap();
+        assert_eq!(SimHash::load_emotion_keywords(&json_path, true).unwrap().extract("蹲一个后续"), SimHash::EMOTION_ANTICIPATION);
+        std::fs::write(&json_path, r#"{"wistful": ["怅然"]}"#).unwrap();
+        assert!(matches!(SimHash::load_emotion_keywords(&json_path, false), Err(ConfigError::Invalid(_))));
+        let _ = std::fs::remove_dir_all(&dir);
     }
 }
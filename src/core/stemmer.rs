@@ -0,0 +1,91 @@
+//! 轻量英文词干化 (基于规则的后缀剥离)
+//! 只处理纯 ASCII 字母词，中文及混合词原样返回。目标是让 optimize / optimization / optimizing
+//! 这类屈折、派生形式落到同一个特征上，而不是语言学上精确的词根。
+
+/// 按长度从长到短排列，命中第一个即停止
+const SUFFIXES: &[(&str, &str)] = &[
+    ("izations", ""), ("ization", ""), ("ational", "ate"), ("fulness", "ful"), ("iveness", "ive"),
+    ("ations", ""), ("izing", ""), ("izers", ""), ("ation", ""), ("ities", ""), ("ness", ""),
+    ("izer", ""), ("ized", ""), ("izes", ""), ("ings", ""), ("ment", ""), ("ity", ""),
+    ("ize", ""), ("ing", ""), ("ies", "y"), ("ed", ""), ("es", ""), ("ly", ""), ("s", ""),
+];
+
+/// 剥离后的词干至少保留的字母数
+const MIN_STEM_LEN: usize = 3;
+
+pub fn stem(word: &str) -> String {
+    let lower = word.to_ascii_lowercase();
+    if lower.len() <= MIN_STEM_LEN || !lower.bytes().all(|b| b.is_ascii_alphabetic()) {
+        return lower;
+    }
+    let mut stem = lower.clone();
+    for &(suffix, replacement) in SUFFIXES {
+        let Some(base) = lower.strip_suffix(suffix) else { continue; };
+        // "class" / "status" / "analysis" 的 s 不是复数
+        if suffix == "s" && (base.ends_with('s') || base.ends_with('u') || base.ends_with('i')) { break; }
+        if base.len() < MIN_STEM_LEN { continue; }
+        stem = format!("{}{}", base, replacement);
+        // running -> runn -> run
+        let bytes = stem.as_bytes();
+        let n = bytes.len();
+        if matches!(suffix, "ing" | "ed") && n > MIN_STEM_LEN && bytes[n - 1] == bytes[n - 2] && !b"lsz".contains(&bytes[n - 1]) {
+            stem.pop();
+        }
+        break;
+    }
+    if stem.len() > MIN_STEM_LEN && stem.ends_with('e') {
+        stem.pop();
+    }
+    stem
+}
+
+/// 对文本中的每个英文词做词干化，其余字符保持不变
+pub fn stem_text(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut word = String::new();
+    for c in text.chars() {
+        if c.is_ascii_alphabetic() {
+            word.push(c);
+            continue;
+        }
+        if !word.is_empty() {
+            out.push_str(&stem(&word));
+            word.clear();
+        }
+        out.push(c);
+    }
+    if !word.is_empty() {
+        out.push_str(&stem(&word));
+    }
+    out
+}
+
+/// 查询扩展: 在原查询后追加与原词不同的词干，原词仍参与 BM25，词干供特征关键词匹配
+pub fn expand_query(query: &str) -> String {
+    let stems: Vec<String> = query.split(|c: char| !c.is_ascii_alphabetic())
+        .filter(|w| !w.is_empty())
+        .map(|w| (w.to_ascii_lowercase(), stem(w)))
+        .filter(|(w, s)| w != s)
+        .map(|(_, s)| s)
+        .collect();
+    if stems.is_empty() { query.to_string() } else { format!("{} {}", query, stems.join(" ")) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stem_groups_inflections() {
+        for word in ["optimize", "optimization", "optimizing", "optimized", "optimizes", "Optimizations"] {
+            assert_eq!(stem(word), "optim", "{}", word);
+        }
+        assert_eq!(stem("memories"), stem("memory"));
+        assert_eq!(stem("running"), "run");
+        assert_eq!(stem("class"), "class");
+        assert_eq!(stem("status"), "status");
+        assert_eq!(stem("分布式"), "分布式");
+        assert_eq!(stem_text("Rust optimization 技巧"), "rust optim 技巧");
+        assert_eq!(expand_query("optimizing memory"), "optimizing memory optim");
+    }
+}
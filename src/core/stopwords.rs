@@ -1,3 +1,6 @@
+use std::sync::OnceLock;
+use ahash::AHashSet;
+
 /// 共享停用词表 (中英文)
 /// 包含中文虚词、英文介词/代词/助动词/连词
 pub const STOPWORDS: &[&str] = &[
@@ -22,8 +25,90 @@ pub const STOPWORDS: &[&str] = &[
     "and", "or", "so", "nor", "yet", "although", "because", "unless", "while", "where", "when", "how", "whether"
 ];
 
-/// 判断是否为停用词
+/// 判断是否为内置停用词
 #[inline]
 pub fn is_stopword(word: &str) -> bool {
-    STOPWORDS.contains(&word)
+    static BUILTIN: OnceLock<AHashSet<&'static str>> = OnceLock::new();
+    BUILTIN.get_or_init(|| STOPWORDS.iter().copied().collect()).contains(word)
+}
+
+/// 引擎使用的停用词集合: 默认为内置表，可在运行时增删
+/// 词一律按小写存储与匹配。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StopwordFilter {
+    words: AHashSet<String>,
+}
+
+impl Default for StopwordFilter {
+    fn default() -> Self {
+        Self { words: STOPWORDS.iter().map(|w| w.to_string()).collect() }
+    }
+}
+
+impl StopwordFilter {
+    /// 空集合: 不做任何中英文停用词过滤
+    pub fn empty() -> Self {
+        Self { words: AHashSet::new() }
+    }
+
+    pub fn is_stopword(&self, word: &str) -> bool {
+        self.words.contains(&word.to_lowercase())
+    }
+
+    /// 登记停用词，已存在时返回 false
+    pub fn insert(&mut self, word: &str) -> bool {
+        self.words.insert(word.to_lowercase())
+    }
+
+    /// 移除停用词，不存在时返回 false
+    pub fn remove(&mut self, word: &str) -> bool {
+        self.words.remove(&word.to_lowercase())
+    }
+
+    pub fn len(&self) -> usize {
+        self.words.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.is_empty()
+    }
+
+    /// 按字典序列出全部停用词
+    pub fn words(&self) -> Vec<&str> {
+        let mut words: Vec<&str> = self.words.iter().map(String::as_str).collect();
+        words.sort_unstable();
+        words
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stopword_filter_is_extensible() {
+        let mut filter = StopwordFilter::default();
+        assert_eq!(filter.len(), STOPWORDS.iter().collect::<AHashSet<_>>().len());
+        assert!(filter.is_stopword("的") && filter.is_stopword("The"));
+        assert!(is_stopword("the") && !is_stopword("rust"));
+
+        assert!(filter.insert("嗯嗯"));
+        assert!(!filter.insert("嗯嗯"));
+        assert!(filter.is_stopword("嗯嗯"));
+        assert!(filter.remove("THE"));
+        assert!(!filter.is_stopword("the"));
+
+        let empty = StopwordFilter::empty();
+        assert!(empty.is_empty() && !empty.is_stopword("的"));
+
+        // 引擎建特征时使用自身的停用词集合
+        let mut engine = crate::core::engine::AdvancedEngine::open_temp("stopword_filter");
+        engine.stopwords.insert("嗯嗯");
+        assert_eq!(engine.get_or_create_feature("嗯嗯"), -1);
+        assert_eq!(engine.get_or_create_feature("的"), -1);
+        engine.stopwords = StopwordFilter::empty();
+        assert!(engine.get_or_create_feature("的") >= 0);
+        engine.add_feature(7, "嗯嗯");
+        assert_eq!(engine.feature_id("嗯嗯"), Some(7));
+    }
 }
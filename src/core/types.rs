@@ -1,4 +1,70 @@
-#![allow(dead_code)]
+//! 后台维护任务接口
+//! 图谱不能跨线程共享 `&mut`，因此后台线程只负责排期: 按固定间隔 (或收到 schedule_maintenance 时)
+//! 产出维护请求，引擎在自己的线程上调用 run_pending_maintenance 取出并执行。
+
+use std::sync::Mutex;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+/// 一次 LTD 衰减剪枝请求 (参数含义见 apply_global_decay_and_pruning)
+#[derive(Debug, Clone, PartialEq)]
+pub struct MaintenanceRequest {
+    /// 触发来源: schedule_maintenance 的 context，定时触发为 "periodic"
+    pub context: String,
+    pub decay_rate: f32,
+    pub threshold: u16,
+}
+
 pub trait AsyncTaskInterface {
+    /// 请求尽快做一次维护 (不阻塞调用方)
     fn schedule_maintenance(&self, context: &str);
+    /// 取出所有已就绪的维护请求 (非阻塞)
+    fn drain_requests(&self) -> Vec<MaintenanceRequest>;
+}
+
+/// 后台线程排期的维护任务: 每隔 interval 产出一次定时请求，schedule_maintenance 立即产出一次
+/// drop 时关闭通道并等待线程退出。
+pub struct ThreadedAsyncTask {
+    commands: Option<Sender<String>>,
+    requests: Mutex<Receiver<MaintenanceRequest>>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl ThreadedAsyncTask {
+    pub fn spawn(interval: Duration, decay_rate: f32, threshold: u16) -> Self {
+        let (command_tx, command_rx) = mpsc::channel::<String>();
+        let (request_tx, request_rx) = mpsc::channel();
+        let worker = std::thread::spawn(move || loop {
+            let context = match command_rx.recv_timeout(interval) {
+                Ok(context) => context,
+                Err(RecvTimeoutError::Timeout) => "periodic".to_string(),
+                Err(RecvTimeoutError::Disconnected) => break,
+            };
+            if request_tx.send(MaintenanceRequest { context, decay_rate, threshold }).is_err() { break; }
+        });
+        Self { commands: Some(command_tx), requests: Mutex::new(request_rx), worker: Some(worker) }
+    }
+}
+
+impl AsyncTaskInterface for ThreadedAsyncTask {
+    fn schedule_maintenance(&self, context: &str) {
+        if let Some(commands) = &self.commands {
+            let _ = commands.send(context.to_string());
+        }
+    }
+
+    fn drain_requests(&self) -> Vec<MaintenanceRequest> {
+        let requests = self.requests.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        requests.try_iter().collect()
+    }
+}
+
+impl Drop for ThreadedAsyncTask {
+    fn drop(&mut self) {
+        self.commands.take();
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
 }
@@ -4,10 +4,10 @@ use std::hash::{Hash, Hasher};
 use std::time::Instant;
 use twox_hash::XxHash64;
 
-use crate::core::engine::AdvancedEngine;
+use crate::core::engine::{AdvancedEngine, EMBEDDING_BATCH_SIZE};
 use crate::data::dataset::{get_tech_domain_data, get_social_domain_data, get_history_domain_data,
                      get_value_domain_data, get_daily_domain_data, get_timeline_domain_data,
-                     get_ontology_data};
+                     get_ontology_data, validate_ontology_edges, OntologyWarning, RawEdge, RawEvent};
 
 impl AdvancedEngine {
     pub fn load_standard_data(&mut self) {
@@ -28,21 +28,24 @@ impl AdvancedEngine {
         let (e6, d6) = get_timeline_domain_data();
         all_events.extend(e6); all_edges.extend(d6);
 
-        for ev in all_events {
-            self.add_event(ev.id, ev.summary, 0, 0, 0);
-            for feature in ev.features {
-                let feature_lower = feature.to_lowercase();
-                let mut s = XxHash64::with_seed(0);
-                feature_lower.hash(&mut s);
-                let feat_id = (s.finish() as i64).abs();
-                self.add_feature(feat_id, &feature_lower);
-                self.add_edge(feat_id, ev.id, 1.0);
-            }
-        }
+        self.load_raw_events(&all_events);
 
         println!("📚 正在注入定义库 (Ontology) 数据...");
         let ontology_edges = get_ontology_data();
-        for edge in ontology_edges {
+        let mut rejected = vec![false; ontology_edges.len()];
+        for warning in validate_ontology_edges(&ontology_edges) {
+            match warning {
+                OntologyWarning::SelfLoop { index, word } => {
+                    println!("⚠️ [Ontology] 跳过自环关系: {} -> {}", word, word);
+                    rejected[index] = true;
+                }
+                OntologyWarning::Contradiction { indices, src, tgt } => {
+                    println!("⚠️ [Ontology] 跳过矛盾关系: {} <-> {} 同时被声明为等价与抑制", src, tgt);
+                    for index in indices { rejected[index] = true; }
+                }
+            }
+        }
+        for (edge, _) in ontology_edges.into_iter().zip(rejected).filter(|(_, rejected)| !rejected) {
             let relation_type = if edge.is_equality {
                 "equality"
             } else if edge.is_inhibition {
@@ -53,15 +56,44 @@ impl AdvancedEngine {
             self.maintain_ontology(edge.src, edge.tgt, relation_type, edge.weight);
         }
 
-        for edge in all_edges {
-            self.add_edge(edge.src, edge.tgt, edge.weight);
-        }
+        self.load_raw_edges(&all_edges);
 
         self.add_edge(205, 100, 0.6);
         self.add_edge(200, 302, 0.4);
         self.build_temporal_backbone();
     }
 
+    /// 写入一组数据集事件，并为每个特征词建节点、连到事件
+    pub(crate) fn load_raw_events(&mut self, events: &[RawEvent]) {
+        for batch in events.chunks(EMBEDDING_BATCH_SIZE) {
+            // 先整批推理预热嵌入缓存，add_event 逐条取向量时直接命中
+            let summaries: Vec<&str> = batch.iter().map(|ev| ev.summary).collect();
+            let _ = self.calculate_chaos_batch(&summaries);
+            for ev in batch {
+                self.add_event(ev.id, ev.summary, 0, 0, 0);
+                for feature in &ev.features {
+                    let feature_lower = feature.to_lowercase();
+                    let mut s = XxHash64::with_seed(0);
+                    feature_lower.hash(&mut s);
+                    let feat_id = (s.finish() as i64).abs();
+                    self.add_feature(feat_id, &feature_lower);
+                    self.add_edge(feat_id, ev.id, 1.0);
+                }
+            }
+        }
+    }
+
+    /// 写入一组数据集的记忆边 (跳过自环)
+    pub(crate) fn load_raw_edges(&mut self, edges: &[RawEdge]) {
+        for edge in edges {
+            if edge.src == edge.tgt {
+                println!("⚠️ [Loader] 跳过自环边: {} -> {}", edge.src, edge.tgt);
+                continue;
+            }
+            self.add_edge(edge.src, edge.tgt, edge.weight);
+        }
+    }
+
     pub fn load_million_test_data(&mut self, node_count: usize) {
         println!("🏗️ 正在生成 {} 级大规模合成数据...", node_count);
         let start = Instant::now();
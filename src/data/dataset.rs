@@ -1,4 +1,5 @@
 #![allow(dead_code)]
+#![allow(clippy::vec_init_then_push)]
 #![allow(unused_imports)]
 use half::f16;
 
@@ -72,6 +73,44 @@ pub fn get_ontology_data() -> Vec<OntologyEdge> {
     edges
 }
 
+/// 定义库数据校验发现的问题
+#[derive(Debug, Clone, PartialEq)]
+pub enum OntologyWarning {
+    /// 源与目标是同一个特征 (按小写归一后比较)
+    SelfLoop { index: usize, word: String },
+    /// 同一对特征既被声明为等价又被声明为抑制
+    Contradiction { indices: Vec<usize>, src: String, tgt: String },
+}
+
+/// 检查定义库边中的自环与矛盾关系 (同一对特征同时为 equality 和 inhibition)
+pub fn validate_ontology_edges(edges: &[OntologyEdge]) -> Vec<OntologyWarning> {
+    use std::collections::BTreeMap;
+    let mut warnings = Vec::new();
+    // 无序特征对 -> (等价边下标, 抑制边下标)
+    let mut pairs: BTreeMap<(String, String), (Vec<usize>, Vec<usize>)> = BTreeMap::new();
+
+    for (index, edge) in edges.iter().enumerate() {
+        let (src, tgt) = (edge.src.to_lowercase(), edge.tgt.to_lowercase());
+        if src == tgt {
+            warnings.push(OntologyWarning::SelfLoop { index, word: src });
+            continue;
+        }
+        let entry = pairs.entry(if src < tgt { (src, tgt) } else { (tgt, src) }).or_default();
+        if edge.is_equality { entry.0.push(index); }
+        if edge.is_inhibition { entry.1.push(index); }
+    }
+
+    for ((src, tgt), (equality, inhibition)) in pairs {
+        if !equality.is_empty() && !inhibition.is_empty() {
+            let mut indices: Vec<usize> = equality.into_iter().chain(inhibition).collect();
+            indices.sort();
+            indices.dedup();
+            warnings.push(OntologyWarning::Contradiction { indices, src, tgt });
+        }
+    }
+    warnings
+}
+
 pub fn get_tech_domain_data() -> (Vec<RawEvent>, Vec<RawEdge>) {
     let mut events = Vec::new();
     let mut edges = Vec::new();
@@ -2471,3 +2510,24 @@ pub fn get_cross_domain_edges() -> Vec<RawEdge> {
 
     edges
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_ontology_edges_flags_contradiction_and_self_loop() {
+        let edges = vec![
+            OntologyEdge { src: "Rust", tgt: "铁锈", weight: 1.0, is_equality: true, is_inhibition: false },
+            OntologyEdge { src: "类型安全", tgt: "Rust", weight: 0.8, is_equality: false, is_inhibition: false },
+            OntologyEdge { src: "铁锈", tgt: "rust", weight: 0.9, is_equality: false, is_inhibition: true },
+            OntologyEdge { src: "Pero", tgt: "pero", weight: 1.0, is_equality: true, is_inhibition: false },
+        ];
+        let warnings = validate_ontology_edges(&edges);
+        assert_eq!(warnings.len(), 2);
+        assert!(warnings.contains(&OntologyWarning::SelfLoop { index: 3, word: "pero".to_string() }));
+        assert!(warnings.contains(&OntologyWarning::Contradiction {
+            indices: vec![0, 2], src: "rust".to_string(), tgt: "铁锈".to_string(),
+        }));
+    }
+}
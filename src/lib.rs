@@ -2,6 +2,7 @@ pub mod core;
 pub mod ml;
 pub mod data;
 pub mod bench;
+pub mod prelude;
 
 pub use core::engine::AdvancedEngine;
 pub use core::simhash::SimHash;
@@ -1,7 +1,4 @@
-mod core;
-mod ml;
-mod data;
-mod bench;
+use pedsa_embedding::bench;
 
 
 fn main() {
@@ -13,6 +10,8 @@ fn main() {
         bench::benchmarks::run_ten_million_test(10_000_000);
     } else if args.contains(&"--latency".to_string()) {
         bench::benchmark_latency::run_latency_benchmark();
+    } else if args.contains(&"--batch".to_string()) {
+        bench::benchmarks::run_batch_benchmark();
     } else {
         println!("Tests are disabled for V2 native reconstruction.");
     }
@@ -2,7 +2,139 @@ use candle_core::{Device, Tensor};
 use crate::ml::inference_engine::QuantizedBertModel as QBertModel;
 use tokenizers::{Tokenizer, PaddingParams, TruncationParams};
 use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use std::hash::{Hash, Hasher};
+use ahash::AHashMap;
+use twox_hash::XxHash64;
+
+/// 嵌入缓存默认容量 (条)
+pub const DEFAULT_EMBEDDING_CACHE_CAPACITY: usize = 1024;
+
+/// 模型单次输入的最大 token 数 (含 [CLS] / [SEP])，超出部分被截断
+pub const MAX_SEQUENCE_TOKENS: usize = 512;
+
+/// vectorize_long 的默认滑窗大小 (正文 token 数，为 [CLS] / [SEP] 留出位置)
+pub const DEFAULT_WINDOW_TOKENS: usize = MAX_SEQUENCE_TOKENS - 2;
+
+/// vectorize_long 相邻窗口的默认重叠 token 数
+pub const DEFAULT_WINDOW_OVERLAP: usize = 64;
+
+/// 把 n 个 token 切成长度至多 window、相邻重叠 overlap 的窗口 [start, end)，最后一个窗口到达末尾
+fn token_windows(n: usize, window: usize, overlap: usize) -> Vec<(usize, usize)> {
+    let window = window.max(1);
+    let step = window.saturating_sub(overlap).max(1);
+    let mut windows = Vec::new();
+    let mut start = 0;
+    loop {
+        let end = (start + window).min(n);
+        windows.push((start, end));
+        if end >= n { return windows; }
+        start += step;
+    }
+}
+
+/// 按权重对向量取加权平均，normalize 时再做 L2 归一化
+fn weighted_mean(vectors: &[Vec<f32>], weights: &[f32], normalize: bool) -> Vec<f32> {
+    let dim = vectors.first().map_or(0, Vec::len);
+    let total: f32 = weights.iter().sum();
+    let mut mean = vec![0.0f32; dim];
+    for (vec, &w) in vectors.iter().zip(weights) {
+        for (m, x) in mean.iter_mut().zip(vec) {
+            *m += x * w / total;
+        }
+    }
+    if normalize {
+        let norm = mean.iter().map(|x| x * x).sum::<f32>().sqrt();
+        if norm > 0.0 { mean.iter_mut().for_each(|x| *x /= norm); }
+    }
+    mean
+}
+
+/// 以文本 XxHash64 为键的 LRU 嵌入缓存，可在多线程间共享
+struct EmbeddingCache {
+    inner: Mutex<CacheInner>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+struct CacheInner {
+    capacity: usize,
+    /// 键 -> (向量, 最近一次访问的序号)
+    entries: AHashMap<u64, (Vec<f32>, u64)>,
+    clock: u64,
+}
+
+impl EmbeddingCache {
+    fn new(capacity: usize) -> Self {
+        Self {
+            inner: Mutex::new(CacheInner { capacity, entries: AHashMap::new(), clock: 0 }),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    fn key(text: &str) -> u64 {
+        let mut hasher = XxHash64::with_seed(0);
+        text.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    fn get(&self, key: u64) -> Option<Vec<f32>> {
+        let mut inner = self.inner.lock().unwrap();
+        inner.clock += 1;
+        let clock = inner.clock;
+        match inner.entries.get_mut(&key) {
+            Some((vec, last_used)) => {
+                *last_used = clock;
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                Some(vec.clone())
+            }
+            None => {
+                self.misses.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+        }
+    }
+
+    fn insert(&self, key: u64, vec: Vec<f32>) {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.capacity == 0 { return; }
+        inner.clock += 1;
+        let clock = inner.clock;
+        if !inner.entries.contains_key(&key) && inner.entries.len() >= inner.capacity
+            && let Some(oldest) = inner.entries.iter().min_by_key(|(_, (_, used))| *used).map(|(&k, _)| k) {
+            inner.entries.remove(&oldest);
+        }
+        inner.entries.insert(key, (vec, clock));
+    }
+
+    fn clear(&self) {
+        self.inner.lock().unwrap().entries.clear();
+    }
+
+    fn set_capacity(&self, capacity: usize) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.capacity = capacity;
+        while inner.entries.len() > capacity {
+            let Some(oldest) = inner.entries.iter().min_by_key(|(_, (_, used))| *used).map(|(&k, _)| k) else { break; };
+            inner.entries.remove(&oldest);
+        }
+    }
+}
+
+/// 句向量的池化方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PoolingStrategy {
+    /// 取 [CLS] token 的隐状态 (BGE 的训练方式，默认)
+    #[default]
+    Cls,
+    /// 非填充 token 隐状态的均值
+    Mean,
+    /// 非填充 token 隐状态逐维取最大值
+    MaxToken,
+}
 
 /// 基于 Candle 的嵌入模型 (支持 BGE-Small-ZH GGUF)
 /// 
@@ -11,36 +143,68 @@ use std::path::PathBuf;
 pub struct CandleModel {
     model: Arc<Mutex<QBertModel>>,
     tokenizer: Tokenizer,
+    device: Device,
     pub dimension: usize,
+    cache: EmbeddingCache,
+    pooling: PoolingStrategy,
+    normalize: bool,
+    window_tokens: usize,
+    window_overlap: usize,
+    /// 单次输入的最大 token 数 (含特殊 token)，超出截断
+    max_length: usize,
 }
 
 impl CandleModel {
     /// 初始化模型 (仅支持 BGE-Small-ZH GGUF)
     pub fn new() -> Result<Self, Box<dyn std::error::Error>> {
+        Self::new_on_device(Device::Cpu)
+    }
+
+    /// 在指定设备上初始化模型 (CPU/CUDA/Metal)
+    /// 
+    /// GPU 设备需要以 `cuda` / `metal` feature 编译，例如 `Device::new_cuda(0)?`。
+    pub fn new_on_device(device: Device) -> Result<Self, Box<dyn std::error::Error>> {
         // 优先级 1: BGE-Small-ZH GGUF F16 (最佳性能, ~0.2s 延迟)
         // 注意: 对于小模型，F16 比 Q8_0 更快，因为反量化开销更小
         let bge_small_f16 = "models/bge-small-zh-v1.5-gguf/bge-small-zh-v1.5-f16.gguf";
         if PathBuf::from(bge_small_f16).exists() {
              println!("🔍 Found local model: {}", bge_small_f16);
-             return Self::load_quantized_gguf(bge_small_f16);
+             return Self::load_quantized_gguf_on(bge_small_f16, device);
         }
 
         // 优先级 2: BGE-Small-ZH GGUF Q8_0 (体积更小, 推理较慢 ~1.8s)
         let bge_small_q8 = "models/bge-small-zh-v1.5-gguf/bge-small-zh-v1.5-q8_0.gguf";
         if PathBuf::from(bge_small_q8).exists() {
              println!("🔍 Found local model: {}", bge_small_q8);
-             return Self::load_quantized_gguf(bge_small_q8);
+             return Self::load_quantized_gguf_on(bge_small_q8, device);
         }
 
         Err("❌ No supported model found. Please download BGE-Small GGUF.".into())
     }
 
+    /// 首个可用的 GPU 设备 (CUDA 优先，其次 Metal)。都不可用或未以 `cuda` / `metal` feature 编译时
+    /// 打印警告并回退到 CPU，可直接传给 new_on_device。
+    pub fn gpu_or_cpu(ordinal: usize) -> Device {
+        match Device::new_cuda(ordinal).or_else(|_| Device::new_metal(ordinal)) {
+            Ok(device) => device,
+            Err(e) => {
+                println!("⚠️ [Embedding] GPU 设备 {} 初始化失败，回退到 CPU: {}", ordinal, e);
+                Device::Cpu
+            }
+        }
+    }
+
     /// 加载量化 GGUF 模型
     pub fn load_quantized_gguf(model_dir: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::load_quantized_gguf_on(model_dir, Device::Cpu)
+    }
+
+    /// 加载量化 GGUF 模型到指定设备，权重无法放到 GPU 上时打印警告并改用 CPU
+    pub fn load_quantized_gguf_on(model_dir: &str, mut device: Device) -> Result<Self, Box<dyn std::error::Error>> {
         let model_path = PathBuf::from(model_dir);
 
         // 检查 model_dir 是文件还是目录
-        let is_file = model_path.extension().map_or(false, |ext| ext == "gguf");
+        let is_file = model_path.extension().is_some_and(|ext| ext == "gguf");
 
         // GGUF 模型文件
         let weights_filename = if is_file {
@@ -77,7 +241,16 @@ impl CandleModel {
             return Err(format!("❌ Weights file not found: {:?}", weights_filename).into());
         }
 
-        let model = QBertModel::new(weights_filename.to_str().unwrap())?;
+        let weights = weights_filename.to_str().unwrap();
+        let model = match QBertModel::new_on(weights, device.clone()) {
+            Ok(model) => model,
+            Err(e) if !device.is_cpu() => {
+                println!("⚠️ [Embedding] 模型无法加载到 {:?}，回退到 CPU: {}", device, e);
+                device = Device::Cpu;
+                QBertModel::new_on(weights, Device::Cpu)?
+            }
+            Err(e) => return Err(e.into()),
+        };
         let tokenizer = Tokenizer::from_file(tokenizer_filename).map_err(|e| e.to_string())?;
 
         // 从模型获取维度
@@ -85,140 +258,251 @@ impl CandleModel {
         
         println!("✅ Model loaded successfully. Hidden size: {}", hidden_size);
 
-        Ok(Self {
+        // tokenizer.json 自带的填充参数保留，只把策略改为按批内最长序列填充
+        let padding = PaddingParams {
+            strategy: tokenizers::PaddingStrategy::BatchLongest,
+            ..tokenizer.get_padding().cloned().unwrap_or_default()
+        };
+        let mut model = Self {
             model: Arc::new(Mutex::new(model)),
             tokenizer,
+            device,
             dimension: hidden_size,
-        })
+            cache: EmbeddingCache::new(DEFAULT_EMBEDDING_CACHE_CAPACITY),
+            pooling: PoolingStrategy::default(),
+            normalize: true,
+            window_tokens: DEFAULT_WINDOW_TOKENS,
+            window_overlap: DEFAULT_WINDOW_OVERLAP,
+            max_length: MAX_SEQUENCE_TOKENS,
+        };
+        model.set_padding(padding);
+        model.apply_truncation()?;
+        Ok(model)
     }
 
-    /// 执行向量化 (推理)
-    pub fn vectorize_weighted(&self, text: &str, _weighted_ranges: &[(usize, usize, f32)]) -> Option<Vec<f32>> {
-        let device = Device::Cpu;
-        let mut tokenizer = self.tokenizer.clone();
-        
-        // 配置填充
-        if let Some(pp) = tokenizer.get_padding_mut() {
-            pp.strategy = tokenizers::PaddingStrategy::BatchLongest;
-        } else {
-            let pp = PaddingParams {
-                strategy: tokenizers::PaddingStrategy::BatchLongest,
-                ..Default::default()
-            };
-            tokenizer.with_padding(Some(pp));
-        }
+    /// 模型所在的推理设备
+    pub fn device(&self) -> &Device {
+        &self.device
+    }
 
-        // 配置截断
-        if let Some(tp) = tokenizer.get_truncation_mut() {
-            tp.max_length = 512;
-        } else {
-            let tp = TruncationParams {
-                max_length: 512,
-                ..Default::default()
-            };
-            let _ = tokenizer.with_truncation(Some(tp));
-        }
+    /// 设置嵌入缓存容量 (条)，0 表示关闭缓存。缩小容量时按 LRU 淘汰多余条目。
+    pub fn set_cache_capacity(&self, capacity: usize) {
+        self.cache.set_capacity(capacity);
+    }
 
-        // 分词
-        let tokens = match tokenizer.encode(text, true) {
-            Ok(t) => t,
-            Err(e) => {
-                eprintln!("❌ Tokenizer error: {}", e);
-                return None;
-            }
-        };
-        let token_ids = match Tensor::new(tokens.get_ids(), &device) {
-            Ok(t) => match t.unsqueeze(0) {
-                Ok(t) => t,
-                Err(e) => {
-                    eprintln!("❌ Tensor unsqueeze error: {}", e);
-                    return None;
-                }
-            },
-            Err(e) => {
-                eprintln!("❌ Tensor creation error: {}", e);
-                return None;
-            }
-        };
-        let token_type_ids = match token_ids.zeros_like() {
-            Ok(t) => t,
-            Err(e) => {
-                eprintln!("❌ Tensor zeros_like error: {}", e);
-                return None;
-            }
-        };
+    pub fn pooling(&self) -> PoolingStrategy {
+        self.pooling
+    }
 
-        // 前向传播
-        let embeddings = {
-            let model = self.model.lock().unwrap();
-            match model.forward(&token_ids, Some(&token_type_ids)) {
-                Ok(e) => e,
-                Err(e) => {
-                    eprintln!("❌ Quantized Model forward error: {}", e);
-                    return None;
-                }
-            }
-        };
+    /// 切换池化方式。已缓存的向量按旧方式计算，一并清空
+    /// 已入库的向量不会自动更新，需要时用 AdvancedEngine::rebuild_chaos_store 重建。
+    pub fn set_pooling(&mut self, pooling: PoolingStrategy) {
+        if pooling == self.pooling { return; }
+        self.pooling = pooling;
+        self.cache.clear();
+    }
 
-        // 池化策略: CLS Token (索引 0)
-        // embeddings 形状: [1, seq_len, hidden_size]
-        let cls_embedding = match embeddings.get(0) {
-            Ok(e) => match e.get(0) {
-                Ok(c) => c,
-                Err(e) => {
-                    eprintln!("❌ Embedding get(0) error: {}", e);
-                    return None;
-                }
-            },
-            Err(e) => {
-                eprintln!("❌ Embeddings get(0) error: {}", e);
-                return None;
-            }
+    pub fn normalize(&self) -> bool {
+        self.normalize
+    }
+
+    /// 输出向量是否 L2 归一化 (默认开启，入库向量为单位长度，余弦相似度即点积)
+    /// 关闭后输出池化后的原始隐状态，便于需要向量模长的下游任务；切换时清空嵌入缓存。
+    pub fn set_normalize(&mut self, normalize: bool) {
+        if normalize == self.normalize { return; }
+        self.normalize = normalize;
+        self.cache.clear();
+    }
+
+    /// vectorize_long 的 (窗口 token 数, 重叠 token 数)
+    pub fn long_text_window(&self) -> (usize, usize) {
+        (self.window_tokens, self.window_overlap)
+    }
+
+    /// 设置 vectorize_long 的滑窗: 窗口钳制到 [1, max_length - 2]，重叠钳制到小于窗口
+    pub fn set_long_text_window(&mut self, window: usize, overlap: usize) {
+        self.window_tokens = window.clamp(1, self.max_length - 2);
+        self.window_overlap = overlap.min(self.window_tokens - 1);
+    }
+
+    pub fn max_length(&self) -> usize {
+        self.max_length
+    }
+
+    /// 设置单次输入的最大 token 数 (含 [CLS] / [SEP])，钳制到 [3, MAX_SEQUENCE_TOKENS]，超出部分截断。
+    /// 调小可以降低长文本的推理延迟；vectorize_long 的滑窗随之收窄，长文本仍会分窗完整编码。
+    /// 截断长度影响向量结果，设置时清空嵌入缓存。
+    pub fn set_max_length(&mut self, max_length: usize) -> Result<(), Box<dyn std::error::Error>> {
+        let max_length = max_length.clamp(3, MAX_SEQUENCE_TOKENS);
+        if max_length == self.max_length { return Ok(()); }
+        self.max_length = max_length;
+        self.apply_truncation()?;
+        self.set_long_text_window(self.window_tokens, self.window_overlap);
+        self.cache.clear();
+        Ok(())
+    }
+
+    /// 当前的填充参数
+    pub fn padding(&self) -> Option<&PaddingParams> {
+        self.tokenizer.get_padding()
+    }
+
+    /// 设置填充参数，影响 vectorize_batch 的整批填充方式: 默认 BatchLongest 只填充到批内最长序列，
+    /// Fixed(n) 则每批都填充到 n (输入不足 n 时浪费算力)。填充位不参与池化，不改变向量结果。
+    /// 为与池化掩码及 [CLS] 位置一致，pad_id 固定为 0、方向固定为右侧填充。
+    pub fn set_padding(&mut self, padding: PaddingParams) {
+        let padding = PaddingParams { pad_id: 0, direction: tokenizers::PaddingDirection::Right, ..padding };
+        self.tokenizer.with_padding(Some(padding));
+    }
+
+    /// 按 max_length 重新配置分词器截断 (保留 tokenizer.json 中的其余截断参数)
+    fn apply_truncation(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let truncation = TruncationParams {
+            max_length: self.max_length,
+            ..self.tokenizer.get_truncation().cloned().unwrap_or_default()
         };
-        
-        // 归一化 (L2)
-        let l2_norm = match cls_embedding.sqr() {
-            Ok(s) => match s.sum_all() {
-                Ok(sum) => match sum.sqrt() {
-                    Ok(sqrt) => sqrt,
-                    Err(e) => {
-                        eprintln!("❌ Sqrt error: {}", e);
-                        return None;
-                    }
-                },
-                Err(e) => {
-                    eprintln!("❌ Sum all error: {}", e);
-                    return None;
-                }
-            },
+        self.tokenizer.with_truncation(Some(truncation)).map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    /// 嵌入缓存的 (命中次数, 未命中次数)
+    pub fn cache_stats(&self) -> (u64, u64) {
+        (self.cache.hits.load(Ordering::Relaxed), self.cache.misses.load(Ordering::Relaxed))
+    }
+
+    /// 执行向量化 (推理)，相同文本直接返回缓存的向量
+    pub fn vectorize_weighted(&self, text: &str, _weighted_ranges: &[(usize, usize, f32)]) -> Option<Vec<f32>> {
+        let key = EmbeddingCache::key(text);
+        if let Some(vec) = self.cache.get(key) {
+            return Some(vec);
+        }
+        let vec = match self.embed_batch(&[text]) {
+            Ok(mut v) => v.pop()?,
             Err(e) => {
-                eprintln!("❌ Sqr error: {}", e);
+                eprintln!("❌ Embedding error: {}", e);
                 return None;
             }
         };
-        let normalized = match cls_embedding.broadcast_div(&l2_norm) {
-            Ok(n) => n,
+        self.cache.insert(key, vec.clone());
+        Some(vec)
+    }
+
+    /// 预热: 用一句占位文本跑一次前向传播 (不经过嵌入缓存)，让首个真实查询不必承担初始化开销。
+    /// 返回这次前向传播的耗时，推理失败时返回 None。
+    pub fn warmup(&self) -> Option<Duration> {
+        let (vec, elapsed) = self.vectorize_timed("warmup 预热");
+        vec.map(|_| elapsed)
+    }
+
+    /// 向量化并计时: 绕过嵌入缓存 (既不读也不写) 直接推理，返回向量与本次推理耗时，
+    /// 可用于比较 F16 / Q8_0 等模型文件的单次延迟。
+    pub fn vectorize_timed(&self, text: &str) -> (Option<Vec<f32>>, Duration) {
+        let start = Instant::now();
+        let vec = match self.embed_batch(&[text]) {
+            Ok(mut v) => v.pop(),
             Err(e) => {
-                eprintln!("❌ Normalization error: {}", e);
-                return None;
+                eprintln!("❌ Embedding error: {}", e);
+                None
             }
         };
+        (vec, start.elapsed())
+    }
+
+    /// 长文本向量化: 正文超过滑窗大小时切成相互重叠的窗口分别编码，再按各窗口 token 数加权平均，
+    /// 避免一次截断丢掉长事件的后半段。不超过窗口的文本与 vectorize_weighted 结果相同。
+    pub fn vectorize_long(&self, text: &str) -> Option<Vec<f32>> {
+        let Some(offsets) = self.content_offsets(text) else { return self.vectorize_weighted(text, &[]); };
+        let windows = token_windows(offsets.len(), self.window_tokens, self.window_overlap);
+        if windows.len() <= 1 { return self.vectorize_weighted(text, &[]); }
+        let chunks: Option<Vec<&str>> = windows.iter().map(|&(start, end)| text.get(offsets[start].0..offsets[end - 1].1)).collect();
+        let Some(chunks) = chunks else { return self.vectorize_weighted(text, &[]); };
+        let vectors = self.vectorize_batch(&chunks)?;
+        let weights: Vec<f32> = windows.iter().map(|&(start, end)| (end - start) as f32).collect();
+        Some(weighted_mean(&vectors, &weights, self.normalize))
+    }
+
+    /// 同 vectorize_batch，超过滑窗的长文本改走 vectorize_long，其余文本仍整批推理
+    pub fn vectorize_batch_long(&self, texts: &[&str]) -> Option<Vec<Vec<f32>>> {
+        let long: Vec<bool> = texts.iter()
+            .map(|t| self.content_offsets(t).is_some_and(|o| o.len() > self.window_tokens))
+            .collect();
+        let short: Vec<&str> = texts.iter().zip(&long).filter(|&(_, &l)| !l).map(|(&t, _)| t).collect();
+        let mut short_vectors = self.vectorize_batch(&short)?.into_iter();
+        texts.iter().zip(&long)
+            .map(|(&t, &l)| if l { self.vectorize_long(t) } else { short_vectors.next() })
+            .collect()
+    }
 
-        let vec: Vec<f32> = match normalized.flatten_all() {
-            Ok(f) => match f.to_vec1() {
+    /// 正文 token (不含特殊 token、不截断) 在原文中的字节偏移
+    fn content_offsets(&self, text: &str) -> Option<Vec<(usize, usize)>> {
+        let mut tokenizer = self.tokenizer.clone();
+        tokenizer.with_padding(None);
+        tokenizer.with_truncation(None).ok()?;
+        let encoding = tokenizer.encode(text, false).ok()?;
+        Some(encoding.get_offsets().to_vec())
+    }
+
+    /// 批量向量化: 整批按最长序列填充后一次前向传播，缓存命中的文本不参与推理
+    /// 返回的向量与输入一一对应；空批次返回空 Vec，推理失败时返回 None。
+    pub fn vectorize_batch(&self, texts: &[&str]) -> Option<Vec<Vec<f32>>> {
+        let keys: Vec<u64> = texts.iter().map(|t| EmbeddingCache::key(t)).collect();
+        let mut vectors: Vec<Option<Vec<f32>>> = keys.iter().map(|&k| self.cache.get(k)).collect();
+        let missing: Vec<usize> = (0..texts.len()).filter(|&i| vectors[i].is_none()).collect();
+        if !missing.is_empty() {
+            let batch: Vec<&str> = missing.iter().map(|&i| texts[i]).collect();
+            let embedded = match self.embed_batch(&batch) {
                 Ok(v) => v,
                 Err(e) => {
-                    eprintln!("❌ To vec1 error: {}", e);
+                    eprintln!("❌ Batch embedding error: {}", e);
                     return None;
                 }
-            },
-            Err(e) => {
-                eprintln!("❌ Flatten all error: {}", e);
-                return None;
+            };
+            for (&i, vec) in missing.iter().zip(embedded) {
+                self.cache.insert(keys[i], vec.clone());
+                vectors[i] = Some(vec);
             }
+        }
+        vectors.into_iter().collect()
+    }
+
+    fn embed_batch(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>, Box<dyn std::error::Error>> {
+        let encodings = self.tokenizer.encode_batch(texts.to_vec(), true).map_err(|e| e.to_string())?;
+        let seq_len = encodings.first().map_or(0, |e| e.get_ids().len());
+        let ids: Vec<u32> = encodings.iter().flat_map(|e| e.get_ids().iter().copied()).collect();
+        let token_ids = Tensor::from_vec(ids, (texts.len(), seq_len), &self.device)?;
+        let token_type_ids = token_ids.zeros_like()?;
+
+        let embeddings = {
+            let model = self.model.lock().unwrap();
+            model.forward(&token_ids, Some(&token_type_ids))?
         };
-        
-        Some(vec)
+        // 池化 + 逐行 L2 归一化: [batch, seq_len, hidden] -> [batch, hidden]
+        let pooled = self.pool(&embeddings, &token_ids)?;
+        if !self.normalize {
+            return Ok(pooled.to_vec2()?);
+        }
+        let norms = pooled.sqr()?.sum_keepdim(1)?.sqrt()?;
+        Ok(pooled.broadcast_div(&norms)?.to_vec2()?)
+    }
+
+    /// 按 pooling 策略把 token 隐状态汇聚为句向量，填充 token (id 为 0) 不参与 Mean / MaxToken
+    fn pool(&self, embeddings: &Tensor, token_ids: &Tensor) -> candle_core::Result<Tensor> {
+        if self.pooling == PoolingStrategy::Cls {
+            return embeddings.narrow(1, 0, 1)?.squeeze(1);
+        }
+        // mask: [batch, seq_len, 1]，有效 token 为 1
+        let mask = token_ids.ne(0u32)?.to_dtype(embeddings.dtype())?.unsqueeze(2)?;
+        match self.pooling {
+            PoolingStrategy::Mean => {
+                let summed = embeddings.broadcast_mul(&mask)?.sum(1)?;
+                let counts = mask.sum(1)?.clamp(1.0, f64::MAX)?;
+                summed.broadcast_div(&counts)
+            }
+            _ => {
+                let penalty = ((mask - 1.0)? * 1e9)?;
+                embeddings.broadcast_add(&penalty)?.max(1)
+            }
+        }
     }
 }
 
@@ -243,4 +527,222 @@ mod tests {
             eprintln!("Model failed to load. Ensure model files are in 'models/bge-m3-gguf/Embedding-GGUF/bge-m3-Q4_K_M-GGUF'");
         }
     }
+
+    #[test]
+    fn test_token_windows_and_weighted_mean() {
+        assert_eq!(token_windows(5, 10, 2), vec![(0, 5)]);
+        assert_eq!(token_windows(10, 4, 1), vec![(0, 4), (3, 7), (6, 10)]);
+        assert_eq!(token_windows(0, 4, 1), vec![(0, 0)]);
+        // 重叠不小于窗口时每次至少前进 1
+        assert_eq!(token_windows(3, 2, 5), vec![(0, 2), (1, 3)]);
+
+        let mean = weighted_mean(&[vec![1.0, 0.0], vec![0.0, 1.0]], &[3.0, 1.0], false);
+        assert_eq!(mean, vec![0.75, 0.25]);
+        let unit = weighted_mean(&[vec![1.0, 0.0], vec![0.0, 1.0]], &[1.0, 1.0], true);
+        assert!((unit[0] - std::f32::consts::FRAC_1_SQRT_2).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_vectorize_long_covers_tail() {
+        let Ok(mut m) = CandleModel::new() else {
+            eprintln!("Model failed to load, skipping long text test");
+            return;
+        };
+        let short = "今天去海边散步";
+        assert_eq!(m.vectorize_long(short), m.vectorize_weighted(short, &[]));
+
+        m.set_long_text_window(16, 4);
+        assert_eq!(m.long_text_window(), (16, 4));
+        let head = "上午在公司写了一整天的检索内核代码，调试内存泄漏。";
+        let tail = "晚上和朋友去海边看日落，吃了烧烤。";
+        let text = format!("{}{}", head.repeat(3), tail);
+        let long = m.vectorize_long(&text).unwrap();
+        assert_eq!(long.len(), m.dimension);
+        assert!((long.iter().map(|x| x * x).sum::<f32>() - 1.0).abs() < 1e-4);
+        // 结尾的内容也进入了向量: 比只看开头更接近结尾那句
+        let dot = |a: &[f32], b: &[f32]| a.iter().zip(b).map(|(x, y)| x * y).sum::<f32>();
+        let tail_vec = m.vectorize_weighted(tail, &[]).unwrap();
+        let head_vec = m.vectorize_weighted(&head.repeat(3), &[]).unwrap();
+        assert!(dot(&long, &tail_vec) > dot(&head_vec, &tail_vec));
+        assert_eq!(m.vectorize_batch_long(&[short, &text]).unwrap(), vec![m.vectorize_weighted(short, &[]).unwrap(), long]);
+
+        m.set_long_text_window(10_000, 20_000);
+        assert_eq!(m.long_text_window(), (DEFAULT_WINDOW_TOKENS, DEFAULT_WINDOW_TOKENS - 1));
+    }
+
+    #[test]
+    fn test_tokenizer_length_and_padding() {
+        let Ok(mut m) = CandleModel::new() else {
+            eprintln!("Model failed to load, skipping tokenizer config test");
+            return;
+        };
+        let texts = ["海边", "周末和朋友去海边散步，看了日落，吃了烧烤"];
+        let batch = m.vectorize_batch(&texts).unwrap();
+        assert_eq!(m.max_length(), MAX_SEQUENCE_TOKENS);
+        assert!(matches!(m.padding().unwrap().strategy, tokenizers::PaddingStrategy::BatchLongest));
+
+        // 固定填充只多算填充位，不改变向量
+        m.set_cache_capacity(0);
+        m.set_padding(PaddingParams { strategy: tokenizers::PaddingStrategy::Fixed(64), pad_id: 7, ..Default::default() });
+        assert_eq!(m.padding().unwrap().pad_id, 0);
+        let fixed = m.vectorize_batch(&texts).unwrap();
+        for (a, b) in batch.iter().zip(&fixed) {
+            let cosine: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+            assert!(cosine > 0.999, "{}", cosine);
+        }
+
+        // 截断到 4 个 token 后长句只剩开头，短句不受影响
+        m.set_max_length(4).unwrap();
+        assert_eq!(m.max_length(), 4);
+        assert_eq!(m.long_text_window().0, 2);
+        let truncated = m.vectorize_weighted(texts[1], &[]).unwrap();
+        let cosine: f32 = truncated.iter().zip(&batch[1]).map(|(x, y)| x * y).sum();
+        assert!(cosine < 0.999, "{}", cosine);
+        m.set_max_length(100_000).unwrap();
+        assert_eq!(m.max_length(), MAX_SEQUENCE_TOKENS);
+    }
+
+    #[test]
+    fn test_warmup_and_timed_vectorize() {
+        let Ok(m) = CandleModel::new() else {
+            eprintln!("Model failed to load, skipping warmup test");
+            return;
+        };
+        let warmup = m.warmup().unwrap();
+        let text = "周末去海边散步";
+        let (timed, elapsed) = m.vectorize_timed(text);
+        assert!(elapsed > Duration::ZERO, "warmup {:?}, timed {:?}", warmup, elapsed);
+        // 计时接口不经过缓存，结果与普通向量化一致
+        assert_eq!(m.cache_stats(), (0, 0));
+        let timed = timed.unwrap();
+        let cached = m.vectorize_weighted(text, &[]).unwrap();
+        let cosine: f32 = timed.iter().zip(&cached).map(|(a, b)| a * b).sum();
+        assert!(cosine > 0.9999);
+    }
+
+    #[test]
+    fn test_embedding_cache_hits_and_evicts() {
+        let cache = EmbeddingCache::new(2);
+        let (a, b, c) = (EmbeddingCache::key("a"), EmbeddingCache::key("b"), EmbeddingCache::key("c"));
+        assert_eq!(cache.get(a), None);
+        cache.insert(a, vec![1.0]);
+        cache.insert(b, vec![2.0]);
+        assert_eq!(cache.get(a), Some(vec![1.0]));
+        // b 最久未使用，被 c 挤出
+        cache.insert(c, vec![3.0]);
+        assert_eq!(cache.get(b), None);
+        assert_eq!(cache.get(c), Some(vec![3.0]));
+        assert_eq!((cache.hits.load(Ordering::Relaxed), cache.misses.load(Ordering::Relaxed)), (2, 2));
+
+        cache.set_capacity(0);
+        cache.insert(a, vec![1.0]);
+        assert_eq!(cache.get(a), None);
+    }
+
+    #[test]
+    fn test_repeated_text_served_from_cache() {
+        let Ok(m) = CandleModel::new() else {
+            eprintln!("Model failed to load, skipping embedding cache test");
+            return;
+        };
+        let first = m.vectorize_weighted("同一句话", &[]).unwrap();
+        assert_eq!(m.cache_stats(), (0, 1));
+        let second = m.vectorize_weighted("同一句话", &[]).unwrap();
+        assert_eq!(m.cache_stats(), (1, 1));
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn test_vectorize_batch_matches_single() {
+        let Ok(m) = CandleModel::new() else {
+            eprintln!("Model failed to load, skipping batch embedding test");
+            return;
+        };
+        assert_eq!(m.vectorize_batch(&[]), Some(Vec::new()));
+        let texts = ["今天去海边散步", "Rust 的所有权机制保证了内存安全", "好"];
+        let batch = m.vectorize_batch(&texts).unwrap();
+        assert_eq!(batch.len(), texts.len());
+        m.set_cache_capacity(0);
+        for (text, vec) in texts.iter().zip(&batch) {
+            let single = m.vectorize_weighted(text, &[]).unwrap();
+            let cosine: f32 = single.iter().zip(vec).map(|(a, b)| a * b).sum();
+            assert!(cosine > 0.999, "{} {}", text, cosine);
+        }
+    }
+
+    #[test]
+    fn test_pooling_strategies_ignore_padding() {
+        let Ok(mut m) = CandleModel::new() else {
+            eprintln!("Model failed to load, skipping pooling test");
+            return;
+        };
+        let short = "海边";
+        let texts = [short, "Rust 的所有权机制保证了内存安全，编译期就能发现悬垂指针"];
+        let cls = m.vectorize_weighted(short, &[]).unwrap();
+        for pooling in [PoolingStrategy::Mean, PoolingStrategy::MaxToken] {
+            m.set_pooling(pooling);
+            assert_eq!(m.pooling(), pooling);
+            let single = m.vectorize_weighted(short, &[]).unwrap();
+            assert_ne!(single, cls, "{:?}", pooling);
+            assert!((single.iter().map(|x| x * x).sum::<f32>() - 1.0).abs() < 1e-4);
+            // 与长句同批时短句被填充，填充位不应改变池化结果
+            m.set_cache_capacity(0);
+            let padded = m.vectorize_batch(&texts).unwrap();
+            let cosine: f32 = single.iter().zip(&padded[0]).map(|(a, b)| a * b).sum();
+            assert!(cosine > 0.999, "{:?} {}", pooling, cosine);
+            m.set_cache_capacity(DEFAULT_EMBEDDING_CACHE_CAPACITY);
+        }
+        m.set_pooling(PoolingStrategy::Cls);
+        let again = m.vectorize_weighted(short, &[]).unwrap();
+        let cosine: f32 = cls.iter().zip(&again).map(|(a, b)| a * b).sum();
+        assert!(cosine > 0.9999);
+    }
+
+    #[test]
+    fn test_unnormalized_output_keeps_direction() {
+        let Ok(mut m) = CandleModel::new() else {
+            eprintln!("Model failed to load, skipping normalization test");
+            return;
+        };
+        let text = "今天去海边散步";
+        let unit = m.vectorize_weighted(text, &[]).unwrap();
+        assert!(m.normalize());
+        assert!((unit.iter().map(|x| x * x).sum::<f32>() - 1.0).abs() < 1e-4);
+
+        m.set_normalize(false);
+        let raw = m.vectorize_weighted(text, &[]).unwrap();
+        let norm = raw.iter().map(|x| x * x).sum::<f32>().sqrt();
+        assert!((norm - 1.0).abs() > 1e-2);
+        // 归一化前后方向一致 (f16 权重精度内)
+        for (u, r) in unit.iter().zip(&raw) {
+            assert!((u - r / norm).abs() < 1e-3);
+        }
+    }
+
+    #[cfg(feature = "cuda")]
+    #[test]
+    fn test_model_loading_on_cuda() {
+        let device = match Device::new_cuda(0) {
+            Ok(d) => d,
+            Err(e) => {
+                eprintln!("CUDA device unavailable, skipping: {}", e);
+                return;
+            }
+        };
+        if let Ok(m) = CandleModel::new_on_device(device) {
+            assert!(m.device().is_cuda());
+            let v = m.vectorize_weighted("Hello world", &[]).unwrap();
+            assert_eq!(v.len(), m.dimension);
+            // GPU 与 CPU 的向量在浮点误差内一致
+            let cpu = CandleModel::new().unwrap().vectorize_weighted("Hello world", &[]).unwrap();
+            let cosine: f32 = v.iter().zip(&cpu).map(|(a, b)| a * b).sum();
+            assert!(cosine > 0.999, "{}", cosine);
+        }
+    }
+
+    #[cfg(not(any(feature = "cuda", feature = "metal")))]
+    #[test]
+    fn test_gpu_or_cpu_falls_back_without_gpu_support() {
+        assert!(CandleModel::gpu_or_cpu(0).is_cpu());
+    }
 }
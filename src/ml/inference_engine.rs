@@ -60,9 +60,10 @@ impl QuantizedBertLayer {
         let num_heads = self.num_heads;
         let head_dim = hidden / num_heads;
         
-        let q = q.reshape((batch, seq_len, num_heads, head_dim))?.transpose(1, 2)?; // [batch, heads, seq, head_dim]
-        let k = k.reshape((batch, seq_len, num_heads, head_dim))?.transpose(1, 2)?;
-        let v = v.reshape((batch, seq_len, num_heads, head_dim))?.transpose(1, 2)?;
+        // batch > 1 时转置后的步长无法直接做 matmul，需先拷贝为连续内存
+        let q = q.reshape((batch, seq_len, num_heads, head_dim))?.transpose(1, 2)?.contiguous()?; // [batch, heads, seq, head_dim]
+        let k = k.reshape((batch, seq_len, num_heads, head_dim))?.transpose(1, 2)?.contiguous()?;
+        let v = v.reshape((batch, seq_len, num_heads, head_dim))?.transpose(1, 2)?.contiguous()?;
 
         // 缩放点积注意力 (Scaled Dot-Product Attention)
         // score = q @ k.t() / sqrt(head_dim)
@@ -116,9 +117,13 @@ impl QuantizedBertLayer {
 
 impl QuantizedBertModel {
     pub fn new<P: AsRef<std::path::Path>>(path: P) -> Result<Self> {
+        Self::new_on(path, Device::Cpu)
+    }
+
+    /// 在指定设备 (CPU/CUDA/Metal) 上加载模型，所有权重张量直接放置到该设备
+    pub fn new_on<P: AsRef<std::path::Path>>(path: P, device: Device) -> Result<Self> {
         let mut file = std::fs::File::open(path)?;
         let content = gguf_file::Content::read(&mut file)?;
-        let device = Device::Cpu;
 
         // 加载张量 (Load tensors)
         let mut layers = Vec::new();
@@ -269,6 +274,7 @@ impl QuantizedBertModel {
         
         // 掩码计算 (Mask calculation)
         // 优化：手动构建 Mask Tensor，减少中间 Tensor 的创建
+        // 该快速路径仅在 CPU 上生效；GPU 设备走下方的张量算子回退分支，避免设备间往返拷贝。
         // 使用 0 作为 BERT 的填充 token id。
         let (batch, seq_len) = input_ids.dims2()?;
         let mask = if matches!(input_ids.device(), Device::Cpu) {
@@ -0,0 +1,22 @@
+//! 常用公开 API 的统一入口: `use pedsa_embedding::prelude::*;`
+//!
+//! 这里列出的类型构成稳定的对外接口。引擎的 `tdb` 字段是直通 TriviumDB 的逃生口，
+//! 其余内部索引 (关键词表、标签索引) 不对外暴露，通过 `feature_id` 等方法访问。
+
+pub use crate::core::config::{
+    BatchParallelism, ConfigError, DuplicatePolicy, EdgeTypeGain, EngineConfig, HybridScanConfig, MultiVectorPooling, ResonanceConfig, ScoreNormalization,
+    SerendipityConfig,
+};
+pub use crate::core::dates::parse_timestamps;
+pub use crate::core::engine::{clamp_strength, AdvancedEngine, AdvancedEngineBuilder, InsertError, MemoryEdgeType, StorageError, DEFAULT_EVENT_TIMESTAMP, DEFAULT_VECTOR_DIM};
+pub use crate::core::integrity::IntegrityError;
+pub use crate::core::merge::{MergePolicy, MergeStats};
+pub use crate::core::ontology::{ontology_edge_code, OntologyChange, ONTOLOGY_EQUALITY, ONTOLOGY_INHIBITION, ONTOLOGY_REPRESENTATION};
+pub use crate::core::path::{GraphLayer, PathHop};
+pub use crate::core::query::{parse_query, ParsedQuery};
+pub use crate::core::retrieval::{FeatureActivations, ResultFilter, RetrievalHit, RetrieveOutcome, ScoreBreakdown, TagMatch, VectorScanIter};
+pub use crate::core::simhash::{EmotionKeywords, FingerprintRegions, QueryAnalysis, RegionSimilarities, SimHash, SimHashLayout, SimHashRegion};
+pub use crate::core::shared::SharedEngine;
+pub use crate::core::stopwords::StopwordFilter;
+pub use crate::core::types::{AsyncTaskInterface, MaintenanceRequest, ThreadedAsyncTask};
+pub use crate::ml::embedding::{CandleModel, PoolingStrategy};
@@ -1,5 +1,5 @@
 use pyo3::prelude::*;
-use crate::core::engine::AdvancedEngine;
+use crate::core::engine::{AdvancedEngine, MemoryEdgeType};
 
 #[pyclass(name = "Engine")]
 pub struct PedsaEngine { inner: AdvancedEngine }
@@ -11,7 +11,7 @@ impl PedsaEngine {
     
     fn load_embedding_model(&mut self) -> PyResult<bool> {
         match crate::ml::embedding::CandleModel::new() {
-            Ok(model) => { self.inner.embedding_model = Some(model); Ok(true) }
+            Ok(model) => Ok(self.inner.set_embedding_model(model).is_ok()),
             Err(_) => Ok(false)
         }
     }
@@ -31,22 +31,44 @@ impl PedsaEngine {
     #[pyo3(signature = (id, summary, timestamp=0, emotion=0, event_type=0))]
     fn add_event(&mut self, id: i64, summary: &str, timestamp: u64, emotion: u8, event_type: u8) { self.inner.add_event(id, summary, timestamp, emotion, event_type); }
     fn add_edge(&mut self, src: i64, tgt: i64, weight: f32) { self.inner.add_edge(src, tgt, weight); }
+    /// edge_type: 0=关联, 1=因果, 2=顺序, 3=对比
+    fn add_edge_typed(&mut self, src: i64, tgt: i64, weight: f32, edge_type: u8) -> PyResult<()> {
+        let edge_type = MemoryEdgeType::from_code(edge_type)
+            .ok_or_else(|| pyo3::exceptions::PyValueError::new_err(format!("未知的边类型 {}", edge_type)))?;
+        self.inner.add_edge_typed(src, tgt, weight, edge_type);
+        Ok(())
+    }
     fn maintain_ontology(&mut self, src: &str, tgt: &str, rel: &str, s: f32) { self.inner.maintain_ontology(src, tgt, rel, s); }
     fn compile(&mut self) { self.inner.compile(); self.inner.build_temporal_backbone(); }
+    fn compile_if_dirty(&mut self) -> bool {
+        if !self.inner.is_dirty() { return false; }
+        self.inner.build_temporal_backbone();
+        self.inner.compile_if_dirty()
+    }
     #[pyo3(signature = (query, ref_time=0, chaos_level=0.0))]
     fn retrieve(&self, query: &str, ref_time: u64, chaos_level: f32) -> Vec<(i64, f32)> { self.inner.retrieve(query, ref_time, chaos_level) }
+    #[pyo3(signature = (query_fp, query, ref_time=0, chaos_level=0.0))]
+    fn retrieve_by_fingerprint(&self, query_fp: u64, query: &str, ref_time: u64, chaos_level: f32) -> Vec<(i64, f32)> { self.inner.retrieve_by_fingerprint(query_fp, query, ref_time, chaos_level) }
+    #[pyo3(signature = (query, ref_time=0, k=10))]
+    fn retrieve_fingerprint_only(&self, query: &str, ref_time: u64, k: usize) -> Vec<(i64, f32)> { self.inner.retrieve_fingerprint_only(query, ref_time, k) }
+    #[pyo3(signature = (id, k=10))]
+    fn similar_nodes(&self, id: i64, k: usize) -> Vec<(i64, f32)> { self.inner.similar_nodes(id, k) }
+    fn events_in_range(&self, start_ts: u64, end_ts: u64) -> Vec<i64> { self.inner.events_in_range(start_ts, end_ts) }
+    fn events_after(&self, id: i64, n: usize) -> Vec<i64> { self.inner.events_after(id, n) }
+    fn events_before(&self, id: i64, n: usize) -> Vec<i64> { self.inner.events_before(id, n) }
     
     fn node_count(&self) -> usize { self.inner.tdb.node_count() }
-    fn feature_count(&self) -> usize { self.inner.keyword_to_node.len() }
+    fn feature_count(&self) -> usize { self.inner.feature_count() }
     fn get_or_create_feature(&mut self, word: &str) -> i64 { self.inner.get_or_create_feature(word) }
     
     #[pyo3(signature = (action, source, target, relation_type, strength, reason=""))]
     fn execute_maintenance(&mut self, action: &str, source: &str, target: &str, relation_type: &str, strength: f32, reason: &str) -> Option<String> {
-        self.inner.execute_maintenance(action, source, target, relation_type, strength, reason)
+        self.inner.execute_maintenance(action, source, target, relation_type, strength, reason).1
     }
 
     fn apply_arbitration(&mut self, source: &str, delete_targets: Vec<String>) { self.inner.apply_arbitration(source, delete_targets); }
     fn trigger_arbitration(&self, source: &str) -> Option<String> { self.inner.trigger_arbitration(source) }
+    fn ontology_neighbors(&self, keyword: &str) -> Vec<(String, f32, u8)> { self.inner.ontology_neighbors(keyword) }
 
     fn get_node(&self, id: i64) -> Option<PyObject> {
         Python::with_gil(|py| {
@@ -70,8 +92,8 @@ impl PedsaEngine {
     }
     fn get_ontology_edges(&self, node_id: i64) -> Vec<(i64, f32, String)> { self.get_edges(node_id) }
     fn all_node_ids(&self) -> Vec<i64> { self.inner.tdb.all_node_ids().into_iter().map(|id| id as i64).collect() }
-    fn all_feature_keywords(&self) -> Vec<String> { self.inner.keyword_to_node.keys().cloned().collect() }
-    fn keyword_to_id(&self, keyword: &str) -> Option<i64> { self.inner.keyword_to_node.get(&keyword.to_lowercase()).copied() }
+    fn all_feature_keywords(&self) -> Vec<String> { self.inner.feature_keywords().map(str::to_string).collect() }
+    fn keyword_to_id(&self, keyword: &str) -> Option<i64> { self.inner.feature_id(keyword) }
     fn apply_decay(&mut self, decay_rate: f32, threshold: u16) -> usize { self.inner.apply_global_decay_and_pruning(decay_rate, threshold) }
 
     fn load_standard_data(&mut self) { self.inner.load_standard_data(); }